@@ -0,0 +1,152 @@
+use crate::error::Error;
+use crate::utils;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+// A bigram table as it is written in a file, each bigram with the one it is replaced by.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct BigramTableFile {
+    bigrams: HashMap<String, String>,
+}
+
+// One of the Kriegsmarine substitution tables (Doppelbuchstabentauschtafeln) used to disguise the indicator of a naval
+// message. Every pair of letters is replaced by another pair, and the tables are reciprocal so the same table is used to
+// encode and decode. A table file only needs to give each pair one way round.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BigramTable {
+    substitutions: Vec<usize>,
+}
+
+impl BigramTable {
+    pub fn new(pairs: &[(&str, &str)]) -> Result<BigramTable> {
+        let mut substitutions: Vec<Option<usize>> = vec![None; 26 * 26];
+
+        for (from, to) in pairs {
+            let a = BigramTable::get_index(from)?;
+            let b = BigramTable::get_index(to)?;
+
+            for (x, y) in [(a, b), (b, a)] {
+                match substitutions[x] {
+                    Some(existing) if existing != y => {
+                        return Err(Error::BigramError).with_context(|| { format!("Invalid bigram table. {} is given more than one substitution.", BigramTable::get_bigram(x)) });
+                    },
+                    _ => substitutions[x] = Some(y),
+                }
+            }
+        }
+
+        if let Some(missing) = substitutions.iter().position(|s| s.is_none()) {
+            return Err(Error::BigramError).with_context(|| { format!("Invalid bigram table. There is no substitution for {}.", BigramTable::get_bigram(missing)) });
+        }
+
+        Ok(BigramTable {
+            substitutions: substitutions.into_iter().flatten().collect(),
+        })
+    }
+
+    pub fn from_toml(toml: &str) -> Result<BigramTable> {
+        let file: BigramTableFile = match toml::from_str(toml) {
+            Ok(f) => f,
+            Err(e) => return Err(Error::BigramError).with_context(|| { format!("Invalid TOML bigram table. {}", e) }),
+        };
+
+        BigramTable::from_file(&file)
+    }
+
+    pub fn from_json(json: &str) -> Result<BigramTable> {
+        let file: BigramTableFile = match serde_json::from_str(json) {
+            Ok(f) => f,
+            Err(e) => return Err(Error::BigramError).with_context(|| { format!("Invalid JSON bigram table. {}", e) }),
+        };
+
+        BigramTable::from_file(&file)
+    }
+
+    // Load a bigram table from a file, picking the format from the extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<BigramTable> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(Error::from).with_context(|| { format!("Unable to read bigram table {}.", path.display()) })?;
+
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+            Some("toml") => BigramTable::from_toml(&contents),
+            Some("json") => BigramTable::from_json(&contents),
+            _ => Err(Error::BigramError).with_context(|| { format!("Invalid bigram table {}. Must be a .toml or .json file.", path.display()) }),
+        }
+    }
+
+    fn from_file(file: &BigramTableFile) -> Result<BigramTable> {
+        let pairs: Vec<(&str, &str)> = file.bigrams.iter().map(|(from, to)| (from.as_str(), to.as_str())).collect();
+
+        BigramTable::new(&pairs)
+    }
+
+    // Replace a pair of letters. As the table is reciprocal, substituting the result gives back the original pair.
+    pub fn substitute(&self, bigram: &str) -> Result<String> {
+        Ok(BigramTable::get_bigram(self.substitutions[BigramTable::get_index(bigram)?]))
+    }
+
+    fn get_index(bigram: &str) -> Result<usize> {
+        let letters: Vec<char> = bigram.chars().collect();
+
+        if letters.len() != 2 {
+            return Err(Error::BigramError).with_context(|| { format!("Invalid bigram {}. Expected 2 letters.", bigram) });
+        }
+
+        Ok(utils::get_position_from_char(letters[0])? * 26 + utils::get_position_from_char(letters[1])?)
+    }
+
+    fn get_bigram(index: usize) -> String {
+        [index / 26, index % 26].iter().map(|p| utils::get_char_from_position(*p).unwrap()).collect()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    // A table that swaps each bigram with its neighbour, AA with AB, AC with AD and so on.
+    pub(crate) fn get_table_toml() -> String {
+        let mut toml = String::from("[bigrams]\n");
+
+        for i in (0..(26 * 26)).step_by(2) {
+            toml.push_str(&format!("{} = \"{}\"\n", BigramTable::get_bigram(i), BigramTable::get_bigram(i + 1)));
+        }
+
+        toml
+    }
+
+    #[test]
+    fn test_bigram_table_from_toml() {
+        let table = BigramTable::from_toml(&get_table_toml()).unwrap();
+
+        assert_eq!("AB", table.substitute("AA").unwrap());
+        assert_eq!("AA", table.substitute("AB").unwrap());
+        assert_eq!("ZY", table.substitute("ZZ").unwrap());
+        assert_eq!("QE", table.substitute("QF").unwrap());
+    }
+
+    #[test]
+    fn test_bigram_table_from_json() {
+        let pairs: Vec<String> = (0..(26 * 26)).step_by(2).map(|i| format!("\"{}\": \"{}\"", BigramTable::get_bigram(i + 1), BigramTable::get_bigram(i))).collect();
+        let table = BigramTable::from_json(&format!("{{ \"bigrams\": {{ {} }} }}", pairs.join(", "))).unwrap();
+
+        assert_eq!(BigramTable::from_toml(&get_table_toml()).unwrap(), table);
+    }
+
+    #[test]
+    fn test_bigram_table_invalid() {
+        // Incomplete.
+        assert!(BigramTable::from_toml("[bigrams]\nAA = \"AB\"").is_err());
+
+        // AB given two different substitutions.
+        let toml = get_table_toml() + "CD = \"AB\"\n";
+        assert!(BigramTable::from_toml(&toml).is_err());
+
+        assert!(BigramTable::from_toml(&(get_table_toml() + "ABC = \"DEF\"\n")).is_err());
+        assert!(BigramTable::from_toml(&get_table_toml()).unwrap().substitute("A").is_err());
+        assert!(BigramTable::load("table.txt").is_err());
+    }
+}
@@ -1,17 +1,110 @@
 use eframe::{run_native, NativeOptions, App};
 use egui::{Response, Ui};
-use enigma::{ReflectorType, Rotor, RotorType, Plugboard, Enigma};
+use enigma::{Catalog, MachineSettings, Reflector, ReflectorType, ReflectorNotation, Rotor, RotorType, GreekWheel, GreekWheelType, EntryWheel, EntryWheelType, Plugboard, Enigma, MachineModel, SteppingMode, InputPolicy, KeySheet, DailyKey};
+
+// The built in parts offered in the comboboxes, followed by any parts from a loaded catalog.
+const MODELS: [(MachineModel, &str); 9] = [
+    (MachineModel::EnigmaI, "EnigmaI"),
+    (MachineModel::M3, "M3"),
+    (MachineModel::M4, "M4"),
+    (MachineModel::EnigmaD, "EnigmaD"),
+    (MachineModel::EnigmaK, "EnigmaK"),
+    (MachineModel::SwissK, "SwissK"),
+    (MachineModel::EnigmaG, "EnigmaG"),
+    (MachineModel::Railway, "Railway"),
+    (MachineModel::EnigmaT, "EnigmaT"),
+];
+
+const REFLECTORS: [(ReflectorType, &str); 10] = [
+    (ReflectorType::A, "A"),
+    (ReflectorType::B, "B"),
+    (ReflectorType::C, "C"),
+    (ReflectorType::ThinB, "ThinB"),
+    (ReflectorType::ThinC, "ThinC"),
+    (ReflectorType::Commercial, "Commercial"),
+    (ReflectorType::G, "G"),
+    (ReflectorType::Railway, "Railway"),
+    (ReflectorType::T, "T"),
+    (ReflectorType::D, "D"),
+];
+
+const ENTRY_WHEELS: [(EntryWheelType, &str); 3] = [
+    (EntryWheelType::Military, "Military"),
+    (EntryWheelType::Commercial, "Commercial"),
+    (EntryWheelType::Tirpitz, "Tirpitz"),
+];
+
+const ROTORS: [(RotorType, &str); 28] = [
+    (RotorType::I, "I"),
+    (RotorType::II, "II"),
+    (RotorType::III, "III"),
+    (RotorType::IV, "IV"),
+    (RotorType::V, "V"),
+    (RotorType::VI, "VI"),
+    (RotorType::VII, "VII"),
+    (RotorType::VIII, "VIII"),
+    (RotorType::ID, "ID"),
+    (RotorType::IID, "IID"),
+    (RotorType::IIID, "IIID"),
+    (RotorType::IK, "IK"),
+    (RotorType::IIK, "IIK"),
+    (RotorType::IIIK, "IIIK"),
+    (RotorType::IG, "IG"),
+    (RotorType::IIG, "IIG"),
+    (RotorType::IIIG, "IIIG"),
+    (RotorType::IR, "IR"),
+    (RotorType::IIR, "IIR"),
+    (RotorType::IIIR, "IIIR"),
+    (RotorType::IT, "IT"),
+    (RotorType::IIT, "IIT"),
+    (RotorType::IIIT, "IIIT"),
+    (RotorType::IVT, "IVT"),
+    (RotorType::VT, "VT"),
+    (RotorType::VIT, "VIT"),
+    (RotorType::VIIT, "VIIT"),
+    (RotorType::VIIIT, "VIIIT"),
+];
 
 struct EnigmaGui {
     input: String,
     previous_input: String,
+    // Each message encrypted on the current machine, most recent last, so they can be undone.
+    history: Vec<String>,
+    input_policy: InputPolicy,
+    preserve_case: bool,
     output: String,
+    model: Option<MachineModel>,
     reflector_type: ReflectorType,
+    reflector_ring: usize,
+    reflector_key: char,
+    reflector_notation: ReflectorNotation,
+    reflector_plugs: String,
+    stepping_mode: SteppingMode,
+    entry_wheel_type: EntryWheelType,
+    greek_wheel_type: Option<GreekWheelType>,
+    greek_wheel_ring: usize,
+    greek_wheel_key: char,
     rotor_type: [RotorType; 3],
     rotor_ring: [usize; 3],
     rotor_key: [char; 3],
     current_plug_pair: [char; 2],
     plugs: Vec<[char; 2]>,
+    uhr_setting: Option<usize>,
+    settings: String,
+    catalog_path: String,
+    catalog: Catalog,
+    key_sheet_path: String,
+    // The key sheets loaded so far, one per network, and the one picked for looking up days.
+    key_sheets: Vec<KeySheet>,
+    key_sheet_index: usize,
+    // The day of the month or date to take from the key sheet.
+    key_sheet_day: String,
+    kenngruppe: String,
+    // Parts chosen from the catalog, which take the place of the built in selection.
+    catalog_model: Option<String>,
+    catalog_reflector: Option<String>,
+    catalog_entry_wheel: Option<String>,
+    catalog_rotor: [Option<String>; 3],
     enigma: Enigma,
 }
 
@@ -22,10 +115,11 @@ impl EnigmaGui {
         // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
         // for e.g. egui::PaintCallback.
 
-        let mut rotors: Vec<Rotor> = Vec::with_capacity(3);
-        rotors.push(Rotor::new(RotorType::I, 'A', 1).unwrap());
-        rotors.push(Rotor::new(RotorType::II, 'A', 1).unwrap());
-        rotors.push(Rotor::new(RotorType::III, 'A', 1).unwrap());
+        let rotors: Vec<Rotor> = vec![
+            Rotor::new(RotorType::I, 'A', 1).unwrap(),
+            Rotor::new(RotorType::II, 'A', 1).unwrap(),
+            Rotor::new(RotorType::III, 'A', 1).unwrap(),
+        ];
 
         let plugs: Vec<[char; 2]> = Vec::new();
         let plugboard = Plugboard::new(&plugs).unwrap();
@@ -33,28 +127,293 @@ impl EnigmaGui {
         EnigmaGui {
             input: String::new(),
             previous_input: String::from("Type a message here"),
+            history: Vec::new(),
+            input_policy: InputPolicy::Fold,
+            preserve_case: false,
             output: String::new(),
+            model: None,
             reflector_type: ReflectorType::B,
+            reflector_ring: 1,
+            reflector_key: 'A',
+            reflector_notation: ReflectorNotation::German,
+            reflector_plugs: String::new(),
+            stepping_mode: SteppingMode::DoubleStep,
+            entry_wheel_type: EntryWheelType::Military,
+            greek_wheel_type: None,
+            greek_wheel_ring: 1,
+            greek_wheel_key: 'A',
             rotor_type: [RotorType::I, RotorType::II, RotorType::III],
             rotor_ring: [1, 1, 1],
             rotor_key: ['A', 'A', 'A'],
             current_plug_pair: ['A', 'Z'],
             plugs: Vec::new(),
+            uhr_setting: None,
+            settings: String::new(),
+            catalog_path: String::new(),
+            catalog: Catalog::default(),
+            key_sheet_path: String::new(),
+            key_sheets: Vec::new(),
+            key_sheet_index: 0,
+            key_sheet_day: String::new(),
+            kenngruppe: String::new(),
+            catalog_model: None,
+            catalog_reflector: None,
+            catalog_entry_wheel: None,
+            catalog_rotor: [None, None, None],
             enigma: Enigma::new(ReflectorType::B, rotors, plugboard),
         }
     }
 
     fn apply_settings(&mut self) {
+        match self.try_apply_settings() {
+            // Messages from the old machine can't be undone on the new one.
+            Ok(()) => self.history.clear(),
+            Err(e) => self.output = format!("[ERROR]: {:#}", e),
+        }
+    }
+
+    fn try_apply_settings(&mut self) -> anyhow::Result<()> {
         let mut rotors: Vec<Rotor> = Vec::with_capacity(3);
 
         for i in 0..3 {
-            let rotor = Rotor::new(self.rotor_type[i], self.rotor_key[i], self.rotor_ring[i]).unwrap();
+            let rotor = match &self.catalog_rotor[i] {
+                Some(name) => self.catalog.get_rotor(name, self.rotor_key[i], self.rotor_ring[i])?,
+                None => Rotor::new(self.rotor_type[i], self.rotor_key[i], self.rotor_ring[i])?,
+            };
+
             rotors.push(rotor);
         }
 
-        let plugboard = Plugboard::new(&self.plugs).unwrap();
+        let plugboard = match self.uhr_setting {
+            Some(uhr_setting) => Plugboard::with_uhr(&self.plugs, uhr_setting)?,
+            None => Plugboard::new(&self.plugs)?,
+        };
+
+        let greek_wheel = self.greek_wheel_type
+            .map(|t| GreekWheel::new(t, self.greek_wheel_key, self.greek_wheel_ring).unwrap());
+
+        // Only the UKW-D can be rewired, a reflector from the catalog comes with its own wiring.
+        let mut reflector_wiring = match (&self.catalog_reflector, self.reflector_type) {
+            (Some(name), _) => Some(self.catalog.get_reflector(name)?),
+            (None, ReflectorType::D) => Some(self.get_reflector_plugging()?),
+            (None, _) => None,
+        };
+
+        if let Some(model) = &self.catalog_model {
+            if greek_wheel.is_some() {
+                return Err(anyhow::anyhow!("The {} does not take a greek wheel.", model));
+            }
+
+            let reflector = match reflector_wiring.take() {
+                Some(reflector) => reflector,
+                None => Reflector::with_setting(self.reflector_type, self.reflector_key, self.reflector_ring)?,
+            };
+
+            // The catalog model picks its rotors by name, so the built in selections are passed by their names.
+
+            let names: Vec<String> = (0..3)
+                .map(|i| self.catalog_rotor[i].clone().unwrap_or_else(|| format!("{:?}", self.rotor_type[i])))
+                .collect();
+
+            let settings: Vec<(&str, char, usize)> = (0..3)
+                .map(|i| (names[i].as_str(), self.rotor_key[i], self.rotor_ring[i]))
+                .collect();
+
+            self.enigma = self.catalog.new_enigma(model, reflector, &settings, plugboard)?;
+            return Ok(());
+        }
+
+        self.enigma = match (self.model, greek_wheel) {
+            (Some(model), greek_wheel) => {
+                let reflector = match reflector_wiring.take() {
+                    Some(reflector) => reflector,
+                    None => Reflector::with_setting(self.reflector_type, self.reflector_key, self.reflector_ring)?,
+                };
+
+                Enigma::new_with_model(model, reflector, greek_wheel, rotors, plugboard)?
+            },
+            (None, Some(greek_wheel)) => Enigma::new_m4(self.reflector_type, greek_wheel, rotors, plugboard)?,
+            (None, None) => Enigma::new(self.reflector_type, rotors, plugboard),
+        };
+
+        if let Some(reflector) = reflector_wiring {
+            self.enigma.set_reflector(reflector);
+        }
+
+        // A model brings its own entry wheel and stepping.
+        if self.model.is_none() {
+            let entry_wheel = match &self.catalog_entry_wheel {
+                Some(name) => self.catalog.get_entry_wheel(name)?,
+                None => EntryWheel::new(self.entry_wheel_type),
+            };
+
+            self.enigma.set_entry_wheel(entry_wheel);
+            self.enigma.set_stepping_mode(self.stepping_mode);
+        }
+
+        Ok(())
+    }
+
+    // Fill in the selections from key sheet notation such as 'B II IV V 02 21 12 BLA AV BS CG DL FU HZ IN KM OW RX'.
+    fn load_settings(&mut self) -> anyhow::Result<()> {
+        let settings: MachineSettings = self.settings.parse()?;
+        settings.validate()?;
+
+        self.model = settings.model;
+        self.catalog_model = None;
+        self.reflector_type = settings.reflector;
+        self.reflector_key = settings.reflector_key;
+        self.reflector_ring = settings.reflector_ring;
+        self.catalog_reflector = None;
+        self.greek_wheel_type = settings.greek_wheel;
+
+        // The greek wheel settings come first, ahead of the three rotors.
+        let offset = settings.key.len() - 3;
+
+        if offset == 1 {
+            self.greek_wheel_ring = settings.rings[0];
+            self.greek_wheel_key = settings.key[0];
+        }
+
+        for i in 0..3 {
+            self.rotor_type[i] = settings.rotors[i];
+            self.rotor_ring[i] = settings.rings[i + offset];
+            self.rotor_key[i] = settings.key[i + offset];
+            self.catalog_rotor[i] = None;
+        }
+
+        self.plugs = settings.plugs;
+        self.update_selected_plugs();
+
+        Ok(())
+    }
+
+    // The current selections in key sheet notation. Parts from the catalog have no notation, so are left out.
+    fn get_settings(&self) -> MachineSettings {
+        let mut rings: Vec<usize> = Vec::new();
+        let mut key = String::new();
+
+        if self.greek_wheel_type.is_some() {
+            rings.push(self.greek_wheel_ring);
+            key.push(self.greek_wheel_key);
+        }
+
+        rings.extend(self.rotor_ring);
+        key.extend(self.rotor_key);
+
+        let mut settings = MachineSettings::new(self.reflector_type, &self.rotor_type, &rings, &key, &self.plugs);
+        settings.model = self.model;
+        settings.greek_wheel = self.greek_wheel_type;
+
+        if self.model.is_some_and(|m| m.has_settable_reflector()) {
+            settings.reflector_key = self.reflector_key;
+            settings.reflector_ring = self.reflector_ring;
+        }
+
+        settings
+    }
+
+    // Load a catalog of extra parts, dropping any selections from the catalog it replaces.
+    fn load_catalog(&mut self) {
+        match Catalog::load(self.catalog_path.trim()) {
+            Ok(catalog) => {
+                self.catalog = catalog;
+                self.catalog_model = None;
+                self.catalog_reflector = None;
+                self.catalog_entry_wheel = None;
+                self.catalog_rotor = [None, None, None];
+                self.output = format!("Loaded catalog {}.", self.catalog_path.trim());
+            },
+            Err(e) => self.output = format!("[ERROR]: {:#}", e),
+        }
+    }
+
+    // Load a key sheet next to the ones already loaded. A sheet for a network that is already loaded takes its place.
+    fn load_key_sheet(&mut self) {
+        match KeySheet::load(self.key_sheet_path.trim()) {
+            Ok(sheet) => {
+                self.output = format!("Loaded key sheet {} with {} days.", sheet.network.as_deref().unwrap_or("-"), sheet.days.len());
+
+                match self.key_sheets.iter().position(|s| s.network == sheet.network) {
+                    Some(index) => {
+                        self.key_sheets[index] = sheet;
+                        self.key_sheet_index = index;
+                    },
+                    None => {
+                        self.key_sheets.push(sheet);
+                        self.key_sheet_index = self.key_sheets.len() - 1;
+                    },
+                }
+            },
+            Err(e) => self.output = format!("[ERROR]: {:#}", e),
+        }
+    }
+
+    fn get_key_sheet(&self) -> anyhow::Result<&KeySheet> {
+        self.key_sheets.get(self.key_sheet_index).ok_or_else(|| anyhow::anyhow!("No key sheet is loaded."))
+    }
+
+    // Set the machine up for a day on the key sheet, given as the day of the month or a date such as '1941-06-22'. The
+    // rotors keep their current keys, as the message key isn't on the sheet.
+    fn use_key_sheet_day(&mut self) -> anyhow::Result<()> {
+        let sheet = self.get_key_sheet()?;
+        let day = self.key_sheet_day.trim();
+
+        let daily_key = match day.parse::<u32>() {
+            Ok(d) => sheet.get_day(d).ok_or_else(|| anyhow::anyhow!("There is no key for day {} on the key sheet.", d))?,
+            Err(_) => sheet.get_date(day)?,
+        };
+
+        let daily_key = daily_key.clone();
+        self.use_daily_key(&daily_key)
+    }
+
+    // Find the network and day from a Kenngruppe received with a message, searching all the loaded key sheets.
+    fn find_kenngruppe(&mut self) -> anyhow::Result<()> {
+        if self.key_sheets.is_empty() {
+            return Err(anyhow::anyhow!("No key sheet is loaded."));
+        }
+
+        let kenngruppe = self.kenngruppe.trim().to_ascii_uppercase();
 
-        self.enigma = Enigma::new(self.reflector_type, rotors, plugboard)
+        let (sheet, daily_key) = KeySheet::find_kenngruppe_in(&self.key_sheets, &kenngruppe)
+            .ok_or_else(|| anyhow::anyhow!("The Kenngruppe {} is not on any of the key sheets.", kenngruppe))?;
+        let daily_key = daily_key.clone();
+
+        self.key_sheet_index = self.key_sheets.iter().position(|s| std::ptr::eq(s, sheet)).unwrap_or(0);
+        self.key_sheet_day = daily_key.day.to_string();
+        self.use_daily_key(&daily_key)
+    }
+
+    fn use_daily_key(&mut self, daily_key: &DailyKey) -> anyhow::Result<()> {
+        let sheet = self.get_key_sheet()?;
+        let network = sheet.network.clone().unwrap_or_else(|| String::from("-"));
+        let key: String = self.rotor_key.iter().collect();
+
+        self.settings = daily_key.get_settings(sheet.reflector, &key).to_string();
+        self.load_settings()?;
+        self.try_apply_settings()?;
+        self.history.clear();
+        self.output = format!("Key sheet {} day {}: {}", network, daily_key.day, self.settings);
+
+        Ok(())
+    }
+
+    // Parse the UKW-D plug connections, entered as pairs of letters separated by spaces such as 'AF CV DS'.
+    fn get_reflector_plugging(&self) -> anyhow::Result<Reflector> {
+        let mut pairs: Vec<[char; 2]> = Vec::new();
+
+        for plug in self.reflector_plugs.split_whitespace() {
+            let chars: Vec<char> = plug.to_ascii_uppercase().chars().collect();
+
+            if chars.len() != 2 {
+                return Err(anyhow::anyhow!("Not a valid plug pair {}.", plug));
+            }
+
+            pairs.push([chars[0], chars[1]]);
+        }
+
+        Reflector::with_plugging(&pairs, self.reflector_notation)
     }
 
     fn is_plug_available(&self, c: char) -> bool {
@@ -103,43 +462,207 @@ impl EnigmaGui {
     fn add_reflector(&mut self, ui: &mut Ui) -> Response {
         ui.group(|ui| {
             ui.vertical(|ui| {
+                ui.label("Model");
+
+                ui.horizontal(|ui| {
+                    ui.label("Type:");
+                    egui::ComboBox::from_id_source("model-combobox")
+                        .selected_text(match (&self.catalog_model, self.model) {
+                            (Some(name), _) => name.clone(),
+                            (None, Some(m)) => format!("{:?}", m),
+                            (None, None) => String::from("Any"),
+                        })
+                        .show_ui(ui, |ui| {
+                            if ui.selectable_label(self.model.is_none() && self.catalog_model.is_none(), "Any").clicked() {
+                                self.model = None;
+                                self.catalog_model = None;
+                            }
+
+                            for (model, name) in MODELS {
+                                if ui.selectable_label(self.model == Some(model) && self.catalog_model.is_none(), name).clicked() {
+                                    self.model = Some(model);
+                                    self.catalog_model = None;
+                                }
+                            }
+
+                            for name in self.catalog.get_model_names() {
+                                if ui.selectable_label(self.catalog_model.as_deref() == Some(name), name).clicked() {
+                                    self.model = None;
+                                    self.catalog_model = Some(name.to_string());
+                                }
+                            }
+                        });
+                });
+
                 ui.label("Reflector");
 
                 ui.horizontal(|ui| {
                     ui.label("Type:");
                     egui::ComboBox::from_id_source("reflector-combobox")
-                        .selected_text(format!("{:?}", self.reflector_type))
+                        .selected_text(match &self.catalog_reflector {
+                            Some(name) => name.clone(),
+                            None => format!("{:?}", self.reflector_type),
+                        })
+                        .show_ui(ui, |ui| {
+                            for (t, name) in REFLECTORS {
+                                if ui.selectable_label(self.reflector_type == t && self.catalog_reflector.is_none(), name).clicked() {
+                                    self.reflector_type = t;
+                                    self.catalog_reflector = None;
+                                }
+                            }
+
+                            for name in self.catalog.get_reflector_names() {
+                                if ui.selectable_label(self.catalog_reflector.as_deref() == Some(name), name).clicked() {
+                                    self.catalog_reflector = Some(name.to_string());
+                                }
+                            }
+                        });
+                });
+
+                ui.add_enabled_ui(self.reflector_type == ReflectorType::D && self.catalog_reflector.is_none(), |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Notation:");
+                        egui::ComboBox::from_id_source("reflector-notation-combobox")
+                            .selected_text(format!("{:?}", self.reflector_notation))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.reflector_notation, ReflectorNotation::German, "German");
+                                ui.selectable_value(&mut self.reflector_notation, ReflectorNotation::Bletchley, "Bletchley");
+                            });
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Plugs:");
+                        ui.text_edit_singleline(&mut self.reflector_plugs);
+                    });
+                });
+
+                let settable = self.model.is_some_and(|m| m.has_settable_reflector());
+
+                ui.add_enabled_ui(settable, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Ring:");
+                        ui.add(egui::Slider::new(&mut self.reflector_ring, 1..=26));
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Key:");
+                        egui::ComboBox::from_id_source("reflector-key-combobox")
+                            .selected_text(self.reflector_key.to_string())
+                            .show_ui(ui, |ui| {
+                                for c in 'A'..='Z' {
+                                    if ui.selectable_label(false, c.to_string()).clicked() {
+                                        self.reflector_key = c;
+                                    }
+                                }
+                            });
+                    });
+                });
+
+                ui.label("Entry Wheel");
+
+                ui.horizontal(|ui| {
+                    ui.label("Type:");
+                    egui::ComboBox::from_id_source("entry-wheel-combobox")
+                        .selected_text(match &self.catalog_entry_wheel {
+                            Some(name) => name.clone(),
+                            None => format!("{:?}", self.entry_wheel_type),
+                        })
                         .show_ui(ui, |ui| {
-                            ui.selectable_value(&mut self.reflector_type, ReflectorType::Beta, "Beta");
-                            ui.selectable_value(&mut self.reflector_type, ReflectorType::Gamma, "Gamma");
-                            ui.selectable_value(&mut self.reflector_type, ReflectorType::A, "A");
-                            ui.selectable_value(&mut self.reflector_type, ReflectorType::B, "B");
-                            ui.selectable_value(&mut self.reflector_type, ReflectorType::C, "C");
-                            ui.selectable_value(&mut self.reflector_type, ReflectorType::ThinB, "ThinB");
-                            ui.selectable_value(&mut self.reflector_type, ReflectorType::ThinC, "ThinC");
-                            ui.selectable_value(&mut self.reflector_type, ReflectorType::ETW, "ETW");
+                            for (t, name) in ENTRY_WHEELS {
+                                if ui.selectable_label(self.entry_wheel_type == t && self.catalog_entry_wheel.is_none(), name).clicked() {
+                                    self.entry_wheel_type = t;
+                                    self.catalog_entry_wheel = None;
+                                }
+                            }
+
+                            for name in self.catalog.get_entry_wheel_names() {
+                                if ui.selectable_label(self.catalog_entry_wheel.as_deref() == Some(name), name).clicked() {
+                                    self.catalog_entry_wheel = Some(name.to_string());
+                                }
+                            }
+                        });
+                });
+
+                ui.label("Stepping");
+
+                ui.horizontal(|ui| {
+                    ui.label("Mode:");
+                    egui::ComboBox::from_id_source("stepping-combobox")
+                        .selected_text(format!("{:?}", self.stepping_mode))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.stepping_mode, SteppingMode::DoubleStep, "DoubleStep");
+                            ui.selectable_value(&mut self.stepping_mode, SteppingMode::Odometer, "Odometer");
                         });
                 });
             });
         }).response
     }
 
+    fn add_greek_wheel(&mut self, ui: &mut Ui) -> Response {
+        ui.group(|ui| {
+            ui.vertical(|ui| {
+                ui.label("Greek Wheel");
+
+                ui.horizontal(|ui| {
+                    ui.label("Type:");
+                    egui::ComboBox::from_id_source("greek-wheel-combobox")
+                        .selected_text(match self.greek_wheel_type {
+                            Some(t) => format!("{:?}", t),
+                            None => String::from("None"),
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.greek_wheel_type, None, "None");
+                            ui.selectable_value(&mut self.greek_wheel_type, Some(GreekWheelType::Beta), "Beta");
+                            ui.selectable_value(&mut self.greek_wheel_type, Some(GreekWheelType::Gamma), "Gamma");
+                        });
+                });
+
+                ui.add_enabled_ui(self.greek_wheel_type.is_some(), |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Ring:");
+                        ui.add(egui::Slider::new(&mut self.greek_wheel_ring, 1..=26));
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Key:");
+                        egui::ComboBox::from_id_source("greek-wheel-key-combobox")
+                            .selected_text(self.greek_wheel_key.to_string())
+                            .show_ui(ui, |ui| {
+                                for c in 'A'..='Z' {
+                                    if ui.selectable_label(false, c.to_string()).clicked() {
+                                        self.greek_wheel_key = c;
+                                    }
+                                }
+                            });
+                    });
+                });
+            });
+        }).response
+    }
+
     fn add_rotor(&mut self, ui: &mut Ui, rotor_index: usize) -> Response {
         ui.group(|ui| {
             ui.vertical(|ui| {
                 ui.horizontal(|ui| {
                     ui.label("Type:");
                     egui::ComboBox::from_id_source(format!("rotor-combobox{rotor_index}"))
-                        .selected_text(format!("{:?}", self.rotor_type[rotor_index]))
+                        .selected_text(match &self.catalog_rotor[rotor_index] {
+                            Some(name) => name.clone(),
+                            None => format!("{:?}", self.rotor_type[rotor_index]),
+                        })
                         .show_ui(ui, |ui| {
-                            ui.selectable_value(&mut self.rotor_type[rotor_index], RotorType::I, "I");
-                            ui.selectable_value(&mut self.rotor_type[rotor_index], RotorType::II, "II");
-                            ui.selectable_value(&mut self.rotor_type[rotor_index], RotorType::III, "III");
-                            ui.selectable_value(&mut self.rotor_type[rotor_index], RotorType::IV, "IV");
-                            ui.selectable_value(&mut self.rotor_type[rotor_index], RotorType::V, "V");
-                            ui.selectable_value(&mut self.rotor_type[rotor_index], RotorType::VI, "VI");
-                            ui.selectable_value(&mut self.rotor_type[rotor_index], RotorType::VII, "VII");
-                            ui.selectable_value(&mut self.rotor_type[rotor_index], RotorType::VIII, "VIII");
+                            for (t, name) in ROTORS {
+                                if ui.selectable_label(self.rotor_type[rotor_index] == t && self.catalog_rotor[rotor_index].is_none(), name).clicked() {
+                                    self.rotor_type[rotor_index] = t;
+                                    self.catalog_rotor[rotor_index] = None;
+                                }
+                            }
+
+                            for name in self.catalog.get_rotor_names() {
+                                if ui.selectable_label(self.catalog_rotor[rotor_index].as_deref() == Some(name), name).clicked() {
+                                    self.catalog_rotor[rotor_index] = Some(name.to_string());
+                                }
+                            }
                         });
                 });
 
@@ -244,22 +767,71 @@ impl EnigmaGui {
                     self.update_selected_plugs();
                 }
             });
+
+            ui.horizontal(|ui| {
+                // The Uhr takes the place of exactly 10 cables, the first letter of each pair on a red plug.
+                let mut uhr = self.uhr_setting.is_some();
+
+                if ui.checkbox(&mut uhr, "Uhr").changed() {
+                    self.uhr_setting = if uhr { Some(0) } else { None };
+                }
+
+                if let Some(uhr_setting) = &mut self.uhr_setting {
+                    ui.label("Setting:");
+                    ui.add(egui::Slider::new(uhr_setting, 0..=39));
+                }
+            });
         }).response
     }
 
     fn encrypt(&mut self) {
-        self.output = match self.enigma.encrypt(&self.input.to_uppercase()) {
-            Ok(s) => s,
-            Err(e) => format!("[ERROR]: {}", e.to_string()),
+        self.enigma.set_input_policy(self.input_policy, self.preserve_case);
+
+        // Rejected input leaves the rotors where they were, so only encrypted messages can be undone.
+        self.output = match self.enigma.encrypt(&self.input) {
+            Ok(s) => {
+                self.history.push(self.input.clone());
+                s
+            },
+            Err(e) => format!("[ERROR]: {}", e),
         };
 
         self.previous_input = self.input.clone();
         self.input.clear();
 
+        self.update_keys();
+    }
+
+    // Step the rotors back to before the last message, and put it back in the input to be corrected.
+    fn undo(&mut self) {
+        let input = match self.history.pop() {
+            Some(input) => input,
+            None => return,
+        };
+
+        // Only letters press a key, whatever the input policy did with the rest.
+        let count = input.chars().filter(|c| c.is_ascii_alphabetic()).count();
+
+        if let Err(e) = self.enigma.rewind(count) {
+            self.output = format!("[ERROR]: {:#}", e);
+            return;
+        }
+
+        self.input = input;
+        self.output.clear();
+        self.update_keys();
+    }
+
+    fn update_keys(&mut self) {
+        // The greek wheel never steps, so only the keys of the three rotors on the right need updating.
         let keys = self.enigma.get_keys();
-        self.rotor_key[0] = keys[0];
-        self.rotor_key[1] = keys[1];
-        self.rotor_key[2] = keys[2];
+        let offset = keys.len() - 3;
+        self.rotor_key[0] = keys[offset];
+        self.rotor_key[1] = keys[offset + 1];
+        self.rotor_key[2] = keys[offset + 2];
+
+        // The reflector of the Enigma G is moved by the rotors.
+        self.reflector_key = self.enigma.get_reflector_key();
     }
 }
 
@@ -273,6 +845,7 @@ impl App for EnigmaGui {
 
                 ui.horizontal(|ui| {
                     self.add_reflector(ui);
+                    self.add_greek_wheel(ui);
                     self.add_rotors(ui);
                 });
 
@@ -282,6 +855,69 @@ impl App for EnigmaGui {
 
                 ui.add_space(10.0);
 
+                ui.horizontal(|ui| {
+                    ui.label("Settings:");
+                    ui.add(egui::TextEdit::singleline(&mut self.settings).hint_text("B II IV V 02 21 12 BLA AV BS CG DL FU HZ IN KM OW RX").desired_width(400.0));
+
+                    if ui.button("Load Settings").clicked() {
+                        if let Err(e) = self.load_settings() {
+                            self.output = format!("[ERROR]: {:#}", e);
+                        }
+                    }
+
+                    if ui.button("Show Settings").clicked() {
+                        self.settings = self.get_settings().to_string();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Catalog:");
+                    ui.add(egui::TextEdit::singleline(&mut self.catalog_path).hint_text("catalog.toml"));
+
+                    if ui.button("Load Catalog").clicked() {
+                        self.load_catalog();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Key Sheet:");
+                    ui.add(egui::TextEdit::singleline(&mut self.key_sheet_path).hint_text("sheet.json"));
+
+                    if ui.button("Load Key Sheet").clicked() {
+                        self.load_key_sheet();
+                    }
+
+                    ui.label("Network:");
+                    egui::ComboBox::from_id_source("network-combobox")
+                        .selected_text(match self.key_sheets.get(self.key_sheet_index) {
+                            Some(sheet) => sheet.network.clone().unwrap_or_else(|| String::from("-")),
+                            None => String::from("-"),
+                        })
+                        .show_ui(ui, |ui| {
+                            for (index, sheet) in self.key_sheets.iter().enumerate() {
+                                if ui.selectable_label(self.key_sheet_index == index, sheet.network.as_deref().unwrap_or("-")).clicked() {
+                                    self.key_sheet_index = index;
+                                }
+                            }
+                        });
+
+                    ui.add(egui::TextEdit::singleline(&mut self.key_sheet_day).hint_text("Day or 1941-06-22").desired_width(100.0));
+
+                    if ui.button("Use Day").clicked() {
+                        if let Err(e) = self.use_key_sheet_day() {
+                            self.output = format!("[ERROR]: {:#}", e);
+                        }
+                    }
+
+                    ui.add(egui::TextEdit::singleline(&mut self.kenngruppe).hint_text("Kenngruppe").desired_width(60.0));
+
+                    if ui.button("Find Kenngruppe").clicked() {
+                        if let Err(e) = self.find_kenngruppe() {
+                            self.output = format!("[ERROR]: {:#}", e);
+                        }
+                    }
+                });
+
                 ui.vertical_centered(|ui| {
                     if ui.button("Apply Settings").clicked() {
                         self.apply_settings();
@@ -293,12 +929,33 @@ impl App for EnigmaGui {
 
             ui.group(|ui| {
                 ui.heading("Input");
+
+                ui.horizontal(|ui| {
+                    ui.label("Other characters:");
+                    egui::ComboBox::from_id_source("input-policy-combobox")
+                        .selected_text(format!("{:?}", self.input_policy))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.input_policy, InputPolicy::Error, "Error");
+                            ui.selectable_value(&mut self.input_policy, InputPolicy::Fold, "Fold");
+                            ui.selectable_value(&mut self.input_policy, InputPolicy::Strip, "Strip");
+                            ui.selectable_value(&mut self.input_policy, InputPolicy::PassThrough, "PassThrough");
+                        });
+
+                    ui.checkbox(&mut self.preserve_case, "Keep case");
+                });
+
                 ui.add_sized([ui.available_width(), 0.0], egui::TextEdit::multiline(&mut self.input).hint_text(&self.previous_input));
 
                 ui.vertical_centered(|ui| {
-                    if ui.button("Encrypt").clicked() {
-                        self.encrypt();
-                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Encrypt").clicked() {
+                            self.encrypt();
+                        }
+
+                        if ui.add_enabled(!self.history.is_empty(), egui::Button::new("Undo")).clicked() {
+                            self.undo();
+                        }
+                    });
                 });
             });
 
@@ -320,7 +977,7 @@ fn main() -> eframe::Result<()> {
         drag_and_drop_support: true,
         icon_data: None,
         initial_window_pos: None,
-        initial_window_size: Option::from(egui::Vec2::new(810 as f32, 600 as f32)),
+        initial_window_size: Option::from(egui::Vec2::new(990_f32, 600_f32)),
         min_window_size: None,
         max_window_size: None,
         resizable: false,
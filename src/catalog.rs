@@ -0,0 +1,445 @@
+use crate::rotor::Rotor;
+use crate::reflector::Reflector;
+use crate::entry_wheel::{EntryWheel, EntryWheelType};
+use crate::plugboard::Plugboard;
+use crate::error::Error;
+use crate::{Enigma, SteppingMode};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CatalogRotor {
+    pub name: String,
+    pub wiring: String,
+    // The letters shown in the window when the rotor turns over the rotor to its left.
+    #[serde(default)]
+    pub notches: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CatalogReflector {
+    pub name: String,
+    pub wiring: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CatalogEntryWheel {
+    pub name: String,
+    pub wiring: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CatalogModel {
+    pub name: String,
+    pub rotors: Vec<String>,
+    pub reflectors: Vec<String>,
+    // Keys wired straight through when not given.
+    #[serde(default)]
+    pub entry_wheel: Option<String>,
+    #[serde(default = "CatalogModel::default_plugboard")]
+    pub plugboard: bool,
+    // Either "DoubleStep" or "Odometer", defaults to the double step.
+    #[serde(default)]
+    pub stepping: Option<String>,
+}
+
+impl CatalogModel {
+    fn default_plugboard() -> bool {
+        true
+    }
+}
+
+// A collection of named components loaded at runtime, for machines that are not covered by the built in types.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Catalog {
+    #[serde(default)]
+    rotors: Vec<CatalogRotor>,
+    #[serde(default)]
+    reflectors: Vec<CatalogReflector>,
+    #[serde(default)]
+    entry_wheels: Vec<CatalogEntryWheel>,
+    #[serde(default)]
+    models: Vec<CatalogModel>,
+}
+
+impl Catalog {
+    pub fn from_toml(toml: &str) -> Result<Catalog> {
+        let catalog: Catalog = match toml::from_str(toml) {
+            Ok(c) => c,
+            Err(e) => return Err(Error::CatalogError).with_context(|| { format!("Invalid TOML catalog. {}", e) }),
+        };
+
+        catalog.validate()?;
+
+        Ok(catalog)
+    }
+
+    pub fn from_json(json: &str) -> Result<Catalog> {
+        let catalog: Catalog = match serde_json::from_str(json) {
+            Ok(c) => c,
+            Err(e) => return Err(Error::CatalogError).with_context(|| { format!("Invalid JSON catalog. {}", e) }),
+        };
+
+        catalog.validate()?;
+
+        Ok(catalog)
+    }
+
+    // Load a catalog from a file, picking the format from the extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Catalog> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(Error::from).with_context(|| { format!("Unable to read catalog {}.", path.display()) })?;
+
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+            Some("toml") => Catalog::from_toml(&contents),
+            Some("json") => Catalog::from_json(&contents),
+            _ => Err(Error::CatalogError).with_context(|| { format!("Invalid catalog {}. Must be a .toml or .json file.", path.display()) }),
+        }
+    }
+
+    // Check every wiring up front, so that a bad catalog is rejected when it is loaded rather than when a part is used.
+    fn validate(&self) -> Result<()> {
+        let mut names: Vec<&str> = Vec::new();
+
+        for rotor in &self.rotors {
+            Rotor::from_wiring_string(&rotor.wiring, &rotor.notches, 'A', 1).with_context(|| { format!("Invalid rotor {} in catalog.", rotor.name) })?;
+            names.push(&rotor.name);
+        }
+
+        Catalog::check_unique("rotor", &names)?;
+        names.clear();
+
+        for reflector in &self.reflectors {
+            Reflector::from_wiring_string(&reflector.wiring).with_context(|| { format!("Invalid reflector {} in catalog.", reflector.name) })?;
+            names.push(&reflector.name);
+        }
+
+        Catalog::check_unique("reflector", &names)?;
+        names.clear();
+
+        for entry_wheel in &self.entry_wheels {
+            EntryWheel::from_wiring_string(&entry_wheel.wiring).with_context(|| { format!("Invalid entry wheel {} in catalog.", entry_wheel.name) })?;
+            names.push(&entry_wheel.name);
+        }
+
+        Catalog::check_unique("entry wheel", &names)?;
+        names.clear();
+
+        // A model can list built in parts by name alongside the parts from the catalog.
+        for model in &self.models {
+            for rotor in &model.rotors {
+                if !self.has_rotor(rotor) {
+                    Rotor::get_rotor_type_from_string(rotor).with_context(|| { format!("Invalid model {} in catalog. Unknown rotor {}.", model.name, rotor) })?;
+                }
+            }
+
+            for reflector in &model.reflectors {
+                if !self.has_reflector(reflector) {
+                    Reflector::get_reflector_type_from_string(reflector).with_context(|| { format!("Invalid model {} in catalog. Unknown reflector {}.", model.name, reflector) })?;
+                }
+            }
+
+            if let Some(entry_wheel) = &model.entry_wheel {
+                self.find_entry_wheel(entry_wheel).with_context(|| { format!("Invalid model {} in catalog.", model.name) })?;
+            }
+
+            if let Some(stepping) = &model.stepping {
+                Enigma::get_stepping_mode_from_string(stepping).with_context(|| { format!("Invalid model {} in catalog.", model.name) })?;
+            }
+
+            names.push(&model.name);
+        }
+
+        Catalog::check_unique("model", &names)
+    }
+
+    fn check_unique(kind: &str, names: &[&str]) -> Result<()> {
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                return Err(Error::CatalogError).with_context(|| { format!("Invalid catalog. The {} {} is listed more than once.", kind, name) });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find_rotor(&self, name: &str) -> Result<&CatalogRotor> {
+        match self.rotors.iter().find(|r| r.name == name) {
+            Some(r) => Ok(r),
+            None => Err(Error::CatalogError).with_context(|| { format!("Unknown rotor {}.", name) }),
+        }
+    }
+
+    fn find_reflector(&self, name: &str) -> Result<&CatalogReflector> {
+        match self.reflectors.iter().find(|r| r.name == name) {
+            Some(r) => Ok(r),
+            None => Err(Error::CatalogError).with_context(|| { format!("Unknown reflector {}.", name) }),
+        }
+    }
+
+    fn find_entry_wheel(&self, name: &str) -> Result<&CatalogEntryWheel> {
+        match self.entry_wheels.iter().find(|e| e.name == name) {
+            Some(e) => Ok(e),
+            None => Err(Error::CatalogError).with_context(|| { format!("Unknown entry wheel {}.", name) }),
+        }
+    }
+
+    pub fn get_rotor_names(&self) -> Vec<&str> {
+        self.rotors.iter().map(|r| r.name.as_str()).collect()
+    }
+
+    pub fn get_reflector_names(&self) -> Vec<&str> {
+        self.reflectors.iter().map(|r| r.name.as_str()).collect()
+    }
+
+    pub fn get_entry_wheel_names(&self) -> Vec<&str> {
+        self.entry_wheels.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    pub fn get_model_names(&self) -> Vec<&str> {
+        self.models.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    pub fn has_rotor(&self, name: &str) -> bool {
+        self.find_rotor(name).is_ok()
+    }
+
+    pub fn has_reflector(&self, name: &str) -> bool {
+        self.find_reflector(name).is_ok()
+    }
+
+    pub fn has_entry_wheel(&self, name: &str) -> bool {
+        self.find_entry_wheel(name).is_ok()
+    }
+
+    pub fn has_model(&self, name: &str) -> bool {
+        self.get_model(name).is_ok()
+    }
+
+    pub fn get_rotor(&self, name: &str, key: char, ring_setting: usize) -> Result<Rotor> {
+        let rotor = self.find_rotor(name)?;
+        Rotor::from_wiring_string(&rotor.wiring, &rotor.notches, key, ring_setting)
+    }
+
+    pub fn get_reflector(&self, name: &str) -> Result<Reflector> {
+        Reflector::from_wiring_string(&self.find_reflector(name)?.wiring)
+    }
+
+    pub fn get_entry_wheel(&self, name: &str) -> Result<EntryWheel> {
+        EntryWheel::from_wiring_string(&self.find_entry_wheel(name)?.wiring)
+    }
+
+    pub fn get_model(&self, name: &str) -> Result<&CatalogModel> {
+        match self.models.iter().find(|m| m.name == name) {
+            Some(m) => Ok(m),
+            None => Err(Error::CatalogError).with_context(|| { format!("Unknown model {}.", name) }),
+        }
+    }
+
+    // Create the machine for a catalog model, rejecting any parts that are not listed as belonging to the model. The
+    // reflector is passed in already set up, so a built in one can be turned or rewired as usual. Each rotor is given as
+    // its name, key and ring setting, starting from the leftmost position, and may be from the catalog or built in.
+    pub fn new_enigma(&self, model: &str, reflector: Reflector, rotors: &[(&str, char, usize)], plugboard: Plugboard) -> Result<Enigma> {
+        let model = self.get_model(model)?;
+
+        if !self.is_model_reflector(model, &reflector) {
+            let name = match reflector.get_reflector_type() {
+                Some(reflector_type) => format!("{:?}", reflector_type),
+                None => String::from("with custom wiring"),
+            };
+
+            return Err(Error::CatalogError).with_context(|| { format!("Invalid reflector {} for the {}. Must be one of {:?}.", name, model.name, model.reflectors) });
+        }
+
+        if rotors.len() != 3 {
+            return Err(Error::RotorError).with_context(|| { format!("Invalid number of rotors {} for the {}. Must be 3.", rotors.len(), model.name) });
+        }
+
+        let mut machine_rotors: Vec<Rotor> = Vec::with_capacity(rotors.len());
+
+        for (i, (name, key, ring_setting)) in rotors.iter().enumerate() {
+            if !model.rotors.iter().any(|r| r == name) {
+                return Err(Error::CatalogError).with_context(|| { format!("Invalid rotor {} for the {}. Must be one of {:?}.", name, model.name, model.rotors) });
+            }
+
+            if rotors[..i].iter().any(|r| r.0 == *name) {
+                return Err(Error::RotorError).with_context(|| { format!("Invalid rotors. Rotor {} is used more than once.", name) });
+            }
+
+            // A rotor from the catalog takes precedence over a built in one of the same name.
+            let rotor = match self.has_rotor(name) {
+                true => self.get_rotor(name, *key, *ring_setting)?,
+                false => Rotor::new(Rotor::get_rotor_type_from_string(name)?, *key, *ring_setting)?,
+            };
+
+            machine_rotors.push(rotor);
+        }
+
+        if !model.plugboard && !plugboard.get_plugs().is_empty() {
+            return Err(Error::PlugboardError).with_context(|| { format!("The {} does not have a plugboard.", model.name) });
+        }
+
+        let entry_wheel = match &model.entry_wheel {
+            Some(name) => self.get_entry_wheel(name)?,
+            None => EntryWheel::new(EntryWheelType::Military),
+        };
+
+        let stepping_mode = match &model.stepping {
+            Some(stepping) => Enigma::get_stepping_mode_from_string(stepping)?,
+            None => SteppingMode::DoubleStep,
+        };
+
+        Ok(Enigma::from_parts(entry_wheel, machine_rotors, reflector, plugboard, stepping_mode))
+    }
+
+    // Return true if the reflector is one the model lists, either a catalog reflector with the same wiring or a built in
+    // reflector of the same type.
+    fn is_model_reflector(&self, model: &CatalogModel, reflector: &Reflector) -> bool {
+        model.reflectors.iter().any(|name| match self.find_reflector(name) {
+            Ok(r) => reflector.get_reflector_type().is_none() && Reflector::from_wiring_string(&r.wiring).is_ok_and(|r| r.get_wiring() == reflector.get_wiring()),
+            Err(_) => reflector.get_reflector_type().is_some() && Reflector::get_reflector_type_from_string(name).ok() == reflector.get_reflector_type(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{RotorType, ReflectorType};
+
+    const CATALOG_TOML: &str = r#"
+[[rotors]]
+name = "Wide I"
+wiring = "EKMFLGDQVZNTOWYHXUSPAIBRCJ"
+notches = "Q"
+
+[[rotors]]
+name = "Wide II"
+wiring = "AJDKSIRUXBLHWTMCQGZNPYFVOE"
+notches = "E"
+
+[[rotors]]
+name = "Wide III"
+wiring = "BDFHJLCPRTXVZNYEIWGAKMUSQO"
+notches = "V"
+
+[[reflectors]]
+name = "Wide B"
+wiring = "YRUHQSLDPXNGOKMIEBFZCWVJAT"
+
+[[entry_wheels]]
+name = "Keyboard"
+wiring = "QWERTZUIOASDFGHJKPYXCVBNML"
+
+[[models]]
+name = "Wide"
+rotors = ["Wide I", "Wide II", "Wide III"]
+reflectors = ["Wide B"]
+"#;
+
+    #[test]
+    fn test_catalog_from_toml() {
+        let catalog = Catalog::from_toml(CATALOG_TOML).unwrap();
+
+        assert_eq!(vec!["Wide I", "Wide II", "Wide III"], catalog.get_rotor_names());
+        assert_eq!(vec!["Wide B"], catalog.get_reflector_names());
+        assert_eq!(vec!["Keyboard"], catalog.get_entry_wheel_names());
+        assert_eq!(vec!["Wide"], catalog.get_model_names());
+
+        let model = catalog.get_model("Wide").unwrap();
+        assert!(model.plugboard);
+        assert_eq!(None, model.entry_wheel);
+    }
+
+    #[test]
+    fn test_catalog_from_json() {
+        let json = r#"{
+            "rotors": [{ "name": "Slow", "wiring": "EKMFLGDQVZNTOWYHXUSPAIBRCJ" }],
+            "reflectors": [{ "name": "Thin", "wiring": "ENKQAUYWJICOPBLMDXZVFTHRGS" }],
+            "models": [{ "name": "Slow", "rotors": ["Slow"], "reflectors": ["Thin"], "plugboard": false, "stepping": "Odometer" }]
+        }"#;
+
+        let catalog = Catalog::from_json(json).unwrap();
+
+        assert!(catalog.has_rotor("Slow"));
+        assert!(catalog.has_reflector("Thin"));
+        assert!(!catalog.has_entry_wheel("Slow"));
+        assert!(!catalog.get_model("Slow").unwrap().plugboard);
+    }
+
+    #[test]
+    fn test_catalog_new_enigma() {
+        let catalog = Catalog::from_toml(CATALOG_TOML).unwrap();
+        let rotors = [("Wide I", 'A', 1), ("Wide II", 'A', 1), ("Wide III", 'A', 1)];
+        let mut enigma = catalog.new_enigma("Wide", catalog.get_reflector("Wide B").unwrap(), &rotors, Plugboard::new(&[]).unwrap()).unwrap();
+
+        // The same wirings as the built in I, II, III and B.
+        let output = enigma.encrypt("AAAAA").expect("Failed to encrypt");
+        assert_eq!("BDZGO", output);
+    }
+
+    #[test]
+    fn test_catalog_new_enigma_built_in_parts() {
+        let toml = "[[models]]\nname = \"Mixed\"\nrotors = [\"I\", \"II\", \"III\"]\nreflectors = [\"Commercial\"]\nstepping = \"Odometer\"";
+        let catalog = Catalog::from_toml(toml).unwrap();
+        let rotors = [("I", 'A', 1), ("II", 'A', 1), ("III", 'A', 1)];
+
+        // The reflector keeps its setting.
+        let reflector = Reflector::with_setting(ReflectorType::Commercial, 'B', 1).unwrap();
+        let mut enigma = catalog.new_enigma("Mixed", reflector, &rotors, Plugboard::new(&[]).unwrap()).unwrap();
+        assert_eq!('B', enigma.get_reflector_key());
+
+        let mut expected = Enigma::new_with_reflector(Reflector::with_setting(ReflectorType::Commercial, 'B', 1).unwrap(), vec![
+            Rotor::new(RotorType::I, 'A', 1).unwrap(),
+            Rotor::new(RotorType::II, 'A', 1).unwrap(),
+            Rotor::new(RotorType::III, 'A', 1).unwrap(),
+        ], Plugboard::new(&[]).unwrap());
+        expected.set_stepping_mode(SteppingMode::Odometer);
+
+        assert_eq!(expected.encrypt("HELLOWORLD").unwrap(), enigma.encrypt("HELLOWORLD").unwrap());
+
+        assert!(catalog.new_enigma("Mixed", Reflector::new(ReflectorType::B), &rotors, Plugboard::new(&[]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_catalog_new_enigma_invalid() {
+        let catalog = Catalog::from_toml(CATALOG_TOML).unwrap();
+        let reflector = || catalog.get_reflector("Wide B").unwrap();
+
+        let rotors = [("Wide I", 'A', 1), ("Wide II", 'A', 1), ("Wide IV", 'A', 1)];
+        assert!(catalog.new_enigma("Wide", reflector(), &rotors, Plugboard::new(&[]).unwrap()).is_err());
+
+        let rotors = [("Wide I", 'A', 1), ("Wide II", 'A', 1), ("Wide III", 'A', 1)];
+        assert!(catalog.new_enigma("Narrow", reflector(), &rotors, Plugboard::new(&[]).unwrap()).is_err());
+
+        // A reflector that isn't listed for the model.
+        assert!(catalog.new_enigma("Wide", Reflector::new(ReflectorType::B), &rotors, Plugboard::new(&[]).unwrap()).is_err());
+
+        // The wrong number of rotors, or the same rotor twice.
+        let rotors = [("Wide I", 'A', 1), ("Wide II", 'A', 1)];
+        assert!(catalog.new_enigma("Wide", reflector(), &rotors, Plugboard::new(&[]).unwrap()).is_err());
+
+        let rotors = [("Wide I", 'A', 1), ("Wide II", 'A', 1), ("Wide I", 'B', 1)];
+        assert!(catalog.new_enigma("Wide", reflector(), &rotors, Plugboard::new(&[]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_catalog_invalid() {
+        // Not a permutation.
+        assert!(Catalog::from_toml("[[rotors]]\nname = \"X\"\nwiring = \"AAAAAAAAAAAAAAAAAAAAAAAAAA\"").is_err());
+
+        // Not an involution.
+        assert!(Catalog::from_toml("[[reflectors]]\nname = \"X\"\nwiring = \"EKMFLGDQVZNTOWYHXUSPAIBRCJ\"").is_err());
+
+        // Duplicate names.
+        let toml = "[[reflectors]]\nname = \"X\"\nwiring = \"YRUHQSLDPXNGOKMIEBFZCWVJAT\"\n[[reflectors]]\nname = \"X\"\nwiring = \"YRUHQSLDPXNGOKMIEBFZCWVJAT\"";
+        assert!(Catalog::from_toml(toml).is_err());
+
+        // Model with an unknown part.
+        assert!(Catalog::from_toml("[[models]]\nname = \"X\"\nrotors = [\"IX\"]\nreflectors = []").is_err());
+        assert!(Catalog::from_toml("[[models]]\nname = \"X\"\nrotors = [\"I\"]\nreflectors = [\"E\"]").is_err());
+
+        assert!(Catalog::from_json("{ \"rotors\": 1 }").is_err());
+    }
+}
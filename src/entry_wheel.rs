@@ -0,0 +1,166 @@
+use crate::utils;
+use crate::error::Error;
+use anyhow::{Context, Result};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EntryWheelType {
+    // Military machines wire the keyboard straight through, A to A.
+    Military,
+    // Commercial machines wire the contacts in the order of the QWERTZU keyboard.
+    Commercial,
+    // The Enigma T wires the contacts in its own scrambled order.
+    Tirpitz,
+}
+
+// The entry wheel (Eintrittswalze) is the fixed stator between the plugboard and the right hand rotor.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "EntryWheelFields"))]
+pub struct EntryWheel {
+    // Maps an entry wheel contact to the key wired to it.
+    wiring: [usize; 26],
+    // Maps a key to the entry wheel contact wired to it.
+    wiring_inverse: [usize; 26],
+}
+
+impl EntryWheel {
+    pub fn new(entry_wheel_type: EntryWheelType) -> EntryWheel {
+        EntryWheel::from_wiring(EntryWheel::get_entry_wheel_wiring(entry_wheel_type))
+    }
+
+    // Create an entry wheel that is not one of the known types, from the keys wired to each contact in turn such as
+    // "QWERTZUIOASDFGHJKPYXCVBNML".
+    pub fn from_wiring_string(wiring: &str) -> Result<EntryWheel> {
+        Ok(EntryWheel::from_wiring(utils::get_wiring_from_string(wiring)?))
+    }
+
+    fn from_wiring(wiring: [usize; 26]) -> EntryWheel {
+        let mut wiring_inverse = [0; 26];
+
+        for (i, w) in wiring.iter().enumerate() {
+            wiring_inverse[*w] = i;
+        }
+
+        EntryWheel {
+            wiring,
+            wiring_inverse,
+        }
+    }
+
+    pub fn get_entry_wheel_type_from_string(entry_wheel_type: &str) -> Result<EntryWheelType> {
+        let lower = entry_wheel_type.to_ascii_lowercase();
+
+        let t = match lower.as_str() {
+            "military" => EntryWheelType::Military,
+            "commercial" => EntryWheelType::Commercial,
+            "tirpitz" => EntryWheelType::Tirpitz,
+            _ => return Err(Error::EntryWheelError).with_context(|| { format!("Invalid entry wheel type {}.", entry_wheel_type) }),
+        };
+
+        Ok(t)
+    }
+
+    fn get_entry_wheel_wiring(entry_wheel_type: EntryWheelType) -> [usize; 26] {
+        match entry_wheel_type {
+            EntryWheelType::Military => [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25],
+            EntryWheelType::Commercial => [16, 22, 4, 17, 19, 25, 20, 8, 14, 0, 18, 3, 5, 6, 7, 9, 10, 15, 24, 23, 2, 21, 1, 13, 12, 11],
+            EntryWheelType::Tirpitz => [10, 25, 17, 14, 20, 16, 7, 24, 0, 8, 6, 1, 11, 22, 21, 18, 19, 3, 23, 5, 15, 13, 12, 2, 9, 4],
+        }
+    }
+
+    // Scramble a key on its way into the rotors.
+    pub fn scramble_left(&self, input: usize) -> usize {
+        self.wiring_inverse[input]
+    }
+
+    // Scramble a contact on its way back out to the lamps.
+    pub fn scramble_right(&self, input: usize) -> usize {
+        self.wiring[input]
+    }
+}
+
+// The fields of an entry wheel as they are read back. Only the wiring is read, the inverse is worked out from it.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct EntryWheelFields {
+    wiring: [usize; 26],
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<EntryWheelFields> for EntryWheel {
+    type Error = anyhow::Error;
+
+    fn try_from(fields: EntryWheelFields) -> Result<EntryWheel> {
+        utils::check_wiring(&fields.wiring)?;
+
+        Ok(EntryWheel::from_wiring(fields.wiring))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_entry_wheel_military() {
+        let entry_wheel = EntryWheel::new(EntryWheelType::Military);
+
+        for i in 0..26 {
+            assert_eq!(i, entry_wheel.scramble_left(i));
+            assert_eq!(i, entry_wheel.scramble_right(i));
+        }
+    }
+
+    #[test]
+    fn test_entry_wheel_commercial() {
+        let entry_wheel = EntryWheel::new(EntryWheelType::Commercial);
+
+        assert_eq!(0, entry_wheel.scramble_left(16)); // Q -> A
+        assert_eq!(1, entry_wheel.scramble_left(22)); // W -> B
+        assert_eq!(25, entry_wheel.scramble_left(11)); // L -> Z
+
+        assert_eq!(16, entry_wheel.scramble_right(0)); // A -> Q
+        assert_eq!(22, entry_wheel.scramble_right(1)); // B -> W
+        assert_eq!(11, entry_wheel.scramble_right(25)); // Z -> L
+    }
+
+    #[test]
+    fn test_entry_wheel_tirpitz() {
+        let entry_wheel = EntryWheel::new(EntryWheelType::Tirpitz);
+
+        assert_eq!(0, entry_wheel.scramble_left(10)); // K -> A
+        assert_eq!(25, entry_wheel.scramble_left(4)); // E -> Z
+
+        assert_eq!(10, entry_wheel.scramble_right(0)); // A -> K
+        assert_eq!(4, entry_wheel.scramble_right(25)); // Z -> E
+    }
+
+    #[test]
+    fn test_entry_wheel_from_wiring_string() {
+        let custom = EntryWheel::from_wiring_string("QWERTZUIOASDFGHJKPYXCVBNML").unwrap();
+        let entry_wheel = EntryWheel::new(EntryWheelType::Commercial);
+
+        for i in 0..26 {
+            assert_eq!(entry_wheel.scramble_left(i), custom.scramble_left(i));
+            assert_eq!(entry_wheel.scramble_right(i), custom.scramble_right(i));
+        }
+
+        assert!(EntryWheel::from_wiring_string("QWERTZUIOASDFGHJKPYXCVBNMQ").is_err());
+    }
+
+    #[test]
+    fn test_get_entry_wheel_type_from_string() {
+        assert_eq!(EntryWheelType::Military, EntryWheel::get_entry_wheel_type_from_string("MILITARY").unwrap());
+        assert_eq!(EntryWheelType::Military, EntryWheel::get_entry_wheel_type_from_string("military").unwrap());
+
+        assert_eq!(EntryWheelType::Commercial, EntryWheel::get_entry_wheel_type_from_string("COMMERCIAL").unwrap());
+        assert_eq!(EntryWheelType::Commercial, EntryWheel::get_entry_wheel_type_from_string("commercial").unwrap());
+
+        assert_eq!(EntryWheelType::Tirpitz, EntryWheel::get_entry_wheel_type_from_string("TIRPITZ").unwrap());
+        assert_eq!(EntryWheelType::Tirpitz, EntryWheel::get_entry_wheel_type_from_string("tirpitz").unwrap());
+    }
+
+    #[test]
+    fn test_get_entry_wheel_type_from_string_invalid() {
+        assert!(EntryWheel::get_entry_wheel_type_from_string("blah").is_err());
+    }
+}
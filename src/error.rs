@@ -4,7 +4,14 @@ use std::{error::Error as StdError, fmt::{Display, Formatter}};
 pub enum Error {
     RotorError,
     ReflectorError,
+    GreekWheelError,
+    EntryWheelError,
     PlugboardError,
+    ModelError,
+    CatalogError,
+    SettingsError,
+    BigramError,
+    KeySheetError,
     InputError,
     IOError(std::io::Error),
     Exit,
@@ -15,7 +22,14 @@ impl Display for Error {
         match &self {
             Error::RotorError => write!(f, "Rotor Error!"),
             Error::ReflectorError => write!(f, "Reflector Error!"),
+            Error::GreekWheelError => write!(f, "Greek Wheel Error!"),
+            Error::EntryWheelError => write!(f, "Entry Wheel Error!"),
             Error::PlugboardError => write!(f, "Plugboard Error!"),
+            Error::ModelError => write!(f, "Model Error!"),
+            Error::CatalogError => write!(f, "Catalog Error!"),
+            Error::SettingsError => write!(f, "Settings Error!"),
+            Error::BigramError => write!(f, "Bigram Table Error!"),
+            Error::KeySheetError => write!(f, "Key Sheet Error!"),
             Error::InputError => write!(f, "Input Error!"),
             Error::IOError(source) => write!(f, "IO Error!\n\nCause: {}", source),
             Error::Exit => write!(f, "Exit"),
@@ -28,7 +42,14 @@ impl StdError for Error {
         match &self {
             Error::RotorError => None,
             Error::ReflectorError => None,
+            Error::GreekWheelError => None,
+            Error::EntryWheelError => None,
             Error::PlugboardError => None,
+            Error::ModelError => None,
+            Error::CatalogError => None,
+            Error::SettingsError => None,
+            Error::BigramError => None,
+            Error::KeySheetError => None,
             Error::InputError => None,
             Error::IOError(source) => Some(source),
             Error::Exit => None,
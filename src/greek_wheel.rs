@@ -0,0 +1,104 @@
+use crate::rotor::Rotor;
+use crate::error::Error;
+use anyhow::{Context, Result};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GreekWheelType {
+    Beta,
+    Gamma,
+}
+
+// The fourth (Zusatzwalze) wheel of the Kriegsmarine M4. It sits between the left hand rotor and the thin reflector
+// and has a ring setting and position like any other rotor, but it is never stepped.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GreekWheel {
+    greek_wheel_type: GreekWheelType,
+    rotor: Rotor,
+}
+
+impl GreekWheel {
+    pub fn new(greek_wheel_type: GreekWheelType, key: char, ring_setting: usize) -> Result<GreekWheel> {
+        let rotor = Rotor::from_wiring(GreekWheel::get_greek_wheel_wiring(greek_wheel_type), Vec::new(), key, ring_setting)?;
+
+        Ok(GreekWheel {
+            greek_wheel_type,
+            rotor,
+        })
+    }
+
+    pub fn get_greek_wheel_type(&self) -> GreekWheelType {
+        self.greek_wheel_type
+    }
+
+    pub fn get_greek_wheel_type_from_string(greek_wheel_type: &str) -> Result<GreekWheelType> {
+        let lower = greek_wheel_type.to_ascii_lowercase();
+
+        let t = match lower.as_str() {
+            "beta" => GreekWheelType::Beta,
+            "gamma" => GreekWheelType::Gamma,
+            _ => return Err(Error::GreekWheelError).with_context(|| { format!("Invalid greek wheel type {}.", greek_wheel_type) }),
+        };
+
+        Ok(t)
+    }
+
+    fn get_greek_wheel_wiring(greek_wheel_type: GreekWheelType) -> [usize; 26] {
+        match greek_wheel_type {
+            GreekWheelType::Beta => [11, 4, 24, 9, 21, 2, 13, 8, 23, 22, 15, 1, 16, 12, 3, 17, 19, 0, 10, 25, 6, 5, 20, 7, 14, 18],
+            GreekWheelType::Gamma => [5, 18, 14, 10, 0, 13, 20, 4, 17, 7, 12, 1, 19, 8, 24, 2, 22, 11, 16, 15, 25, 23, 21, 6, 9, 3],
+        }
+    }
+
+    pub fn get_key(&self) -> char {
+        self.rotor.get_key()
+    }
+
+    pub(crate) fn set_position(&mut self, position: usize) {
+        self.rotor.set_position(position);
+    }
+
+    pub fn scramble_left(&self, input: usize) -> usize {
+        self.rotor.scramble_left(input)
+    }
+
+    pub fn scramble_right(&self, input: usize) -> usize {
+        self.rotor.scramble_right(input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_greek_wheel_scramble() {
+        let beta = GreekWheel::new(GreekWheelType::Beta, 'A', 1).unwrap();
+        assert_eq!(11, beta.scramble_left(0)); // A -> L
+        assert_eq!(0, beta.scramble_right(11)); // L -> A
+
+        let gamma = GreekWheel::new(GreekWheelType::Gamma, 'A', 1).unwrap();
+        assert_eq!(5, gamma.scramble_left(0)); // A -> F
+        assert_eq!(0, gamma.scramble_right(5)); // F -> A
+    }
+
+    #[test]
+    fn test_greek_wheel_invalid_ring_setting() {
+        assert!(GreekWheel::new(GreekWheelType::Beta, 'A', 0).is_err());
+        assert!(GreekWheel::new(GreekWheelType::Beta, 'A', 27).is_err());
+    }
+
+    #[test]
+    fn test_get_greek_wheel_type_from_string() {
+        assert_eq!(GreekWheelType::Beta, GreekWheel::get_greek_wheel_type_from_string("BETA").unwrap());
+        assert_eq!(GreekWheelType::Beta, GreekWheel::get_greek_wheel_type_from_string("beta").unwrap());
+
+        assert_eq!(GreekWheelType::Gamma, GreekWheel::get_greek_wheel_type_from_string("GAMMA").unwrap());
+        assert_eq!(GreekWheelType::Gamma, GreekWheel::get_greek_wheel_type_from_string("gamma").unwrap());
+    }
+
+    #[test]
+    fn test_get_greek_wheel_type_from_string_invalid() {
+        assert!(GreekWheel::get_greek_wheel_type_from_string("blah").is_err());
+    }
+}
@@ -0,0 +1,631 @@
+use crate::rotor::{Rotor, RotorType};
+use crate::reflector::{Reflector, ReflectorType};
+use crate::plugboard::Plugboard;
+use crate::settings::MachineSettings;
+use crate::error::Error;
+use crate::utils;
+use anyhow::{Context, Result};
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+// The number of plug connections on every day of a sheet, which left six letters unplugged.
+const PLUG_COUNT: usize = 10;
+
+// The number of Kenngruppen for each day, any of which an operator could send to identify the key.
+const KENNGRUPPE_COUNT: usize = 4;
+
+// The settings for one day of the month.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DailyKey {
+    pub day: u32,
+    pub rotors: Vec<RotorType>,
+    pub rings: Vec<usize>,
+    pub plugs: Vec<[char; 2]>,
+    pub kenngruppen: Vec<String>,
+}
+
+impl DailyKey {
+    // The machine settings for the day, with the rotors at the given key.
+    pub fn get_settings(&self, reflector: ReflectorType, key: &str) -> MachineSettings {
+        MachineSettings::new(reflector, &self.rotors, &self.rings, key, &self.plugs)
+    }
+}
+
+// A key sheet as it is written to a file, with the parts by name.
+#[derive(Serialize, Deserialize)]
+struct KeySheetFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    network: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    month: Option<String>,
+    reflector: String,
+    days: Vec<DailyKeyFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DailyKeyFile {
+    day: u32,
+    rotors: Vec<String>,
+    rings: Vec<usize>,
+    plugs: Vec<String>,
+    kenngruppen: Vec<String>,
+}
+
+// A month of daily keys for one network, all using the same reflector. The network is the name of the group of
+// stations sharing the key, and the month is written as "1941-06".
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeySheet {
+    pub network: Option<String>,
+    pub month: Option<String>,
+    pub reflector: ReflectorType,
+    pub days: Vec<DailyKey>,
+}
+
+impl KeySheet {
+    // Make up a sheet for training, keeping to the rules the key makers followed. A rotor is never in the same place
+    // two days running, there are always ten plug connections and no letter is plugged to the one next to it in the
+    // alphabet. The Kenngruppen are all different across the sheet. A seeded generator always makes the same sheet.
+    pub fn generate<R: Rng>(rng: &mut R, reflector: ReflectorType, rotors: &[RotorType], days: u32) -> Result<KeySheet> {
+        if rotors.len() < 3 || rotors.iter().enumerate().any(|(i, r)| rotors[..i].contains(r)) {
+            return Err(Error::InputError).with_context(|| { format!("Invalid rotors {:?}. At least 3 different rotors are needed.", rotors) });
+        }
+
+        if !(1..=31).contains(&days) {
+            return Err(Error::InputError).with_context(|| { format!("Invalid number of days {}. Expected 1 to 31.", days) });
+        }
+
+        let mut sheet = KeySheet {
+            network: None,
+            month: None,
+            reflector,
+            days: Vec::with_capacity(days as usize),
+        };
+
+        let mut kenngruppen: Vec<String> = Vec::new();
+        let mut pool = rotors.to_vec();
+
+        for day in 1..=days {
+            let previous = sheet.days.last().map(|d| d.rotors.clone());
+
+            let order = loop {
+                let order = pool.partial_shuffle(rng, 3).0.to_vec();
+
+                if previous.as_ref().is_none_or(|p| p.iter().zip(&order).all(|(a, b)| a != b)) {
+                    break order;
+                }
+            };
+
+            let rings = (0..3).map(|_| rng.gen_range(1..=26)).collect();
+            let plugs = KeySheet::generate_plugs(rng)?;
+
+            let mut daily_kenngruppen = Vec::with_capacity(KENNGRUPPE_COUNT);
+
+            while daily_kenngruppen.len() < KENNGRUPPE_COUNT {
+                let kenngruppe = utils::get_random_letters_from(rng, 3);
+
+                if !kenngruppen.contains(&kenngruppe) {
+                    kenngruppen.push(kenngruppe.clone());
+                    daily_kenngruppen.push(kenngruppe);
+                }
+            }
+
+            sheet.days.push(DailyKey {
+                day,
+                rotors: order,
+                rings,
+                plugs,
+                kenngruppen: daily_kenngruppen,
+            });
+        }
+
+        Ok(sheet)
+    }
+
+    fn generate_plugs<R: Rng>(rng: &mut R) -> Result<Vec<[char; 2]>> {
+        let mut letters: Vec<char> = ('A'..='Z').collect();
+
+        loop {
+            letters.shuffle(rng);
+
+            let mut plugs: Vec<[char; 2]> = letters[..(PLUG_COUNT * 2)].chunks(2).map(|p| [p[0].min(p[1]), p[0].max(p[1])]).collect();
+
+            if plugs.iter().all(|p| p[1] as u8 - p[0] as u8 != 1) {
+                plugs.sort();
+
+                // Checked against the plugboard itself, so a sheet can always be used to set up a machine.
+                Plugboard::new(&plugs)?;
+
+                return Ok(plugs);
+            }
+        }
+    }
+
+    pub fn get_day(&self, day: u32) -> Option<&DailyKey> {
+        self.days.iter().find(|d| d.day == day)
+    }
+
+    // The key for a date such as "1941-06-22". If the sheet says which month it is for, the date has to be in it.
+    pub fn get_date(&self, date: &str) -> Result<&DailyKey> {
+        let parts: Vec<&str> = date.trim().split('-').collect();
+
+        let day = match parts.as_slice() {
+            [year, month, day] if year.len() == 4 && month.len() == 2 && day.len() == 2 => day.parse::<u32>().ok(),
+            _ => None,
+        };
+
+        let day = match day {
+            Some(d) => d,
+            None => return Err(Error::KeySheetError).with_context(|| { format!("Invalid date {}. Expected a date such as 1941-06-22.", date) }),
+        };
+
+        // Without a month any date would match one of the days, so it has to be picked by day instead.
+        let month = match &self.month {
+            Some(m) => m,
+            None => return Err(Error::KeySheetError).with_context(|| { format!("The key sheet has no month, so the date {} can't be looked up. Pick the day instead.", date) }),
+        };
+
+        if !date.trim().starts_with(month.as_str()) {
+            return Err(Error::KeySheetError).with_context(|| { format!("The date {} is not in the month of the key sheet, {}.", date, month) });
+        }
+
+        match self.get_day(day) {
+            Some(d) => Ok(d),
+            None => Err(Error::KeySheetError).with_context(|| { format!("There is no key for day {} on the key sheet.", day) }),
+        }
+    }
+
+    // The day a Kenngruppe belongs to, which tells a receiving operator which key a message was sent with.
+    pub fn find_kenngruppe(&self, kenngruppe: &str) -> Option<&DailyKey> {
+        self.days.iter().find(|d| d.kenngruppen.iter().any(|k| k == kenngruppe))
+    }
+
+    // Look for a Kenngruppe across the sheets of several networks, giving the sheet and day it belongs to.
+    pub fn find_kenngruppe_in<'a>(sheets: &'a [KeySheet], kenngruppe: &str) -> Option<(&'a KeySheet, &'a DailyKey)> {
+        sheets.iter().find_map(|s| s.find_kenngruppe(kenngruppe).map(|d| (s, d)))
+    }
+
+    pub fn from_json(json: &str) -> Result<KeySheet> {
+        let file: KeySheetFile = match serde_json::from_str(json) {
+            Ok(f) => f,
+            Err(e) => return Err(Error::KeySheetError).with_context(|| { format!("Invalid JSON key sheet. {}", e) }),
+        };
+
+        KeySheet::from_file(file)
+    }
+
+    // Read the CSV written by to_csv. Every line has to use the same reflector.
+    pub fn from_csv(csv: &str) -> Result<KeySheet> {
+        let mut lines = csv.lines().filter(|l| !l.trim().is_empty());
+
+        if !lines.next().is_some_and(|h| h.trim().starts_with("day,")) {
+            return Err(Error::KeySheetError).with_context(|| "Invalid CSV key sheet. Expected a header line starting with 'day,'.");
+        }
+
+        // The reflector, network and month belong to the whole sheet, so they are repeated on every line.
+        let mut sheet: Option<[&str; 3]> = None;
+        let mut days = Vec::new();
+
+        for line in lines {
+            let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+
+            if fields.len() != 8 {
+                return Err(Error::KeySheetError).with_context(|| { format!("Invalid CSV key sheet line '{}'. Expected 8 fields.", line) });
+            }
+
+            if sheet.is_some_and(|s| s != [fields[1], fields[6], fields[7]]) {
+                return Err(Error::KeySheetError).with_context(|| { format!("Invalid CSV key sheet line '{}'. Every day has to have the same reflector, network and month.", line) });
+            }
+
+            sheet = Some([fields[1], fields[6], fields[7]]);
+            days.push(KeySheet::get_daily_key_file(fields[0], fields[2], fields[3], fields[4], fields[5])?);
+        }
+
+        let [reflector, network, month] = sheet.unwrap_or_default();
+        let optional = |f: &str| if f.is_empty() { None } else { Some(f.to_string()) };
+
+        KeySheet::from_file(KeySheetFile {
+            network: optional(network),
+            month: optional(month),
+            reflector: reflector.to_string(),
+            days,
+        })
+    }
+
+    // Read a sheet laid out as it is printed, as written by to_string or transcribed from an archive.
+    pub fn from_text(text: &str) -> Result<KeySheet> {
+        let mut file = KeySheetFile {
+            network: None,
+            month: None,
+            reflector: String::new(),
+            days: Vec::new(),
+        };
+
+        for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            if let Some(network) = line.strip_prefix("Netz ") {
+                file.network = Some(network.trim().to_string());
+            } else if let Some(month) = line.strip_prefix("Monat ") {
+                file.month = Some(month.trim().to_string());
+            } else if let Some(reflector) = line.strip_prefix("Umkehrwalze ") {
+                file.reflector = reflector.trim().to_string();
+            } else if !line.starts_with("Tag") {
+                let fields: Vec<&str> = line.split('|').map(|f| f.trim()).collect();
+
+                if fields.len() != 5 {
+                    return Err(Error::KeySheetError).with_context(|| { format!("Invalid key sheet line '{}'. Expected 5 columns.", line) });
+                }
+
+                file.days.push(KeySheet::get_daily_key_file(fields[0], fields[1], fields[2], fields[3], fields[4])?);
+            }
+        }
+
+        KeySheet::from_file(file)
+    }
+
+    // Load a key sheet from a file, picking the format from the extension. A sheet that doesn't name its network is
+    // named after the file, so "red.csv" is the key for the Red network.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<KeySheet> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(Error::from).with_context(|| { format!("Unable to read key sheet {}.", path.display()) })?;
+
+        let mut sheet = match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+            Some("json") => KeySheet::from_json(&contents),
+            Some("csv") => KeySheet::from_csv(&contents),
+            Some("txt") => KeySheet::from_text(&contents),
+            _ => Err(Error::KeySheetError).with_context(|| { format!("Invalid key sheet {}. Must be a .json, .csv or .txt file.", path.display()) }),
+        }.with_context(|| { format!("Unable to load key sheet {}.", path.display()) })?;
+
+        if sheet.network.is_none() {
+            sheet.network = path.file_stem().and_then(|s| s.to_str()).map(|s| s.to_string());
+        }
+
+        Ok(sheet)
+    }
+
+    fn get_daily_key_file(day: &str, rotors: &str, rings: &str, plugs: &str, kenngruppen: &str) -> Result<DailyKeyFile> {
+        let day = day.parse::<u32>().map_err(|_| Error::KeySheetError).with_context(|| { format!("Invalid day {} on key sheet.", day) })?;
+        let rings = rings.split_whitespace().map(|r| r.parse::<usize>()).collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| Error::KeySheetError).with_context(|| { format!("Invalid ring settings {} for day {}.", rings, day) })?;
+
+        Ok(DailyKeyFile {
+            day,
+            rotors: rotors.split_whitespace().map(|r| r.to_string()).collect(),
+            rings,
+            plugs: plugs.split_whitespace().map(|p| p.to_string()).collect(),
+            kenngruppen: kenngruppen.split_whitespace().map(|k| k.to_string()).collect(),
+        })
+    }
+
+    // Check every day of a sheet read from a file, so that a bad sheet is rejected when it is loaded rather than on the
+    // day it is used.
+    fn from_file(file: KeySheetFile) -> Result<KeySheet> {
+        let reflector = Reflector::get_reflector_type_from_string(&file.reflector).map_err(|_| Error::KeySheetError)
+            .with_context(|| { format!("Invalid reflector {} on key sheet.", file.reflector) })?;
+
+        if let Some(month) = &file.month {
+            let valid = month.len() == 7 && month.chars().enumerate().all(|(i, c)| if i == 4 { c == '-' } else { c.is_ascii_digit() });
+
+            if !valid {
+                return Err(Error::KeySheetError).with_context(|| { format!("Invalid month {} on key sheet. Expected a month such as 1941-06.", month) });
+            }
+        }
+
+        let mut days: Vec<DailyKey> = Vec::with_capacity(file.days.len());
+
+        for day in &file.days {
+            if !(1..=31).contains(&day.day) || days.iter().any(|d| d.day == day.day) {
+                return Err(Error::KeySheetError).with_context(|| { format!("Invalid day {} on key sheet. Days run from 1 to 31 and each is given once.", day.day) });
+            }
+
+            let rotors = day.rotors.iter().map(|r| Rotor::get_rotor_type_from_string(r)).collect::<Result<Vec<RotorType>>>()
+                .with_context(|| { format!("Invalid rotors for day {}.", day.day) })?;
+
+            if day.rings.len() != rotors.len() || day.rings.iter().any(|r| !(1..=26).contains(r)) {
+                return Err(Error::KeySheetError).with_context(|| { format!("Invalid ring settings {:?} for day {}. Expected one from 1 to 26 for each rotor.", day.rings, day.day) });
+            }
+
+            let plugs: Vec<[char; 2]> = day.plugs.iter()
+                .map(|p| {
+                    let letters: Vec<char> = p.chars().collect();
+
+                    match letters.as_slice() {
+                        [a, b] => Ok([*a, *b]),
+                        _ => Err(Error::KeySheetError).with_context(|| { format!("Invalid plug {} for day {}. Expected a pair of letters.", p, day.day) }),
+                    }
+                })
+                .collect::<Result<Vec<[char; 2]>>>()?;
+
+            Plugboard::new(&plugs).with_context(|| { format!("Invalid plugs for day {}.", day.day) })?;
+
+            if let Some(k) = day.kenngruppen.iter().find(|k| k.len() != 3 || !k.chars().all(|c| c.is_ascii_uppercase())) {
+                return Err(Error::KeySheetError).with_context(|| { format!("Invalid Kenngruppe {} for day {}. Expected 3 upper case letters.", k, day.day) });
+            }
+
+            let key = DailyKey {
+                day: day.day,
+                rotors,
+                rings: day.rings.clone(),
+                plugs,
+                kenngruppen: day.kenngruppen.clone(),
+            };
+
+            // Each part can be fine on its own and still not make a machine, such as a rotor used twice or two rotors.
+            key.get_settings(reflector, "AAA").validate().with_context(|| { format!("Invalid settings for day {}.", day.day) })?;
+
+            days.push(key);
+        }
+
+        if days.is_empty() {
+            return Err(Error::KeySheetError).with_context(|| "Invalid key sheet. There are no days on it.");
+        }
+
+        days.sort_by_key(|d| d.day);
+
+        Ok(KeySheet {
+            network: file.network,
+            month: file.month,
+            reflector,
+            days,
+        })
+    }
+
+    // One line for each day, with a header row, and the fields that have more than one value separated by spaces.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("day,reflector,rotors,rings,plugs,kenngruppen,network,month\n");
+        let network = self.network.as_deref().unwrap_or_default();
+        let month = self.month.as_deref().unwrap_or_default();
+
+        for day in &self.days {
+            csv.push_str(&format!("{},{:?},{},{},{},{},{},{}\n", day.day, self.reflector, KeySheet::get_rotor_names(day).join(" "), KeySheet::get_rings(day), KeySheet::get_plugs(day).join(" "), day.kenngruppen.join(" "), network, month));
+        }
+
+        csv
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        let file = KeySheetFile {
+            network: self.network.clone(),
+            month: self.month.clone(),
+            reflector: format!("{:?}", self.reflector),
+            days: self.days.iter()
+                .map(|d| DailyKeyFile {
+                    day: d.day,
+                    rotors: KeySheet::get_rotor_names(d),
+                    rings: d.rings.clone(),
+                    plugs: KeySheet::get_plugs(d),
+                    kenngruppen: d.kenngruppen.clone(),
+                })
+                .collect(),
+        };
+
+        match serde_json::to_string_pretty(&file) {
+            Ok(json) => Ok(json),
+            Err(e) => Err(Error::InputError).with_context(|| { format!("Unable to write the key sheet as JSON. {}", e) }),
+        }
+    }
+
+    fn get_rotor_names(day: &DailyKey) -> Vec<String> {
+        day.rotors.iter().map(|r| format!("{:?}", r)).collect()
+    }
+
+    fn get_rings(day: &DailyKey) -> String {
+        day.rings.iter().map(|r| format!("{:02}", r)).collect::<Vec<String>>().join(" ")
+    }
+
+    fn get_plugs(day: &DailyKey) -> Vec<String> {
+        day.plugs.iter().map(|p| p.iter().collect()).collect()
+    }
+}
+
+// Laid out like the printed sheets, which ran from the last day of the month down to the first so that the day just
+// used could be cut off the bottom and destroyed.
+impl Display for KeySheet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(network) = &self.network {
+            writeln!(f, "Netz {}", network)?;
+        }
+
+        if let Some(month) = &self.month {
+            writeln!(f, "Monat {}", month)?;
+        }
+
+        writeln!(f, "Umkehrwalze {:?}", self.reflector)?;
+        writeln!(f, "{:<5}| {:<13}| {:<13}| {:<30}| Kenngruppen", "Tag", "Walzenlage", "Ringstellung", "Steckerverbindungen")?;
+
+        for day in self.days.iter().rev() {
+            writeln!(f, "{:<5}| {:<13}| {:<13}| {:<30}| {}", day.day, KeySheet::get_rotor_names(day).join(" "), KeySheet::get_rings(day), KeySheet::get_plugs(day).join(" "), day.kenngruppen.join(" "))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const ROTORS: [RotorType; 5] = [RotorType::I, RotorType::II, RotorType::III, RotorType::IV, RotorType::V];
+
+    fn get_sheet() -> KeySheet {
+        KeySheet {
+            network: None,
+            month: None,
+            reflector: ReflectorType::B,
+            days: vec![
+                DailyKey {
+                    day: 1,
+                    rotors: vec![RotorType::II, RotorType::IV, RotorType::V],
+                    rings: vec![2, 21, 12],
+                    plugs: vec![['A', 'V'], ['B', 'S'], ['C', 'G'], ['D', 'L'], ['F', 'U'], ['H', 'Z'], ['I', 'N'], ['K', 'M'], ['O', 'W'], ['R', 'X']],
+                    kenngruppen: vec!["UGZ".to_string(), "DFK".to_string(), "SOT".to_string(), "PLQ".to_string()],
+                },
+                DailyKey {
+                    day: 2,
+                    rotors: vec![RotorType::I, RotorType::V, RotorType::III],
+                    rings: vec![14, 9, 24],
+                    plugs: vec![['A', 'J'], ['B', 'P'], ['C', 'Z'], ['D', 'W'], ['E', 'Q'], ['F', 'L'], ['G', 'O'], ['H', 'S'], ['K', 'Y'], ['M', 'T']],
+                    kenngruppen: vec!["NRO".to_string(), "EBA".to_string(), "WIK".to_string(), "YMC".to_string()],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_key_sheet_generate() {
+        let sheet = KeySheet::generate(&mut StdRng::seed_from_u64(1941), ReflectorType::B, &ROTORS, 31).unwrap();
+
+        assert_eq!(31, sheet.days.len());
+        assert_eq!(sheet, KeySheet::generate(&mut StdRng::seed_from_u64(1941), ReflectorType::B, &ROTORS, 31).unwrap());
+        assert_ne!(sheet, KeySheet::generate(&mut StdRng::seed_from_u64(1942), ReflectorType::B, &ROTORS, 31).unwrap());
+
+        for (i, day) in sheet.days.iter().enumerate() {
+            assert_eq!(i as u32 + 1, day.day);
+            assert_eq!(3, day.rotors.len());
+            assert!(day.rings.iter().all(|r| (1..=26).contains(r)));
+
+            assert_eq!(10, day.plugs.len());
+            assert!(day.plugs.iter().all(|p| (p[0] as i32 - p[1] as i32).abs() != 1));
+
+            let mut letters: Vec<char> = day.plugs.iter().flatten().cloned().collect();
+            letters.sort();
+            letters.dedup();
+            assert_eq!(20, letters.len());
+
+            if i > 0 {
+                assert!(sheet.days[i - 1].rotors.iter().zip(&day.rotors).all(|(a, b)| a != b));
+            }
+
+            assert!(day.get_settings(sheet.reflector, "AAA").build().is_ok());
+        }
+
+        let mut kenngruppen: Vec<&String> = sheet.days.iter().flat_map(|d| d.kenngruppen.iter()).collect();
+        kenngruppen.sort();
+        kenngruppen.dedup();
+        assert_eq!(31 * 4, kenngruppen.len());
+
+        // With only three rotors they still move every day.
+        let sheet = KeySheet::generate(&mut StdRng::seed_from_u64(1941), ReflectorType::B, &ROTORS[..3], 31).unwrap();
+        assert!(sheet.days.windows(2).all(|w| w[0].rotors.iter().zip(&w[1].rotors).all(|(a, b)| a != b)));
+    }
+
+    #[test]
+    fn test_key_sheet_generate_invalid() {
+        let mut rng = StdRng::seed_from_u64(1941);
+
+        assert!(KeySheet::generate(&mut rng, ReflectorType::B, &ROTORS[..2], 31).is_err());
+        assert!(KeySheet::generate(&mut rng, ReflectorType::B, &[RotorType::I, RotorType::II, RotorType::I], 31).is_err());
+        assert!(KeySheet::generate(&mut rng, ReflectorType::B, &ROTORS, 0).is_err());
+        assert!(KeySheet::generate(&mut rng, ReflectorType::B, &ROTORS, 32).is_err());
+    }
+
+    #[test]
+    fn test_key_sheet_output() {
+        let sheet = get_sheet();
+
+        let text = sheet.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!("Umkehrwalze B", lines[0]);
+        assert_eq!("2    | I V III      | 14 09 24     | AJ BP CZ DW EQ FL GO HS KY MT | NRO EBA WIK YMC", lines[2]);
+        assert_eq!("1    | II IV V      | 02 21 12     | AV BS CG DL FU HZ IN KM OW RX | UGZ DFK SOT PLQ", lines[3]);
+
+        let csv = sheet.to_csv();
+        assert_eq!("day,reflector,rotors,rings,plugs,kenngruppen,network,month", csv.lines().next().unwrap());
+        assert_eq!("1,B,II IV V,02 21 12,AV BS CG DL FU HZ IN KM OW RX,UGZ DFK SOT PLQ,,", csv.lines().nth(1).unwrap());
+
+        let json: serde_json::Value = serde_json::from_str(&sheet.to_json().unwrap()).unwrap();
+        assert_eq!("B", json["reflector"]);
+        assert_eq!("V", json["days"][1]["rotors"][1]);
+        assert_eq!(21, json["days"][0]["rings"][1]);
+        assert_eq!("AV", json["days"][0]["plugs"][0]);
+        assert_eq!("PLQ", json["days"][0]["kenngruppen"][3]);
+
+        assert_eq!(Some(&sheet.days[1]), sheet.get_day(2));
+        assert_eq!(None, sheet.get_day(3));
+    }
+
+    #[test]
+    fn test_key_sheet_import() {
+        let mut sheet = get_sheet();
+
+        assert_eq!(sheet, KeySheet::from_csv(&sheet.to_csv()).unwrap());
+        assert_eq!(sheet, KeySheet::from_text(&sheet.to_string()).unwrap());
+        assert_eq!(sheet, KeySheet::from_json(&sheet.to_json().unwrap()).unwrap());
+
+        sheet.network = Some("Red".to_string());
+        sheet.month = Some("1941-06".to_string());
+
+        assert_eq!(sheet, KeySheet::from_csv(&sheet.to_csv()).unwrap());
+        assert_eq!(sheet, KeySheet::from_text(&sheet.to_string()).unwrap());
+        assert_eq!(sheet, KeySheet::from_json(&sheet.to_json().unwrap()).unwrap());
+
+        // Transcribed by hand, with the columns not lined up.
+        let text = "Umkehrwalze B\n\
+                    Tag | Walzenlage | Ringstellung | Steckerverbindungen | Kenngruppen\n\
+                    2 | I V III | 14 09 24 | AJ BP CZ DW EQ FL GO HS KY MT | NRO EBA WIK YMC\n\
+                    1 | II IV V | 02 21 12 | AV BS CG DL FU HZ IN KM OW RX | UGZ DFK SOT PLQ";
+
+        assert_eq!(get_sheet(), KeySheet::from_text(text).unwrap());
+
+        let generated = KeySheet::generate(&mut StdRng::seed_from_u64(1941), ReflectorType::C, &ROTORS, 31).unwrap();
+        assert_eq!(generated, KeySheet::from_csv(&generated.to_csv()).unwrap());
+    }
+
+    #[test]
+    fn test_key_sheet_import_invalid() {
+        let csv = get_sheet().to_csv();
+
+        assert!(KeySheet::from_csv("").is_err());
+        assert!(KeySheet::from_csv("day,reflector,rotors,rings,plugs,kenngruppen,network,month\n").is_err());
+        assert!(KeySheet::from_csv(&csv.replace("II IV V", "II IV X")).is_err());
+        assert!(KeySheet::from_csv(&csv.replace("02 21 12", "02 27 12")).is_err());
+        assert!(KeySheet::from_csv(&csv.replace("02 21 12", "02 21")).is_err());
+        assert!(KeySheet::from_csv(&csv.replace("II IV V", "II IV II")).is_err());
+        assert!(KeySheet::from_csv(&csv.replace("II IV V,02 21 12", "II IV,02 21")).is_err());
+        assert!(KeySheet::from_csv(&csv.replace("AV BS", "AV AS")).is_err());
+        assert!(KeySheet::from_csv(&csv.replace("UGZ", "UG")).is_err());
+        assert!(KeySheet::from_csv(&csv.replace("2,B", "1,B")).is_err());
+        assert!(KeySheet::from_csv(&csv.replace("2,B", "2,C")).is_err());
+        assert!(KeySheet::from_csv(&csv.replace("PLQ,,", "PLQ,Red,1941-06,")).is_err());
+        assert!(KeySheet::from_csv(&csv.replace("PLQ,,", "PLQ,")).is_err());
+        assert!(KeySheet::from_text("Umkehrwalze B\n1 | II IV V | 02 21 12").is_err());
+        assert!(KeySheet::from_json("{ \"reflector\": \"B\", \"month\": \"June\", \"days\": [] }").is_err());
+        assert!(KeySheet::load("sheet.toml").is_err());
+    }
+
+    #[test]
+    fn test_key_sheet_lookup() {
+        let mut red = get_sheet();
+        red.network = Some("Red".to_string());
+        red.month = Some("1941-06".to_string());
+
+        assert_eq!(1, red.get_date("1941-06-01").unwrap().day);
+        assert_eq!(2, red.get_date("1941-06-02").unwrap().day);
+        assert!(red.get_date("1941-06-03").is_err());
+        assert!(red.get_date("1941-07-01").is_err());
+        assert!(red.get_date("01/06/1941").is_err());
+
+        // Without a month the date can't be told apart from any other month.
+        assert!(get_sheet().get_date("1944-12-02").is_err());
+
+        assert_eq!(Some(&red.days[1]), red.find_kenngruppe("WIK"));
+        assert_eq!(None, red.find_kenngruppe("ABC"));
+
+        let blue = KeySheet::generate(&mut StdRng::seed_from_u64(1941), ReflectorType::B, &ROTORS, 31).unwrap();
+        let kenngruppe = blue.days[10].kenngruppen[2].clone();
+        let sheets = [red, blue];
+
+        let (sheet, day) = KeySheet::find_kenngruppe_in(&sheets, &kenngruppe).unwrap();
+        assert_eq!(None, sheet.network);
+        assert_eq!(11, day.day);
+
+        let (sheet, day) = KeySheet::find_kenngruppe_in(&sheets, "UGZ").unwrap();
+        assert_eq!(Some("Red".to_string()), sheet.network);
+        assert_eq!(1, day.day);
+    }
+}
@@ -1,89 +1,657 @@
 mod rotor;
 mod reflector;
+mod greek_wheel;
+mod entry_wheel;
 mod plugboard;
+mod model;
+mod catalog;
+mod settings;
+mod stream;
+mod plaintext;
+mod traffic;
+mod bigram;
+mod procedure;
+mod key_sheet;
 pub mod utils;
 mod error;
 
 use anyhow::{Context, Result};
+use std::collections::HashMap;
 
 pub use crate::rotor::Rotor;
 pub use crate::rotor::RotorType;
 pub use crate::reflector::Reflector;
 pub use crate::reflector::ReflectorType;
+pub use crate::reflector::ReflectorNotation;
+pub use crate::greek_wheel::GreekWheel;
+pub use crate::greek_wheel::GreekWheelType;
+pub use crate::entry_wheel::EntryWheel;
+pub use crate::entry_wheel::EntryWheelType;
 pub use crate::plugboard::Plugboard;
+pub use crate::model::MachineModel;
+pub use crate::catalog::{Catalog, CatalogRotor, CatalogReflector, CatalogEntryWheel, CatalogModel};
+pub use crate::settings::MachineSettings;
+pub use crate::stream::{EnigmaReader, EnigmaWriter};
+pub use crate::plaintext::PlaintextConvention;
+pub use crate::traffic::{GroupFormat, Message};
+pub use crate::bigram::BigramTable;
+pub use crate::procedure::{DoubledIndicator, ChosenIndicator, NavalIndicator, ReceivedMessage};
+pub use crate::key_sheet::{KeySheet, DailyKey};
 pub use crate::error::*;
 
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SteppingMode {
+    // The pawl and ratchet mechanism of the military machines, including the double step of the middle rotor.
+    DoubleStep,
+    // Simplified stepping where a rotor only moves when the rotor to its right turns over.
+    Odometer,
+}
+
+// What to do with input other than upper case letters. Whichever is chosen, whitespace and anything else that is
+// passed through never moves the rotors.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InputPolicy {
+    // Only upper case letters and whitespace are accepted, anything else is an error.
+    #[default]
+    Error,
+    // Lower case letters are encrypted as upper case, whitespace is passed through and anything else is an error.
+    Fold,
+    // Letters of either case are encrypted and everything else, including whitespace, is dropped.
+    Strip,
+    // Letters of either case are encrypted and everything else is copied to the output unchanged.
+    PassThrough,
+}
+
+// The positions of everything that moves while typing, which is all that changes as a message is entered. The greek
+// wheel is never stepped so is left out.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MachineState {
+    rotors: Vec<usize>,
+    reflector: usize,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "EnigmaFields"))]
 pub struct Enigma {
+    entry_wheel: EntryWheel,
     rotors: Vec<Rotor>,
+    greek_wheel: Option<GreekWheel>,
     reflector: Reflector,
     plugboard: Plugboard,
+    stepping_mode: SteppingMode,
+    input_policy: InputPolicy,
+    // Give each encrypted letter the case of the letter typed, rather than always upper case.
+    preserve_case: bool,
+    // Where the rotors were set and how many keys have been pressed since, so rewinding can replay the exact steps.
+    start: MachineState,
+    presses: usize,
 }
 
 impl Enigma {
     pub fn new(reflector_type: ReflectorType, rotors: Vec<Rotor>, plugboard: Plugboard) -> Enigma {
-        Enigma {
+        Enigma::from_parts(EntryWheel::new(EntryWheelType::Military), rotors, Reflector::new(reflector_type), plugboard, SteppingMode::DoubleStep)
+    }
+
+    // Create a machine with a reflector that is already set up, such as a rewired UKW-D or one from a catalog.
+    pub fn new_with_reflector(reflector: Reflector, rotors: Vec<Rotor>, plugboard: Plugboard) -> Enigma {
+        Enigma::from_parts(EntryWheel::new(EntryWheelType::Military), rotors, reflector, plugboard, SteppingMode::DoubleStep)
+    }
+
+    // Create a four rotor Kriegsmarine M4. The greek wheel sits to the left of the three stepping rotors and only
+    // fits alongside one of the thin reflectors.
+    pub fn new_m4(reflector_type: ReflectorType, greek_wheel: GreekWheel, rotors: Vec<Rotor>, plugboard: Plugboard) -> Result<Enigma> {
+        if reflector_type != ReflectorType::ThinB && reflector_type != ReflectorType::ThinC {
+            return Err(Error::ReflectorError).with_context(|| { format!("Invalid reflector {:?} for the M4. Must be ThinB or ThinC.", reflector_type) });
+        }
+
+        if rotors.len() != 3 {
+            return Err(Error::RotorError).with_context(|| { format!("Invalid number of rotors {} for the M4. Must be 3 alongside the greek wheel.", rotors.len()) });
+        }
+
+        let mut enigma = Enigma::new(reflector_type, rotors, plugboard);
+        enigma.greek_wheel = Some(greek_wheel);
+
+        Ok(enigma)
+    }
+
+    // Create the machine for a particular model, rejecting any components the model could not be fitted with.
+    pub fn new_with_model(model: MachineModel, reflector: Reflector, greek_wheel: Option<GreekWheel>, rotors: Vec<Rotor>, plugboard: Plugboard) -> Result<Enigma> {
+        model.validate(&reflector, &greek_wheel, &rotors, &plugboard)?;
+
+        let mut enigma = Enigma::from_parts(EntryWheel::new(model.get_entry_wheel_type()), rotors, reflector, plugboard, model.get_stepping_mode());
+        enigma.greek_wheel = greek_wheel;
+
+        Ok(enigma)
+    }
+
+    pub(crate) fn from_parts(entry_wheel: EntryWheel, rotors: Vec<Rotor>, reflector: Reflector, plugboard: Plugboard, stepping_mode: SteppingMode) -> Enigma {
+        let mut enigma = Enigma {
+            entry_wheel,
             rotors,
-            reflector: Reflector::new(reflector_type),
+            greek_wheel: None,
+            reflector,
             plugboard,
-        }
+            stepping_mode,
+            input_policy: InputPolicy::Error,
+            preserve_case: false,
+            start: MachineState::default(),
+            presses: 0,
+        };
+
+        enigma.start = enigma.snapshot();
+        enigma
+    }
+
+    // Swap the reflector, as when the UKW-D is rewired in the field.
+    pub fn set_reflector(&mut self, reflector: Reflector) {
+        self.reflector = reflector;
+        self.start = self.snapshot();
+        self.presses = 0;
+    }
+
+    pub fn set_entry_wheel(&mut self, entry_wheel: EntryWheel) {
+        self.entry_wheel = entry_wheel;
+        self.start = self.snapshot();
+        self.presses = 0;
+    }
+
+    // Change how the rotors step. The keys pressed so far stepped the old way, so the rewind history starts again here.
+    pub fn set_stepping_mode(&mut self, stepping_mode: SteppingMode) {
+        self.stepping_mode = stepping_mode;
+        self.start = self.snapshot();
+        self.presses = 0;
+    }
+
+    pub fn set_input_policy(&mut self, input_policy: InputPolicy, preserve_case: bool) {
+        self.input_policy = input_policy;
+        self.preserve_case = preserve_case;
+    }
+
+    pub fn get_input_policy_from_string(input_policy: &str) -> Result<InputPolicy> {
+        let lower = input_policy.to_ascii_lowercase();
+
+        let p = match lower.as_str() {
+            "error" => InputPolicy::Error,
+            "fold" => InputPolicy::Fold,
+            "strip" => InputPolicy::Strip,
+            "passthrough" => InputPolicy::PassThrough,
+            _ => return Err(Error::InputError).with_context(|| { format!("Invalid input policy {}.", input_policy) }),
+        };
+
+        Ok(p)
+    }
+
+    pub fn get_stepping_mode_from_string(stepping_mode: &str) -> Result<SteppingMode> {
+        let lower = stepping_mode.to_ascii_lowercase();
+
+        let m = match lower.as_str() {
+            "doublestep" => SteppingMode::DoubleStep,
+            "odometer" => SteppingMode::Odometer,
+            _ => return Err(Error::InputError).with_context(|| { format!("Invalid stepping mode {}.", stepping_mode) }),
+        };
+
+        Ok(m)
     }
 
     pub fn encrypt(&mut self, input: &str) -> Result<String> {
-        if !input.is_ascii() {
-            return Err(Error::InputError).with_context(|| format!("Input is not upper case ASCII: {}", input))?;
+        // Check everything first, so the rotors are only moved once all the input is known to be acceptable.
+        if let Some(c) = input.chars().find(|c| !self.accepts(*c)) {
+            return self.reject(c);
         }
 
         let mut result = String::with_capacity(input.len());
 
         for c in input.chars() {
-            if c.is_ascii_whitespace() {
-                result.push(c);
+            if !c.is_ascii_alphabetic() {
+                if self.input_policy != InputPolicy::Strip {
+                    result.push(c);
+                }
+
                 continue
             }
 
-            // Move the right hand rotor 1 position, and subsequent rotors if they are in the correct position.
-            let mut iter = self.rotors.iter_mut().rev();
+            result.push(self.encrypt_letter(c as u8) as char);
+        }
 
-            while let Some(r) = iter.next() {
-                if !r.step(){
-                    break;
-                }
+        Ok(result)
+    }
+
+    // Return true if the input policy lets the character in, whether it is then encrypted, passed through or stripped.
+    fn accepts(&self, c: char) -> bool {
+        match self.input_policy {
+            InputPolicy::Error => c.is_ascii_uppercase() || c.is_ascii_whitespace(),
+            InputPolicy::Fold => c.is_ascii_alphabetic() || c.is_ascii_whitespace(),
+            InputPolicy::Strip | InputPolicy::PassThrough => true,
+        }
+    }
+
+    // The error for a character the input policy turns away.
+    fn reject<T>(&self, c: char) -> Result<T> {
+        Err(Error::InputError).with_context(|| format!("Invalid input {:?}. It is not accepted by the {:?} input policy.", c, self.input_policy))
+    }
+
+    // Encrypt a single ASCII letter. Lower case letters are encrypted as upper case unless the input policy is Error, and
+    // keep their case if preserve_case is set. Anything else is returned as it is without pressing a key, so stripping
+    // or rejecting it is up to the caller.
+    pub fn encrypt_letter(&mut self, letter: u8) -> u8 {
+        let upper = letter.to_ascii_uppercase();
+
+        if !upper.is_ascii_uppercase() || (upper != letter && self.input_policy == InputPolicy::Error) {
+            return letter;
+        }
+
+        let output = b'A' + self.encrypt_position((upper - b'A') as usize) as u8;
+
+        match self.preserve_case && upper != letter {
+            true => output.to_ascii_lowercase(),
+            false => output,
+        }
+    }
+
+    // Encrypt the buffer in place following the input policy and return how much of it holds the result. Everything is
+    // checked first, so rejected input leaves the rotors where they were. Stripped characters are dropped by moving the
+    // rest up to the front of the buffer, and anything else that isn't a letter stays where it is.
+    pub fn encrypt_in_place(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        if let Some(i) = buffer.iter().position(|b| !self.accepts(*b as char)) {
+            // Report the character the rejected byte starts, rather than the byte on its own.
+            let rest = &buffer[i..buffer.len().min(i + 4)];
+            return self.reject(String::from_utf8_lossy(rest).chars().next().unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+
+        let mut length = 0;
+
+        for i in 0..buffer.len() {
+            let b = buffer[i];
+
+            if !b.is_ascii_alphabetic() && self.input_policy == InputPolicy::Strip {
+                continue;
             }
 
-            let mut pos = utils::get_position_from_char(c)?;
+            buffer[length] = self.encrypt_letter(b);
+            length += 1;
+        }
 
-            pos = self.plugboard.scramble(pos);
-            pos = self.rotor_scramble(pos);
-            pos = self.plugboard.scramble(pos);
+        Ok(length)
+    }
 
-            result.push(utils::get_char_from_position(pos)?);
+    // Encrypt characters as they are taken from the iterator, following the input policy. A character the policy
+    // rejects comes out as an error, and the characters before it have already been encrypted.
+    pub fn encrypt_chars<I: Iterator<Item = char>>(&mut self, chars: I) -> EncryptChars<'_, I> {
+        EncryptChars {
+            enigma: self,
+            chars,
         }
+    }
 
-        Ok(result)
+    // Press the key for the letter at a position and return the position of the lamp that lights up.
+    fn encrypt_position(&mut self, position: usize) -> usize {
+        self.step_rotors();
+        self.presses += 1;
+
+        let mut pos = self.plugboard.scramble_left(position);
+        pos = self.entry_wheel.scramble_left(pos);
+        pos = self.rotor_scramble(pos);
+        pos = self.entry_wheel.scramble_right(pos);
+        self.plugboard.scramble_right(pos)
+    }
+
+    fn step_rotors(&mut self) {
+        match self.stepping_mode {
+            SteppingMode::Odometer => {
+                // Move the right hand rotor 1 position, and subsequent rotors if the rotor to their right turned over.
+                let mut turnover = true;
+
+                for r in self.rotors.iter_mut().rev() {
+                    turnover = r.step();
+
+                    if !turnover {
+                        break;
+                    }
+                }
+
+                // A movable reflector is geared to the left hand rotor just like another rotor.
+                if turnover && self.reflector.is_movable() {
+                    self.reflector.step();
+                }
+            },
+            SteppingMode::DoubleStep => {
+                // Each pawl pushes the rotor in front of it and, when it drops into the notch of the rotor to its right,
+                // that rotor as well. A rotor sitting on its notch is therefore pushed along with the rotor to its
+                // left, which is what makes the middle rotor step twice in a row.
+                // Working from left to right means every decision is made on the positions before the key press.
+                let count = self.rotors.len();
+
+                for i in 0..count {
+                    let step = i == count - 1
+                        || self.rotors[i + 1].is_at_notch()
+                        || (i > 0 && self.rotors[i].is_at_notch());
+
+                    if step {
+                        self.rotors[i].step();
+                    }
+                }
+            },
+        }
     }
 
-    fn rotor_scramble(&mut self, input: usize) -> usize {
+    fn rotor_scramble(&self, input: usize) -> usize {
         let mut result = input;
 
         // Scramble from right to left.
-        let mut rev_iter = self.rotors.iter_mut().rev();
-        while let Some(rotor) = rev_iter.next() {
+        for rotor in self.rotors.iter().rev() {
             result = rotor.scramble_left(result);
         }
 
+        if let Some(greek_wheel) = &self.greek_wheel {
+            result = greek_wheel.scramble_left(result);
+        }
+
         result = self.reflector.scramble(result);
 
         // Scramble from left to right.
-        let mut iter = self.rotors.iter_mut();
-        while let Some(rotor) = iter.next() {
+        if let Some(greek_wheel) = &self.greek_wheel {
+            result = greek_wheel.scramble_right(result);
+        }
+
+        for rotor in self.rotors.iter() {
             result = rotor.scramble_right(result);
         }
 
         result
     }
 
+    pub fn snapshot(&self) -> MachineState {
+        MachineState {
+            rotors: self.rotors.iter().map(|r| r.get_position()).collect(),
+            reflector: self.reflector.get_position(),
+        }
+    }
+
+    pub fn restore(&mut self, state: &MachineState) -> Result<()> {
+        self.check_state(state)?;
+        self.set_state(state);
+        self.start = state.clone();
+        self.presses = 0;
+
+        Ok(())
+    }
+
+    fn check_state(&self, state: &MachineState) -> Result<()> {
+        if state.rotors.len() != self.rotors.len() {
+            return Err(Error::RotorError).with_context(|| { format!("Cannot restore {} rotor positions to a machine with {} rotors.", state.rotors.len(), self.rotors.len()) });
+        }
+
+        if state.rotors.iter().chain(std::iter::once(&state.reflector)).any(|p| *p > 25) {
+            return Err(Error::RotorError).with_context(|| { format!("Invalid machine state {:?}.", state) });
+        }
+
+        Ok(())
+    }
+
+    fn set_state(&mut self, state: &MachineState) {
+        for (rotor, position) in self.rotors.iter_mut().zip(&state.rotors) {
+            rotor.set_position(*position);
+        }
+
+        self.reflector.set_position(state.reflector);
+    }
+
+    // Move the rotors to where they would be offset key presses after they were set, without stepping through each one.
+    //
+    // A rotor's position follows from how many times it has stepped, and that is counted straight from the rotor to its
+    // right: every notch that rotor steps off pushes this one along. Under the double step a middle rotor also steps off
+    // its own notch on the key press after landing on it, so how far it has got also depends on when the last push came,
+    // which is worked out the same way from the rotors to its right. The work grows with the number of rotors but not
+    // with the offset.
+    //
+    // A push that comes while a middle rotor is already stepping off its notch is lost. That can only happen on the first
+    // key press unless a middle rotor, or the rotor to its right, has notches on neighbouring positions, and those
+    // machines are moved round the loop their rotors settle into instead.
+    pub fn seek(&mut self, offset: usize) {
+        let start = self.start.clone();
+        self.set_state(&start);
+        self.presses = offset;
+
+        let count = self.rotors.len();
+
+        if count == 0 {
+            return;
+        }
+
+        let loses_pushes = self.stepping_mode == SteppingMode::DoubleStep
+            && (1..count - 1).any(|i| self.rotors[i].has_adjacent_notches() || self.rotors[i + 1].has_adjacent_notches());
+
+        if loses_pushes {
+            self.seek_loop(offset);
+            return;
+        }
+
+        // A movable reflector is pushed by the left hand rotor just like another rotor.
+        if self.stepping_mode == SteppingMode::Odometer && self.reflector.is_movable() {
+            let pushes = self.rotors[0].count_steps(start.rotors[0], self.steps_taken(0, offset), true);
+            self.reflector.set_position((start.reflector + pushes) % 26);
+        }
+
+        for i in 0..count {
+            let steps = self.steps_taken(i, offset);
+            self.rotors[i].set_position((start.rotors[i] + steps) % 26);
+        }
+    }
+
+    // Move the rotors offset key presses on when pushes can be lost on any key press. Which ones are lost depends on where
+    // every rotor but the left hand one is, and there are only so many places they can be, so within 26 to the power of
+    // their number key presses they come back to where they have been before and go round the same loop from then on.
+    // They are stepped until that happens and the rest of the offset is covered a whole number of loops at a time. The
+    // left hand rotor never pushes another rotor, so it only has to be counted.
+    fn seek_loop(&mut self, offset: usize) {
+        let from = self.start.rotors[0];
+        let mut seen: HashMap<Vec<usize>, usize> = HashMap::new();
+
+        // Where the other rotors were before each key press, and how many times the left hand rotor had stepped by then.
+        let mut positions: Vec<Vec<usize>> = Vec::new();
+        let mut steps = vec![0];
+
+        for press in 0..offset {
+            let current: Vec<usize> = self.rotors[1..].iter().map(|r| r.get_position()).collect();
+
+            if let Some(&first) = seen.get(&current) {
+                let length = press - first;
+                let loops = (offset - press) / length;
+                let rest = (offset - press) % length;
+
+                for (rotor, position) in self.rotors[1..].iter_mut().zip(&positions[first + rest]) {
+                    rotor.set_position(*position);
+                }
+
+                let left = steps[press] + loops * (steps[press] - steps[first]) + steps[first + rest] - steps[first];
+                self.rotors[0].set_position((from + left) % 26);
+
+                return;
+            }
+
+            seen.insert(current.clone(), press);
+            positions.push(current);
+            steps.push(steps[press] + if self.rotors[1].is_at_notch() { 1 } else { 0 });
+
+            self.step_rotors();
+        }
+    }
+
+    // How many times rotor i steps in the first presses key presses after the rotors were set.
+    fn steps_taken(&self, i: usize, presses: usize) -> usize {
+        if i == self.rotors.len() - 1 {
+            return presses;
+        }
+
+        let right = &self.rotors[i + 1];
+        let pushes = right.count_steps(self.start.rotors[i + 1], self.steps_taken(i + 1, presses), true);
+
+        if self.stepping_mode == SteppingMode::Odometer || i == 0 {
+            return pushes;
+        }
+
+        let rotor = &self.rotors[i];
+        let from = self.start.rotors[i];
+        let lost = if self.first_push_lost(i) && presses > 0 { 1 } else { 0 };
+
+        // Without a push the rotor only steps off the notch it started on.
+        if pushes == lost {
+            return presses.min(if rotor.is_notch(from) { 1 } else { 0 });
+        }
+
+        // Each push moves the rotor off a position without a notch. If the last one left it on a notch it steps again on
+        // the next key press.
+        let step = rotor.find_step(from, pushes - lost, false);
+        let pushed = self.push_time(i + 1, pushes);
+
+        step + 1 + if rotor.is_notch(from + step + 1) && pushed + 1 < presses { 1 } else { 0 }
+    }
+
+    // The key press, counting from 0, on which rotor i takes its step numbered step, also counting from 0.
+    fn step_time(&self, i: usize, step: usize) -> usize {
+        // The right hand rotor steps on every key press.
+        if i == self.rotors.len() - 1 {
+            return step;
+        }
+
+        let rotor = &self.rotors[i];
+        let from = self.start.rotors[i];
+
+        // A middle rotor steps off a notch on the key press after it got there.
+        if rotor.is_notch(from + step) {
+            return if step == 0 { 0 } else { self.step_time(i, step - 1) + 1 };
+        }
+
+        let lost = if self.first_push_lost(i) { 1 } else { 0 };
+
+        self.push_time(i + 1, rotor.count_steps(from, step + 1, false) + lost)
+    }
+
+    // The key press, counting from 0, on which rotor i pushes the rotor to its left for the nth time, counting from 1.
+    fn push_time(&self, i: usize, nth: usize) -> usize {
+        self.step_time(i, self.rotors[i].find_step(self.start.rotors[i], nth, true))
+    }
+
+    // Return true if middle rotor i and the rotor to its right both start on a notch. The first key press then steps the
+    // middle rotor off its notch and the push it gets at the same time is lost.
+    fn first_push_lost(&self, i: usize) -> bool {
+        self.rotors[i].is_notch(self.start.rotors[i]) && self.rotors[i + 1].is_notch(self.start.rotors[i + 1])
+    }
+
+    // Step the rotors backwards by count key presses, as if the last count characters had never been typed.
+    pub fn rewind(&mut self, count: usize) -> Result<()> {
+        // The double step means two positions can step to the same place, so while the keys pressed since the rotors
+        // were set are known they are replayed rather than guessed at.
+        if count <= self.presses {
+            self.seek(self.presses - count);
+            return Ok(());
+        }
+
+        let current = self.snapshot();
+        let presses = self.presses;
+        let count = count - presses;
+        let start = self.start.clone();
+
+        self.restore(&start)?;
+
+        // Before the rotors were set any chain of positions stepping into the start will do. The double step means some
+        // positions can't be stepped into at all, so the search backs up and tries another branch when it reaches one.
+        // The fewest steps back each dead end was found at is kept, as a position that fails once fails again with as
+        // many or more steps still to go.
+        let mut path: Vec<MachineState> = Vec::new();
+        let mut branches: Vec<Vec<MachineState>> = vec![self.get_previous_states()];
+        let mut dead_ends: HashMap<MachineState, usize> = HashMap::new();
+
+        while path.len() < count {
+            let remaining = count - path.len() - 1;
+
+            match branches.last_mut().and_then(|b| b.pop()) {
+                Some(state) => {
+                    if dead_ends.get(&state).is_some_and(|r| remaining >= *r) {
+                        continue;
+                    }
+
+                    self.restore(&state)?;
+                    branches.push(self.get_previous_states());
+                    path.push(state);
+                },
+                None => {
+                    branches.pop();
+
+                    match path.pop() {
+                        Some(state) => {
+                            dead_ends.insert(state, remaining + 1);
+                        },
+                        None => {
+                            let keys = self.get_keys();
+
+                            // Leave the machine where it was.
+                            self.set_state(&current);
+                            self.start = start;
+                            self.presses = presses;
+
+                            return Err(Error::RotorError).with_context(|| { format!("Cannot rewind {} steps before {:?}, no sequence of positions steps to it.", count, keys) });
+                        },
+                    }
+                },
+            }
+        }
+
+        self.restore(&path[count - 1])
+    }
+
+    // Find every state that steps into the current one. Each key press moves a wheel by at most one position and
+    // always moves the right hand rotor, so only those combinations need trying. They are returned with the one moving
+    // the fewest wheels last, so it is the first taken off the end.
+    fn get_previous_states(&mut self) -> Vec<MachineState> {
+        let current = self.snapshot();
+
+        if self.rotors.is_empty() {
+            return vec![current];
+        }
+
+        let moving = self.rotors.len() - 1 + if self.reflector.is_movable() { 1 } else { 0 };
+        let mut previous: Vec<MachineState> = Vec::new();
+
+        for moved in 0..(1usize << moving) {
+            let mut candidate = current.clone();
+            let last = candidate.rotors.len() - 1;
+
+            candidate.rotors[last] = (candidate.rotors[last] + 25) % 26;
+
+            for i in 0..moving {
+                if moved & (1 << i) != 0 {
+                    if i < last {
+                        candidate.rotors[i] = (candidate.rotors[i] + 25) % 26;
+                    }
+                    else {
+                        candidate.reflector = (candidate.reflector + 25) % 26;
+                    }
+                }
+            }
+
+            self.restore(&candidate).unwrap();
+            self.step_rotors();
+
+            if self.snapshot() == current {
+                previous.push(candidate);
+            }
+        }
+
+        self.restore(&current).unwrap();
+        previous.reverse();
+
+        previous
+    }
+
     pub fn get_keys(&self) -> Vec<char> {
-        let mut v: Vec<char> = Vec::with_capacity(self.rotors.len());
+        let mut v: Vec<char> = Vec::with_capacity(self.rotors.len() + 1);
+
+        if let Some(greek_wheel) = &self.greek_wheel {
+            v.push(greek_wheel.get_key());
+        }
 
         for r in &self.rotors {
             v.push(r.get_key());
@@ -91,11 +659,120 @@ impl Enigma {
 
         v
     }
+
+    // Turn the rotors to a new key, such as a message key, in the same order as get_keys returns them. The rewind history
+    // starts again from the new key.
+    pub fn set_keys(&mut self, keys: &str) -> Result<()> {
+        let count = self.rotors.len() + self.greek_wheel.iter().count();
+
+        if keys.chars().count() != count {
+            return Err(Error::InputError).with_context(|| { format!("Invalid key {}. Expected {} letters for the rotors.", keys, count) });
+        }
+
+        let positions = keys.chars().map(utils::get_position_from_char).collect::<Result<Vec<usize>>>()
+            .with_context(|| { format!("Invalid key {}.", keys) })?;
+
+        let (greek, rotors) = positions.split_at(count - self.rotors.len());
+
+        if let (Some(greek_wheel), Some(position)) = (&mut self.greek_wheel, greek.first()) {
+            greek_wheel.set_position(*position);
+        }
+
+        let state = MachineState {
+            rotors: rotors.to_vec(),
+            reflector: self.reflector.get_position(),
+        };
+
+        self.restore(&state)
+    }
+
+    pub fn get_reflector_key(&self) -> char {
+        self.reflector.get_key()
+    }
+}
+
+// The fields of a machine as they are read back. Each part checks itself, which leaves the position the rewind history
+// starts from to be checked against the rotors.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct EnigmaFields {
+    entry_wheel: EntryWheel,
+    rotors: Vec<Rotor>,
+    greek_wheel: Option<GreekWheel>,
+    reflector: Reflector,
+    plugboard: Plugboard,
+    stepping_mode: SteppingMode,
+    input_policy: InputPolicy,
+    preserve_case: bool,
+    start: MachineState,
+    presses: usize,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<EnigmaFields> for Enigma {
+    type Error = anyhow::Error;
+
+    fn try_from(fields: EnigmaFields) -> Result<Enigma> {
+        let enigma = Enigma {
+            entry_wheel: fields.entry_wheel,
+            rotors: fields.rotors,
+            greek_wheel: fields.greek_wheel,
+            reflector: fields.reflector,
+            plugboard: fields.plugboard,
+            stepping_mode: fields.stepping_mode,
+            input_policy: fields.input_policy,
+            preserve_case: fields.preserve_case,
+            start: fields.start,
+            presses: fields.presses,
+        };
+
+        enigma.check_state(&enigma.start)?;
+
+        Ok(enigma)
+    }
+}
+
+pub struct EncryptChars<'a, I: Iterator<Item = char>> {
+    enigma: &'a mut Enigma,
+    chars: I,
+}
+
+impl<I: Iterator<Item = char>> Iterator for EncryptChars<'_, I> {
+    type Item = Result<char>;
+
+    fn next(&mut self) -> Option<Result<char>> {
+        loop {
+            let c = self.chars.next()?;
+
+            if !self.enigma.accepts(c) {
+                return Some(self.enigma.reject(c));
+            }
+
+            if c.is_ascii_alphabetic() {
+                return Some(Ok(self.enigma.encrypt_letter(c as u8) as char));
+            }
+
+            if self.enigma.input_policy != InputPolicy::Strip {
+                return Some(Ok(c));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.chars.size_hint();
+
+        match self.enigma.input_policy {
+            InputPolicy::Strip => (0, upper),
+            _ => (lower, upper),
+        }
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::vec_init_then_push, clippy::useless_vec)]
 mod test {
     use super::*;
+    use std::collections::HashSet;
     use crate::rotor::Rotor;
     use crate::rotor::RotorType;
 
@@ -207,4 +884,867 @@ mod test {
 
         assert_eq!(&input, &output);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_enigma_double_step() {
+        let rotors = vec![
+            Rotor::new(RotorType::I, 'A', 1).unwrap(),
+            Rotor::new(RotorType::II, 'D', 1).unwrap(),
+            Rotor::new(RotorType::III, 'U', 1).unwrap(),
+        ];
+
+        let plugboard = Plugboard::new(&[]).unwrap();
+        let mut enigma = Enigma::new(ReflectorType::B, rotors, plugboard);
+
+        enigma.encrypt("A").expect("Failed to encrypt");
+        assert_eq!(vec!['A', 'D', 'V'], enigma.get_keys());
+
+        enigma.encrypt("A").expect("Failed to encrypt");
+        assert_eq!(vec!['A', 'E', 'W'], enigma.get_keys());
+
+        // The middle rotor is on its notch, so it steps again along with the left rotor.
+        enigma.encrypt("A").expect("Failed to encrypt");
+        assert_eq!(vec!['B', 'F', 'X'], enigma.get_keys());
+
+        enigma.encrypt("A").expect("Failed to encrypt");
+        assert_eq!(vec!['B', 'F', 'Y'], enigma.get_keys());
+    }
+
+    #[test]
+    fn test_enigma_odometer_step() {
+        let rotors = vec![
+            Rotor::new(RotorType::I, 'A', 1).unwrap(),
+            Rotor::new(RotorType::II, 'D', 1).unwrap(),
+            Rotor::new(RotorType::III, 'U', 1).unwrap(),
+        ];
+
+        let plugboard = Plugboard::new(&[]).unwrap();
+        let mut enigma = Enigma::new(ReflectorType::B, rotors, plugboard);
+        enigma.set_stepping_mode(SteppingMode::Odometer);
+
+        enigma.encrypt("A").expect("Failed to encrypt");
+        assert_eq!(vec!['A', 'D', 'V'], enigma.get_keys());
+
+        enigma.encrypt("A").expect("Failed to encrypt");
+        assert_eq!(vec!['A', 'E', 'W'], enigma.get_keys());
+
+        enigma.encrypt("A").expect("Failed to encrypt");
+        assert_eq!(vec!['A', 'E', 'X'], enigma.get_keys());
+    }
+
+    #[test]
+    fn test_enigma_double_step_encrypt() {
+        let input = String::from("AAA");
+        let expected = String::from("EQI");
+
+        let rotors = vec![
+            Rotor::new(RotorType::I, 'A', 1).unwrap(),
+            Rotor::new(RotorType::II, 'D', 1).unwrap(),
+            Rotor::new(RotorType::III, 'U', 1).unwrap(),
+        ];
+
+        let plugboard = Plugboard::new(&[]).unwrap();
+        let mut enigma = Enigma::new(ReflectorType::B, rotors, plugboard);
+
+        let output = enigma.encrypt(&input).expect("Failed to encrypt");
+
+        assert_eq!(&expected, &output);
+    }
+
+    #[test]
+    fn test_enigma_decrypt_historical_message() {
+        // Operation Barbarossa, 7th July 1941. Message key BLA.
+        let input = String::from("EDPUDNRGYSZRCXNUYTPOMRMBOFKTBZREZKMLXLVEFGUEYSIOZVEQMIKUBPMMYLKLTTDEISMDICAGYKUACTCDOMOHWXMUUIAUBSTSLRNBZSZWNRFXWFYSSXJZVIJHIDISHPRKLKAYUPADTXQSPINQMATLPIFSVKDASCTACDPBOPVHJK");
+        let expected = String::from("AUFKLXABTEILUNGXVONXKURTINOWAXKURTINOWAXNORDWESTLXSEBEZXSEBEZXUAFFLIEGERSTRASZERIQTUNGXDUBROWKIXDUBROWKIXOPOTSCHKAXOPOTSCHKAXUMXEINSAQTDREINULLXUHRANGETRETENXANGRIFFXINFXRGTX");
+
+        let plugs = [['A', 'V'], ['B', 'S'], ['C', 'G'], ['D', 'L'], ['F', 'U'], ['H', 'Z'], ['I', 'N'], ['K', 'M'], ['O', 'W'], ['R', 'X']];
+
+        let mut settings = MachineSettings::new(ReflectorType::B, &[RotorType::II, RotorType::IV, RotorType::V], &[2, 21, 12], "BLA", &plugs);
+        settings.model = Some(MachineModel::EnigmaI);
+
+        let mut enigma = settings.build().unwrap();
+
+        let output = enigma.encrypt(&input).expect("Failed to encrypt");
+
+        assert_eq!(&expected, &output);
+    }
+
+    #[test]
+    fn test_enigma_uhr_encrypt_decrypt() {
+        let input = String::from("UHRSTELLUNGSIEBENUNDZWANZIG");
+        let expected = String::from("TZKPSGGAIBDZUQPXPQYWPCGDCTN");
+
+        let plugs = vec![['A', 'V'], ['B', 'S'], ['C', 'G'], ['D', 'L'], ['F', 'U'], ['H', 'Z'], ['I', 'N'], ['K', 'M'], ['O', 'W'], ['R', 'X']];
+
+        let rotors = vec![
+            Rotor::new(RotorType::II, 'B', 2).unwrap(),
+            Rotor::new(RotorType::IV, 'L', 21).unwrap(),
+            Rotor::new(RotorType::V, 'A', 12).unwrap(),
+        ];
+
+        let plugboard = Plugboard::with_uhr(&plugs, 27).unwrap();
+        let mut enigma = Enigma::new(ReflectorType::B, rotors, plugboard);
+
+        let output = enigma.encrypt(&input).expect("Failed to encrypt");
+
+        assert_eq!(&expected, &output);
+
+        // The machine as a whole stays reciprocal with the Uhr attached.
+        let rotors = vec![
+            Rotor::new(RotorType::II, 'B', 2).unwrap(),
+            Rotor::new(RotorType::IV, 'L', 21).unwrap(),
+            Rotor::new(RotorType::V, 'A', 12).unwrap(),
+        ];
+
+        let plugboard = Plugboard::with_uhr(&plugs, 27).unwrap();
+        let mut enigma = Enigma::new(ReflectorType::B, rotors, plugboard);
+
+        let output = enigma.encrypt(&expected).expect("Failed to decrypt");
+
+        assert_eq!(&input, &output);
+    }
+
+    #[test]
+    fn test_enigma_m4_decrypt_historical_message() {
+        // U-534, 1945.
+        let input = String::from("NCZWVUSXPNYMINHZXMQXSFWXWLKJAHSHNMCOCCAKUQPMKCSMHKSEINJUSBLKIOSXCKUBHMLLXCSJUSRRDVKOHULXWCCBGVLIYXEOAHXRHKKFVDREWEZLXOBAFGYUJQUKGRTVUKAMEURBVEKSUHHVOYHABCJWMAKLFKLMYFVNRIZRVVRTKOFDANJMOLBGFFLEOPRGTFLVRHOWOPBEKVWMUQFMPWPARMFHAGKXIIBG");
+        let expected = String::from("VONVONJLOOKSJHFFTTTEINSEINSDREIZWOYYQNNSNEUNINHALTXXBEIANGRIFFUNTERWASSERGEDRUECKTYWABOSXLETZTERGEGNERSTANDNULACHTDREINULUHRMARQUANTONJOTANEUNACHTSEYHSDREIYZWOZWONULGRADYACHTSMYSTOSSENACHXEKNSVIERMBFAELLTYNNNNNNOOOVIERYSICHTEINSNULL");
+
+        let plugs = vec![['A', 'T'], ['B', 'L'], ['D', 'F'], ['G', 'J'], ['H', 'M'], ['N', 'W'], ['O', 'P'], ['Q', 'Y'], ['R', 'Z'], ['V', 'X']];
+
+        let rotors = vec![
+            Rotor::new(RotorType::II, 'J', 1).unwrap(),
+            Rotor::new(RotorType::IV, 'N', 1).unwrap(),
+            Rotor::new(RotorType::I, 'A', 22).unwrap(),
+        ];
+
+        let greek_wheel = GreekWheel::new(GreekWheelType::Beta, 'V', 1).unwrap();
+        let plugboard = Plugboard::new(&plugs).unwrap();
+        let mut enigma = Enigma::new_m4(ReflectorType::ThinB, greek_wheel, rotors, plugboard).unwrap();
+
+        let output = enigma.encrypt(&input).expect("Failed to encrypt");
+
+        assert_eq!(&expected, &output);
+
+        // The greek wheel never steps.
+        assert_eq!('V', enigma.get_keys()[0]);
+    }
+
+    #[test]
+    fn test_enigma_m4_equivalent_to_three_rotor() {
+        // Beta at A with ring 1 and the thin B reflector behave exactly like the wide B reflector.
+        let input = String::from("THEQUICKBROWNFOXJUMPSOVERTHELAZYDOG");
+
+        let rotors = vec![
+            Rotor::new(RotorType::I, 'A', 1).unwrap(),
+            Rotor::new(RotorType::II, 'A', 1).unwrap(),
+            Rotor::new(RotorType::III, 'A', 1).unwrap(),
+        ];
+
+        let mut enigma = Enigma::new(ReflectorType::B, rotors, Plugboard::new(&[]).unwrap());
+        let expected = enigma.encrypt(&input).expect("Failed to encrypt");
+
+        let rotors = vec![
+            Rotor::new(RotorType::I, 'A', 1).unwrap(),
+            Rotor::new(RotorType::II, 'A', 1).unwrap(),
+            Rotor::new(RotorType::III, 'A', 1).unwrap(),
+        ];
+
+        let greek_wheel = GreekWheel::new(GreekWheelType::Beta, 'A', 1).unwrap();
+        let mut enigma = Enigma::new_m4(ReflectorType::ThinB, greek_wheel, rotors, Plugboard::new(&[]).unwrap()).unwrap();
+        let output = enigma.encrypt(&input).expect("Failed to encrypt");
+
+        assert_eq!(&expected, &output);
+    }
+
+    #[test]
+    fn test_enigma_m4_requires_thin_reflector() {
+        let rotors = vec![
+            Rotor::new(RotorType::I, 'A', 1).unwrap(),
+            Rotor::new(RotorType::II, 'A', 1).unwrap(),
+            Rotor::new(RotorType::III, 'A', 1).unwrap(),
+        ];
+
+        let greek_wheel = GreekWheel::new(GreekWheelType::Gamma, 'A', 1).unwrap();
+
+        assert!(Enigma::new_m4(ReflectorType::B, greek_wheel, rotors, Plugboard::new(&[]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_enigma_m4_requires_three_rotors() {
+        let rotors = vec![
+            Rotor::new(RotorType::I, 'A', 1).unwrap(),
+            Rotor::new(RotorType::II, 'A', 1).unwrap(),
+        ];
+
+        let greek_wheel = GreekWheel::new(GreekWheelType::Gamma, 'A', 1).unwrap();
+
+        assert!(Enigma::new_m4(ReflectorType::ThinC, greek_wheel, rotors, Plugboard::new(&[]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_enigma_commercial_entry_wheel() {
+        let input = String::from("AAAAA");
+        let expected = String::from("YWRLJ");
+        let mut output;
+
+        {
+            let rotors = vec![
+                Rotor::new(RotorType::I, 'A', 1).unwrap(),
+                Rotor::new(RotorType::II, 'A', 1).unwrap(),
+                Rotor::new(RotorType::III, 'A', 1).unwrap(),
+            ];
+
+            let mut enigma = Enigma::new(ReflectorType::B, rotors, Plugboard::new(&[]).unwrap());
+            enigma.set_entry_wheel(EntryWheel::new(EntryWheelType::Commercial));
+
+            output = enigma.encrypt(&input).expect("Failed to encrypt");
+            assert_eq!(&expected, &output);
+        }
+
+        {
+            let rotors = vec![
+                Rotor::new(RotorType::I, 'A', 1).unwrap(),
+                Rotor::new(RotorType::II, 'A', 1).unwrap(),
+                Rotor::new(RotorType::III, 'A', 1).unwrap(),
+            ];
+
+            let mut enigma = Enigma::new(ReflectorType::B, rotors, Plugboard::new(&[]).unwrap());
+            enigma.set_entry_wheel(EntryWheel::new(EntryWheelType::Commercial));
+
+            output = enigma.encrypt(&output).expect("Failed to encrypt");
+        }
+
+        assert_eq!(&input, &output);
+    }
+
+    #[test]
+    fn test_enigma_swiss_k_encrypt() {
+        let input = String::from("AAAAAAAAAA");
+        let expected = String::from("YLYMIRTSWJ");
+
+        let rotors = vec![
+            Rotor::new(RotorType::IK, 'A', 1).unwrap(),
+            Rotor::new(RotorType::IIK, 'A', 1).unwrap(),
+            Rotor::new(RotorType::IIIK, 'A', 1).unwrap(),
+        ];
+
+        let reflector = Reflector::new(ReflectorType::Commercial);
+        let plugboard = Plugboard::new(&[]).unwrap();
+        let mut enigma = Enigma::new_with_model(MachineModel::SwissK, reflector, None, rotors, plugboard).unwrap();
+
+        let output = enigma.encrypt(&input).expect("Failed to encrypt");
+
+        assert_eq!(&expected, &output);
+    }
+
+    #[test]
+    fn test_enigma_d_encrypt_set_reflector() {
+        let input = String::from("HELLOWORLD");
+        let expected = String::from("KVECTPZIIP");
+
+        let rotors = vec![
+            Rotor::new(RotorType::ID, 'Q', 5).unwrap(),
+            Rotor::new(RotorType::IID, 'E', 12).unwrap(),
+            Rotor::new(RotorType::IIID, 'V', 20).unwrap(),
+        ];
+
+        let reflector = Reflector::with_setting(ReflectorType::Commercial, 'H', 1).unwrap();
+        let plugboard = Plugboard::new(&[]).unwrap();
+        let mut enigma = Enigma::new_with_model(MachineModel::EnigmaD, reflector, None, rotors, plugboard).unwrap();
+
+        let output = enigma.encrypt(&input).expect("Failed to encrypt");
+
+        assert_eq!(&expected, &output);
+    }
+
+    #[test]
+    fn test_enigma_g_encrypt() {
+        let input = String::from("ABWEHRGEHEIMSACHE");
+        let expected = String::from("CVYRIABREGRZMMRXW");
+
+        let rotors = vec![
+            Rotor::new(RotorType::IIIG, 'Q', 3).unwrap(),
+            Rotor::new(RotorType::IG, 'K', 17).unwrap(),
+            Rotor::new(RotorType::IIG, 'D', 9).unwrap(),
+        ];
+
+        let reflector = Reflector::with_setting(ReflectorType::G, 'K', 1).unwrap();
+        let plugboard = Plugboard::new(&[]).unwrap();
+        let mut enigma = Enigma::new_with_model(MachineModel::EnigmaG, reflector, None, rotors, plugboard).unwrap();
+
+        assert_eq!(SteppingMode::Odometer, enigma.stepping_mode);
+
+        let output = enigma.encrypt(&input).expect("Failed to encrypt");
+
+        assert_eq!(&expected, &output);
+        assert_eq!(vec!['W', 'U', 'U'], enigma.get_keys());
+    }
+
+    #[test]
+    fn test_enigma_g_reflector_step() {
+        let input = String::from("ABWEHRGEHEIMSACHE");
+        let expected = String::from("TTGRNPWRZGYEIDJKC");
+
+        // All three rotors turn over on the first key press, which moves the reflector.
+        let rotors = vec![
+            Rotor::new(RotorType::IG, 'Z', 1).unwrap(),
+            Rotor::new(RotorType::IIG, 'Z', 1).unwrap(),
+            Rotor::new(RotorType::IIIG, 'A', 1).unwrap(),
+        ];
+
+        let reflector = Reflector::new(ReflectorType::G);
+        let plugboard = Plugboard::new(&[]).unwrap();
+        let mut enigma = Enigma::new_with_model(MachineModel::EnigmaG, reflector, None, rotors, plugboard).unwrap();
+
+        let output = enigma.encrypt(&input).expect("Failed to encrypt");
+
+        assert_eq!(&expected, &output);
+        assert_eq!('E', enigma.get_reflector_key());
+    }
+
+    #[test]
+    fn test_enigma_t_five_notches() {
+        let input = String::from("AAAAAAAAAAAAAAAAAAAAAAAAAA");
+        let expected = String::from("FWRYLQCQGZRGSVNOZEPVLYXPXQ");
+
+        let rotors = vec![
+            Rotor::new(RotorType::IT, 'A', 1).unwrap(),
+            Rotor::new(RotorType::IIT, 'A', 1).unwrap(),
+            Rotor::new(RotorType::IIIT, 'A', 1).unwrap(),
+        ];
+
+        let reflector = Reflector::new(ReflectorType::T);
+        let plugboard = Plugboard::new(&[]).unwrap();
+        let mut enigma = Enigma::new_with_model(MachineModel::EnigmaT, reflector, None, rotors, plugboard).unwrap();
+
+        let output = enigma.encrypt(&input).expect("Failed to encrypt");
+
+        // One full revolution of the right hand rotor moves the middle rotor five times.
+        assert_eq!(&expected, &output);
+        assert_eq!(vec!['A', 'F', 'A'], enigma.get_keys());
+    }
+
+    #[test]
+    fn test_ukw_d_encrypt() {
+        let input = String::from("LUFTWAFFEUMKEHRWALZE");
+        let expected = String::from("XCHUTGQBOIEVAYSNPZKC");
+
+        let rotors = vec![
+            Rotor::new(RotorType::V, 'Q', 12).unwrap(),
+            Rotor::new(RotorType::I, 'E', 3).unwrap(),
+            Rotor::new(RotorType::IV, 'V', 7).unwrap(),
+        ];
+
+        // The same plugging as AV ZF XI WP US TB QO NL ME KD HG RC in German notation.
+        let pairs = [['A', 'F'], ['C', 'V'], ['D', 'S'], ['E', 'L'], ['G', 'I'], ['H', 'Z'], ['K', 'M'], ['N', 'Q'], ['P', 'W'], ['R', 'X'], ['T', 'U'], ['J', 'Y']];
+        let reflector = Reflector::with_plugging(&pairs, ReflectorNotation::Bletchley).unwrap();
+        let plugboard = Plugboard::new(&[['A', 'T'], ['B', 'L'], ['D', 'F']]).unwrap();
+        let mut enigma = Enigma::new_with_model(MachineModel::EnigmaI, reflector, None, rotors, plugboard).unwrap();
+
+        let output = enigma.encrypt(&input).expect("Failed to encrypt");
+
+        assert_eq!(&expected, &output);
+    }
+
+    #[test]
+    fn test_enigma_model_rejects_components() {
+        let rotors = vec![
+            Rotor::new(RotorType::I, 'A', 1).unwrap(),
+            Rotor::new(RotorType::IID, 'A', 1).unwrap(),
+            Rotor::new(RotorType::IIID, 'A', 1).unwrap(),
+        ];
+
+        let reflector = Reflector::new(ReflectorType::Commercial);
+        let plugboard = Plugboard::new(&[]).unwrap();
+
+        assert!(Enigma::new_with_model(MachineModel::EnigmaK, reflector, None, rotors, plugboard).is_err());
+    }
+
+    #[test]
+    fn test_enigma_input_policy() {
+        let settings = MachineSettings::new(ReflectorType::B, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1], "AAA", &[]);
+
+        let mut enigma = settings.build().unwrap();
+        assert!(enigma.encrypt("Aa").is_err());
+        assert!(enigma.encrypt("A, A").is_err());
+        assert_eq!("BD ZG", enigma.encrypt("AA AA").unwrap());
+
+        // Nothing moves when the input is rejected.
+        let mut enigma = settings.build().unwrap();
+        assert!(enigma.encrypt("AAAA1").is_err());
+        assert_eq!(vec!['A', 'A', 'A'], enigma.get_keys());
+
+        enigma.set_input_policy(InputPolicy::Fold, false);
+        assert_eq!("BD ZG", enigma.encrypt("aA Aa").unwrap());
+        assert!(enigma.encrypt("A.").is_err());
+
+        // The error names the character turned away and the policy that did it.
+        assert_eq!("Invalid input '.'. It is not accepted by the Fold input policy.", enigma.encrypt("a.").unwrap_err().to_string());
+        assert_eq!("Invalid input 'ä'. It is not accepted by the Fold input policy.", enigma.encrypt_in_place(&mut "aä".as_bytes().to_vec()).unwrap_err().to_string());
+        assert_eq!("Invalid input '1'. It is not accepted by the Fold input policy.", enigma.encrypt_chars("a1".chars()).collect::<Result<String>>().unwrap_err().to_string());
+
+        let mut enigma = settings.build().unwrap();
+        enigma.set_input_policy(InputPolicy::Strip, false);
+        assert_eq!("BDZGO", enigma.encrypt("Aa, a-a\na!").unwrap());
+
+        let mut enigma = settings.build().unwrap();
+        enigma.set_input_policy(InputPolicy::PassThrough, false);
+        assert_eq!("BD, Z-G. 1O", enigma.encrypt("aA, a-A. 1a").unwrap());
+
+        let mut enigma = settings.build().unwrap();
+        enigma.set_input_policy(InputPolicy::PassThrough, true);
+        assert_eq!("Bd, z-G. 1o ä", enigma.encrypt("Aa, a-A. 1a ä").unwrap());
+    }
+
+    #[test]
+    fn test_get_input_policy_from_string() {
+        assert_eq!(InputPolicy::Error, Enigma::get_input_policy_from_string("Error").unwrap());
+        assert_eq!(InputPolicy::Fold, Enigma::get_input_policy_from_string("fold").unwrap());
+        assert_eq!(InputPolicy::Strip, Enigma::get_input_policy_from_string("Strip").unwrap());
+        assert_eq!(InputPolicy::PassThrough, Enigma::get_input_policy_from_string("passthrough").unwrap());
+
+        assert!(Enigma::get_input_policy_from_string("blah").is_err());
+    }
+
+    #[test]
+    fn test_get_stepping_mode_from_string() {
+        assert_eq!(SteppingMode::DoubleStep, Enigma::get_stepping_mode_from_string("DoubleStep").unwrap());
+        assert_eq!(SteppingMode::DoubleStep, Enigma::get_stepping_mode_from_string("doublestep").unwrap());
+
+        assert_eq!(SteppingMode::Odometer, Enigma::get_stepping_mode_from_string("Odometer").unwrap());
+        assert_eq!(SteppingMode::Odometer, Enigma::get_stepping_mode_from_string("odometer").unwrap());
+
+        assert!(Enigma::get_stepping_mode_from_string("blah").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_enigma_serde_resume() {
+        // The Uhr and a stepping reflector don't fit an M4, so the second machine covers the rest of the state.
+        let settings: MachineSettings = "M4 ThinB Beta II IV I 01 01 01 22 VJNA AT BL DF GJ HM NW OP QY RZ VX".parse().unwrap();
+        let input = "NCZWVUSXPNYMINHZXMQXSFWXWLKJAHSHNMCOCCAKUQPMKCSMHKSEINJUSBLKIOSXCKUBHMLLXCSJUSRRDVKOHULXWCCBGVLIYXEOAHXRHKKFVDREWEZLXOBAFGYUJQUKGRTVUKAMEURBVEKSUHHVOYHABCJWMAKLFKLMYFVNRIZRVVRTKOFDANJMOLBGFFLEOPRGTFLVRHOWOPBEKVWMUQFMPWPARMFHAGKXIIBG";
+
+        let mut expected = settings.build().unwrap();
+        let expected = expected.encrypt(input).unwrap();
+
+        let mut enigma = settings.build().unwrap();
+        let mut output = enigma.encrypt(&input[..100]).unwrap();
+
+        let json = serde_json::to_string(&enigma).unwrap();
+        let mut resumed: Enigma = serde_json::from_str(&json).unwrap();
+
+        output.push_str(&resumed.encrypt(&input[100..]).unwrap());
+        assert_eq!(expected, output);
+
+        let rotors = vec![
+            Rotor::new(RotorType::IG, 'Z', 3).unwrap(),
+            Rotor::new(RotorType::IIG, 'Y', 17).unwrap(),
+            Rotor::new(RotorType::IIIG, 'X', 9).unwrap(),
+        ];
+
+        let plugs = [['A', 'B'], ['C', 'D'], ['E', 'F'], ['G', 'H'], ['I', 'J'], ['K', 'L'], ['M', 'N'], ['O', 'P'], ['Q', 'R'], ['S', 'T']];
+        let reflector = Reflector::with_setting(ReflectorType::G, 'M', 5).unwrap();
+        let mut enigma = Enigma::new_with_reflector(reflector, rotors, Plugboard::with_uhr(&plugs, 27).unwrap());
+        enigma.set_stepping_mode(SteppingMode::Odometer);
+
+        let first = enigma.encrypt(&"A".repeat(2000)).unwrap();
+        let json = serde_json::to_string(&enigma).unwrap();
+        let second = enigma.encrypt(&"A".repeat(2000)).unwrap();
+
+        let mut resumed: Enigma = serde_json::from_str(&json).unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(second, resumed.encrypt(&"A".repeat(2000)).unwrap());
+        assert_eq!(enigma.get_reflector_key(), resumed.get_reflector_key());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_enigma_serde_invalid() {
+        let enigma = "B I II III 01 01 01 AAA AB CD".parse::<MachineSettings>().unwrap().build().unwrap();
+        let json: serde_json::Value = serde_json::to_value(&enigma).unwrap();
+
+        let is_rejected = |change: &dyn Fn(&mut serde_json::Value)| {
+            let mut json = json.clone();
+            change(&mut json);
+            serde_json::from_value::<Enigma>(json).is_err()
+        };
+
+        assert!(!is_rejected(&|_| {}));
+        assert!(is_rejected(&|j| j["rotors"][0]["position"] = 99.into()));
+        assert!(is_rejected(&|j| j["rotors"][1]["ring_setting"] = 26.into()));
+        assert!(is_rejected(&|j| j["rotors"][2]["wiring"][0] = 0.into()));
+        assert!(is_rejected(&|j| j["rotors"][2]["turnover"][0] = 40.into()));
+        assert!(is_rejected(&|j| j["reflector"]["position"] = 26.into()));
+        assert!(is_rejected(&|j| j["reflector"]["wiring"][0] = 0.into()));
+        assert!(is_rejected(&|j| j["entry_wheel"]["wiring"][0] = 30.into()));
+        assert!(is_rejected(&|j| j["plugboard"]["plugs"][0][1] = 2.into()));
+        assert!(is_rejected(&|j| j["plugboard"]["uhr_setting"] = 3.into()));
+        assert!(is_rejected(&|j| j["start"]["rotors"] = serde_json::json!([0, 0])));
+        assert!(is_rejected(&|j| j["start"]["reflector"] = 26.into()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_machine_settings_serde() {
+        let settings: MachineSettings = "B II IV V 02 21 12 BLA AV BS CG DL FU HZ IN KM OW RX".parse().unwrap();
+        let json = serde_json::to_string(&settings).unwrap();
+
+        assert_eq!(settings, serde_json::from_str::<MachineSettings>(&json).unwrap());
+    }
+
+    #[test]
+    fn test_enigma_snapshot_restore() {
+        let settings = MachineSettings::new(ReflectorType::B, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1], "ADU", &[]);
+        let mut enigma = settings.build().unwrap();
+
+        let first = enigma.encrypt("HELLO").unwrap();
+        let state = enigma.snapshot();
+        let second = enigma.encrypt("WORLD").unwrap();
+
+        enigma.restore(&state).unwrap();
+
+        assert_eq!(second, enigma.encrypt("WORLD").unwrap());
+        assert_ne!(first, second);
+
+        // A snapshot only fits a machine with the same number of rotors.
+        let mut m4 = MachineSettings::new(ReflectorType::ThinB, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1, 1], "AAAA", &[]);
+        m4.greek_wheel = Some(GreekWheelType::Beta);
+
+        assert!(m4.build().unwrap().restore(&state).is_ok());
+        assert!(Enigma::new(ReflectorType::B, Vec::new(), Plugboard::new(&[]).unwrap()).restore(&state).is_err());
+    }
+
+    #[test]
+    fn test_enigma_set_keys() {
+        let settings = MachineSettings::new(ReflectorType::B, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1], "ADU", &[]);
+        let mut enigma = settings.build().unwrap();
+
+        enigma.encrypt("HELLO").unwrap();
+        enigma.set_keys("ADU").unwrap();
+
+        assert_eq!(settings.build().unwrap().encrypt("WORLD").unwrap(), enigma.encrypt("WORLD").unwrap());
+        assert_eq!(vec!['B', 'F', 'Z'], enigma.get_keys());
+
+        let mut m4 = MachineSettings::new(ReflectorType::ThinB, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1, 1], "AAAA", &[]);
+        m4.greek_wheel = Some(GreekWheelType::Beta);
+        let mut enigma = m4.build().unwrap();
+
+        enigma.set_keys("QRST").unwrap();
+        assert_eq!(vec!['Q', 'R', 'S', 'T'], enigma.get_keys());
+
+        assert!(enigma.set_keys("RST").is_err());
+        assert!(enigma.set_keys("qrst").is_err());
+        assert_eq!(vec!['Q', 'R', 'S', 'T'], enigma.get_keys());
+    }
+
+    #[test]
+    fn test_enigma_rewind() {
+        // Starting just before the double step of the middle rotor, and going through it.
+        let settings = MachineSettings::new(ReflectorType::B, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1], "ADS", &[]);
+        let mut enigma = settings.build().unwrap();
+        let mut states: Vec<MachineState> = vec![enigma.snapshot()];
+
+        for _ in 0..700 {
+            enigma.encrypt("A").unwrap();
+            states.push(enigma.snapshot());
+        }
+
+        for state in states.iter().rev().skip(1) {
+            enigma.rewind(1).unwrap();
+            assert_eq!(state, &enigma.snapshot());
+        }
+
+        // Rewinding in one go, and then typing the same message again.
+        let output = enigma.encrypt("RAINBOWRAINBOW").unwrap();
+        enigma.rewind(14).unwrap();
+
+        assert_eq!(states[0], enigma.snapshot());
+        assert_eq!(output, enigma.encrypt("RAINBOWRAINBOW").unwrap());
+
+        // Going back before the key the rotors were set to.
+        enigma.rewind(114).unwrap();
+        enigma.encrypt(&"A".repeat(100)).unwrap();
+
+        assert_eq!(states[0], enigma.snapshot());
+    }
+
+    #[test]
+    fn test_enigma_rewind_double_step_start() {
+        // With the middle rotor set on its notch the first key press double steps, to the same place as a key of BFX.
+        let settings = MachineSettings::new(ReflectorType::B, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1], "AEX", &[]);
+        let mut enigma = settings.build().unwrap();
+        let start = enigma.snapshot();
+
+        enigma.encrypt("A").unwrap();
+        assert_eq!(vec!['B', 'F', 'Y'], enigma.get_keys());
+
+        enigma.rewind(1).unwrap();
+        assert_eq!(start, enigma.snapshot());
+    }
+
+    #[test]
+    fn test_enigma_rewind_before_start() {
+        // Taking the first position that steps into each one runs into a position nothing steps to after a few presses.
+        let rotors = vec![
+            Rotor::new(RotorType::V, 'A', 1).unwrap(),
+            Rotor::new(RotorType::III, 'W', 1).unwrap(),
+            Rotor::new(RotorType::I, 'B', 1).unwrap(),
+        ];
+
+        let mut enigma = Enigma::new(ReflectorType::B, rotors, Plugboard::new(&[]).unwrap());
+        let start = enigma.snapshot();
+
+        enigma.rewind(12).unwrap();
+        enigma.encrypt(&"A".repeat(12)).unwrap();
+
+        assert_eq!(start, enigma.snapshot());
+    }
+
+    #[test]
+    fn test_enigma_rewind_spread_of_keys() {
+        for rotor_types in [[RotorType::V, RotorType::III, RotorType::I], [RotorType::I, RotorType::II, RotorType::III], [RotorType::VI, RotorType::VII, RotorType::VIII]] {
+            let rotors: Vec<Rotor> = rotor_types.iter().map(|t| Rotor::new(*t, 'A', 1).unwrap()).collect();
+            let mut enigma = Enigma::new(ReflectorType::B, rotors, Plugboard::new(&[]).unwrap());
+
+            // Every position that can be reached by 40 key presses, found by stepping every position forward.
+            let mut reachable: HashSet<MachineState> = (0..(26 * 26 * 26))
+                .map(|i| MachineState { rotors: vec![i / 676, (i / 26) % 26, i % 26], reflector: 0 })
+                .collect();
+
+            for _ in 0..40 {
+                reachable = reachable.iter().map(|state| {
+                    enigma.restore(state).unwrap();
+                    enigma.step_rotors();
+                    enigma.snapshot()
+                }).collect();
+            }
+
+            // Keys spread over every position, each one moving the three wheels on by a different amount.
+            for i in (0..(26 * 26 * 26)).step_by(877) {
+                let start = MachineState { rotors: vec![i / 676, (i / 26) % 26, i % 26], reflector: 0 };
+                enigma.restore(&start).unwrap();
+
+                let rewound = enigma.rewind(40).is_ok();
+                assert_eq!(reachable.contains(&start), rewound, "{:?} {:?}", rotor_types, start);
+
+                // A failed rewind leaves the rotors where they were.
+                if rewound {
+                    enigma.encrypt(&"A".repeat(40)).unwrap();
+                }
+
+                assert_eq!(start, enigma.snapshot(), "{:?} {:?}", rotor_types, start);
+            }
+        }
+    }
+
+    #[test]
+    fn test_enigma_rewind_after_changing_stepping() {
+        let settings = MachineSettings::new(ReflectorType::B, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1], "ADS", &[]);
+        let mut enigma = settings.build().unwrap();
+
+        enigma.encrypt(&"A".repeat(50)).unwrap();
+        enigma.set_stepping_mode(SteppingMode::Odometer);
+
+        let state = enigma.snapshot();
+        enigma.encrypt("AAAAA").unwrap();
+        enigma.rewind(5).unwrap();
+
+        assert_eq!(state, enigma.snapshot());
+        assert_eq!(vec!['B', 'G', 'Q'], enigma.get_keys());
+    }
+
+    #[test]
+    fn test_enigma_rewind_odometer_multi_notch() {
+        let rotors = vec![
+            Rotor::new(RotorType::IG, 'Z', 1).unwrap(),
+            Rotor::new(RotorType::IIG, 'Y', 1).unwrap(),
+            Rotor::new(RotorType::IIIG, 'R', 1).unwrap(),
+        ];
+
+        let reflector = Reflector::with_setting(ReflectorType::G, 'A', 1).unwrap();
+        let mut enigma = Enigma::new_with_model(MachineModel::EnigmaG, reflector, None, rotors, Plugboard::new(&[]).unwrap()).unwrap();
+        let start = enigma.snapshot();
+
+        let output = enigma.encrypt(&"A".repeat(3000)).unwrap();
+        enigma.rewind(3000).unwrap();
+
+        assert_eq!(start, enigma.snapshot());
+        assert_eq!(output, enigma.encrypt(&"A".repeat(3000)).unwrap());
+    }
+
+    #[test]
+    fn test_enigma_seek() {
+        fn check_seek(machine: &str, build: &dyn Fn() -> Enigma) {
+            let mut sequential = build();
+            let mut seeking = build();
+
+            for offset in 0..700 {
+                seeking.seek(offset);
+                assert_eq!(sequential.snapshot(), seeking.snapshot(), "{} at {}", machine, offset);
+
+                sequential.step_rotors();
+            }
+
+            // Further in, past every cycle of the rotors and the double step.
+            let mut offset = 700;
+
+            for target in [1000, 17_581, 40_000, 250_000] {
+                while offset < target {
+                    sequential.step_rotors();
+                    offset += 1;
+                }
+
+                seeking.seek(target);
+                assert_eq!(sequential.snapshot(), seeking.snapshot(), "{} at {}", machine, target);
+            }
+
+            // And back again.
+            seeking.seek(17);
+            let mut expected = build();
+            expected.encrypt(&"A".repeat(17)).unwrap();
+
+            assert_eq!(expected.snapshot(), seeking.snapshot(), "{}", machine);
+            assert_eq!(expected.encrypt("SEEKING").unwrap(), seeking.encrypt("SEEKING").unwrap());
+
+            // Too far in to step there, so the last key presses are stepped on from a shorter seek instead.
+            let far = 1 << 40;
+            seeking.seek(far - 1000);
+
+            for _ in 0..1000 {
+                seeking.step_rotors();
+            }
+
+            let mut expected = build();
+            expected.seek(far);
+
+            assert_eq!(expected.snapshot(), seeking.snapshot(), "{} at {}", machine, far);
+        }
+
+        let machines = [
+            "B I II III 01 01 01 AAA",
+            // The middle rotor starts on its notch, so it double steps on the first key press.
+            "B I II III 01 01 01 AEX",
+            // The right hand rotor starts on its notch too, and its first push on the middle rotor is lost.
+            "B I II III 01 01 01 AEV",
+            // Rotors VI to VIII have two notches.
+            "M3 B VI VIII VII 05 11 19 QZL",
+            "M3 B VIII VI VII 05 11 19 QYZ",
+            "M4 ThinC Gamma VII VI VIII 03 07 11 15 RMZM",
+            // Five notches on each rotor of the Enigma T.
+            "EnigmaT T IT VT VIIIT 01 01 01 01 AQKZ",
+            // The gear driven Enigma G, where the reflector is stepped too.
+            "EnigmaG G IG IIIG IIG 01 01 01 01 ZYXW",
+        ];
+
+        for machine in machines {
+            let settings: MachineSettings = machine.parse().unwrap();
+            check_seek(machine, &|| settings.build().unwrap());
+        }
+
+        // Five rotors, so three of them double step.
+        check_seek("five rotors", &|| {
+            let rotors = [(RotorType::I, 'Q'), (RotorType::VI, 'Y'), (RotorType::II, 'E'), (RotorType::VIII, 'L'), (RotorType::III, 'U')];
+            let rotors = rotors.iter().map(|(t, key)| Rotor::new(*t, *key, 1).unwrap()).collect();
+
+            Enigma::new(ReflectorType::B, rotors, Plugboard::new(&[]).unwrap())
+        });
+
+        // The Enigma G rotors have notches side by side, which can lose pushes all the way through under the double step.
+        for machine in ["G IG IIIG IIG 01 01 01 01 ZYXW", "B I II IIG 01 01 01 ADA"] {
+            let settings: MachineSettings = machine.parse().unwrap();
+
+            check_seek(machine, &|| {
+                let mut enigma = settings.build().unwrap();
+                enigma.set_stepping_mode(SteppingMode::DoubleStep);
+                enigma
+            });
+        }
+    }
+
+    #[test]
+    fn test_enigma_encrypt_letter() {
+        let settings: MachineSettings = "B II IV V 02 21 12 BLA AV BS CG DL FU HZ IN KM OW RX".parse().unwrap();
+        let input = "EDPUDNRGYS ZRCXNUYTPO MRMBOFKTBZ";
+
+        let mut enigma = settings.build().unwrap();
+        let expected = enigma.encrypt(input).unwrap();
+
+        let mut enigma = settings.build().unwrap();
+        let letters: String = input.bytes().map(|b| enigma.encrypt_letter(b) as char).collect();
+
+        assert_eq!(expected, letters);
+
+        let mut enigma = settings.build().unwrap();
+        let mut buffer = input.as_bytes().to_vec();
+
+        assert_eq!(buffer.len(), enigma.encrypt_in_place(&mut buffer).unwrap());
+        assert_eq!(expected.as_bytes(), &buffer[..]);
+
+        let mut enigma = settings.build().unwrap();
+        let chars: String = enigma.encrypt_chars(input.chars()).collect::<Result<String>>().unwrap();
+
+        assert_eq!(expected, chars);
+        assert_eq!(expected.len(), enigma.encrypt_chars(input.chars()).count());
+
+        // Anything other than an upper case letter goes straight through without moving the rotors.
+        let state = enigma.snapshot();
+
+        assert_eq!(b'a', enigma.encrypt_letter(b'a'));
+        assert_eq!(b'.', enigma.encrypt_letter(b'.'));
+        assert_eq!(state, enigma.snapshot());
+    }
+
+    #[test]
+    fn test_enigma_encrypt_letter_input_policy() {
+        let settings = MachineSettings::new(ReflectorType::B, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1], "AAA", &[]);
+        let input = "Aa, a-A. 1a ä";
+
+        for policy in [InputPolicy::Error, InputPolicy::Fold, InputPolicy::Strip, InputPolicy::PassThrough] {
+            for preserve_case in [false, true] {
+                let build = || {
+                    let mut enigma = settings.build().unwrap();
+                    enigma.set_input_policy(policy, preserve_case);
+                    enigma
+                };
+
+                let expected = build().encrypt(input);
+
+                let mut enigma = build();
+                let mut buffer = input.as_bytes().to_vec();
+                let length = enigma.encrypt_in_place(&mut buffer);
+
+                let mut chars = build();
+                let encrypted = chars.encrypt_chars(input.chars()).collect::<Result<String>>();
+
+                match expected {
+                    Ok(expected) => {
+                        assert_eq!(expected.as_bytes(), &buffer[..length.unwrap()], "{:?} {}", policy, preserve_case);
+                        assert_eq!(expected, encrypted.unwrap(), "{:?} {}", policy, preserve_case);
+                    },
+                    Err(_) => {
+                        assert!(length.is_err(), "{:?} {}", policy, preserve_case);
+                        assert_eq!(vec!['A', 'A', 'A'], enigma.get_keys());
+                        assert!(encrypted.is_err(), "{:?} {}", policy, preserve_case);
+                    },
+                }
+            }
+        }
+
+        // Lower case letters are folded one at a time too, unless the policy is Error.
+        let mut enigma = settings.build().unwrap();
+        enigma.set_input_policy(InputPolicy::Fold, false);
+
+        let mut buffer = b"hello".to_vec();
+        enigma.encrypt_in_place(&mut buffer).unwrap();
+
+        assert_eq!(b"ILBDA", &buffer[..]);
+
+        let mut upper = settings.build().unwrap();
+        let expected = upper.encrypt("HELLOA").unwrap().to_ascii_lowercase();
+
+        enigma.set_input_policy(InputPolicy::Fold, true);
+        assert_eq!(expected.as_bytes()[5], enigma.encrypt_letter(b'a'));
+    }
+}
@@ -1,64 +1,298 @@
 use enigma::Enigma;
 use enigma::Rotor;
 use enigma::Reflector;
+use enigma::ReflectorType;
+use enigma::GreekWheel;
+use enigma::EntryWheel;
 use enigma::Plugboard;
-
-use std::io::{self, Write};
-use clap::Parser;
-use anyhow::Result;
+use enigma::MachineModel;
+use enigma::Catalog;
+use enigma::MachineSettings;
+use enigma::PlaintextConvention;
+use enigma::GroupFormat;
+use enigma::Message;
+use enigma::ChosenIndicator;
+use enigma::NavalIndicator;
+use enigma::BigramTable;
+use enigma::KeySheet;
+use enigma::RotorType;
+
+use std::io::{self, Read, Write};
+use clap::{Parser, Subcommand};
+use rand::{rngs::StdRng, SeedableRng};
+use anyhow::{anyhow, Result};
+
+// The built in components, alongside which any parts from a catalog can be used.
+const MODELS: [&str; 9] = ["EnigmaI", "M3", "M4", "EnigmaD", "EnigmaK", "SwissK", "EnigmaG", "Railway", "EnigmaT"];
+const REFLECTORS: [&str; 10] = ["A", "B", "C", "ThinB", "ThinC", "Commercial", "G", "Railway", "T", "D"];
+const ENTRY_WHEELS: [&str; 3] = ["Military", "Commercial", "Tirpitz"];
+const ROTORS: [&str; 28] = ["I", "II", "III", "IV", "V", "VI", "VII", "VIII", "ID", "IID", "IIID", "IK", "IIK", "IIIK", "IG", "IIG", "IIIG", "IR", "IIR", "IIIR", "IT", "IIT", "IIIT", "IVT", "VT", "VIT", "VIIT", "VIIIT"];
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 struct Args
 {
-    #[arg(long, value_parser = reflector_parser, required = true,
+    #[arg(long, required = false,
+        help = "Loads a catalog of additional rotors, reflectors, entry wheels and models from a TOML or JSON file. Parts from the catalog can be selected by name.")]
+    catalog: Option<String>,
+
+    #[arg(long, required = false, default_value_t = false,
+        help = "Lists the built in parts and the contents of the catalog, then exits.")]
+    list: bool,
+
+    #[arg(long, value_parser = key_sheet_format_parser, required = false,
+        help = "Generates a key sheet for training with rotors I to V and reflector B, then exits. Valid values are 'Text', 'CSV' and 'JSON'.")]
+    key_sheet: Option<String>,
+
+    #[arg(long, required = false,
+        help = "Seeds the random numbers used for the key sheet, or for the keys and Kenngruppen picked by send, so the same seed always gives the same output.")]
+    seed: Option<u64>,
+
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=31), required = false, default_value_t = 31, requires = "key_sheet",
+        help = "Sets the number of days on the key sheet.")]
+    days: u32,
+
+    #[arg(long, required = false, conflicts_with_all = ["model", "reflector", "reflector_key", "reflector_ring", "greek", "rotors", "rings", "key", "plugs"],
+        help = "Sets up the machine from key sheet notation, such as 'B II IV V 02 21 12 BLA AV BS CG DL FU HZ IN KM OW RX'. That is an optional model, the reflector, an optional greek wheel, the rotor order, the ring settings, the key and the plug connections.")]
+    settings: Option<String>,
+
+    #[arg(long, num_args = 1.., value_name = "PATH", required = false, conflicts_with_all = ["settings", "model", "reflector", "reflector_key", "reflector_ring", "greek", "rotors", "rings", "plugs"],
+        help = "Loads key sheets from .json, .csv or .txt files and sets up the machine for the day picked with --day, --date or --find-kenngruppe. The rotors are set to --key, or AAA. A sheet that doesn't name its network is named after its file.")]
+    sheet: Vec<String>,
+
+    #[arg(long, required = false, requires = "sheet",
+        help = "Picks the key sheet for this network, when more than one is loaded.")]
+    network: Option<String>,
+
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=31), required = false, requires = "sheet", conflicts_with_all = ["date", "find_kenngruppe"],
+        help = "Picks the day of the month on the key sheet.")]
+    day: Option<u32>,
+
+    #[arg(long, required = false, requires = "sheet", conflicts_with = "find_kenngruppe",
+        help = "Picks the day on the key sheet by date, such as '1941-06-22'. Only sheets that name their month can be looked up by date.")]
+    date: Option<String>,
+
+    #[arg(long, value_parser = kenngruppe_parser, required = false, requires = "sheet",
+        help = "Picks the network and day from a Kenngruppe received with a message.")]
+    find_kenngruppe: Option<String>,
+
+    #[arg(long, required = false,
+        help = "Sets the machine model. The rotors, reflector, entry wheel and plugboard are checked against the model.")]
+    model: Option<String>,
+
+    #[arg(long, required_unless_present_any = ["list", "settings", "key_sheet", "sheet"],
         help = "Sets the reflector type.")]
-    reflector: String,
+    reflector: Option<String>,
+
+    #[arg(long, value_parser = clap::value_parser!(char), required = false, default_value = "A", requires = "model",
+        help = "Sets the position of the reflector for models with a settable reflector. Valid values are letters in the range A to Z.")]
+    reflector_key: char,
+
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=26), required = false, default_value = "1", requires = "model",
+        help = "Sets the ring setting of the reflector for models with a settable reflector. Valid values are numbers in the range 1 to 26.")]
+    reflector_ring: u32,
+
+    #[arg(long, value_parser = plug_parser, required = false, num_args = 12, value_name = "PLUG",
+        help = "Sets the 12 plug connections of the rewirable reflector D (UKW-D), such as 'AF' for linking contact 'A' to contact 'F'. The fixed pair (J-Y in German notation, B-O in Bletchley notation) is always connected.")]
+    reflector_plugs: Vec<String>,
 
-    #[arg(long, value_parser = rotor_parser, num_args = 3, value_name = "ROTOR", required = true,
+    #[arg(long, value_parser = notation_parser, required = false, default_value = "German",
+        help = "Sets the notation of the reflector D (UKW-D) plug connections. 'German' uses the labels on the wheel, 'Bletchley' the labels used at Bletchley Park.")]
+    reflector_notation: String,
+
+    #[arg(long, required = false,
+        help = "Sets the entry wheel (Eintrittswalze). 'Military' wires the keys straight through, 'Commercial' in QWERTZU keyboard order, 'Tirpitz' in the order of the Enigma T. Defaults to the entry wheel of the model, or 'Military'.")]
+    entry_wheel: Option<String>,
+
+    #[arg(long, value_parser = greek_wheel_parser, required = false,
+        help = "Sets the greek wheel (Zusatzwalze) for the four rotor M4. Requires a thin reflector, and a fourth ring setting and key for the greek wheel given first.")]
+    greek: Option<String>,
+
+    #[arg(long, num_args = 3, value_name = "ROTOR", required_unless_present_any = ["list", "settings", "key_sheet", "sheet"],
         help = "Sets the rotor order (Walzenlage), starting in the leftmost position.")]
     rotors: Vec<String>,
 
-    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=26), num_args = 3, value_name = "SETTING", required = true,
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=26), num_args = 3..=4, value_name = "SETTING", required_unless_present_any = ["list", "settings", "key_sheet", "sheet"],
         help = "Sets the ring settings for the rotors (Ringstellung). Valid values are numbers in the range 1 to 26.")]
     rings: Vec<u32>,
 
-    #[arg(long, value_parser = key_parser, required = true,
+    #[arg(long, value_parser = key_parser, required_unless_present_any = ["list", "settings", "key_sheet", "sheet"],
         help = "Sets the intial positions for the rotors (Grundstellung/Kenngruppen). Valid values are letters in the range A to Z.")]
-    key: String,
+    key: Option<String>,
 
     #[arg(long, value_parser = plug_parser, required = false, num_args = 0..=10, value_name = "PLUG", 
         help = "Sets the plug connections on the plugboard (Steckerverbindungen). Valid values are pairs of letters such as 'AL' for linking the letter 'A' to the letter 'L'.")]
-    plugs: Vec<String>
+    plugs: Vec<String>,
+
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0..=39), required = false, requires = "plugs",
+        help = "Attaches the Enigma Uhr in place of 10 plug connections, with the first letter of each pair on a red plug. Valid values are numbers in the range 0 to 39.")]
+    uhr: Option<u32>,
+
+    #[arg(long, value_parser = stepping_parser, required = false,
+        help = "Sets how the rotors step. 'DoubleStep' models the historical pawl mechanism, 'Odometer' only moves a rotor when the rotor to its right turns over. Defaults to the stepping of the model, or 'DoubleStep'.")]
+    stepping: Option<String>,
+
+    #[arg(long, value_parser = input_policy_parser, required = false, default_value = "Error",
+        help = "Sets what happens to input other than upper case letters. 'Error' only accepts upper case letters and whitespace, 'Fold' also encrypts lower case letters, 'Strip' drops anything that is not a letter and 'PassThrough' copies it to the output.")]
+    input_policy: String,
+
+    #[arg(long, required = false, default_value_t = false,
+        help = "Keeps lower case letters in lower case after they have been encrypted.")]
+    preserve_case: bool,
+
+    #[arg(long, value_parser = convention_parser, required = false,
+        help = "Turns each line into plaintext the way operators did before it is encrypted, spelling out umlauts, numbers and punctuation. Valid values are 'Army' and 'Navy'.")]
+    prepare: Option<String>,
+
+    #[arg(long, value_parser = convention_parser, required = false,
+        help = "Puts the punctuation, numbers and CH back into each decrypted line. Valid values are 'Army' and 'Navy'.")]
+    prettify: Option<String>,
+
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), required = false, conflicts_with = "prettify",
+        help = "Prints the output in groups of this many letters, usually 5 for the Army and 4 for the Navy.")]
+    group_size: Option<u32>,
+
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), required = false, default_value_t = 10, requires = "group_size",
+        help = "Sets how many groups are printed on each line of output.")]
+    groups_per_line: u32,
+
+    #[arg(long, required = false, default_value_t = false, requires = "group_size",
+        help = "Numbers each line of grouped output.")]
+    line_numbers: bool,
+
+    #[arg(long, required = false, default_value_t = false,
+        help = "Reads the input as received traffic, skipping headers up to the last '=' on a line, line numbers and the spaces between groups.")]
+    traffic: bool,
+
+    #[arg(long, required = false, default_value_t = false, requires = "traffic",
+        help = "Skips the first group of the traffic and of each message after a header, as it is the Kenngruppe identifying the key rather than part of the message.")]
+    kenngruppe: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(about = "Encrypts everything on stdin as a message and prints it as traffic ready to send. Uses the procedure from 1940, with a random Grundstellung and message key for each part, or the Kriegsmarine procedure when given a bigram table.")]
+    Send {
+        #[arg(long, value_parser = time_parser, required_unless_present = "bigram_table",
+            help = "Sets the time of the message in the header, such as '1840'.")]
+        time: Option<String>,
+
+        #[arg(long, value_parser = kenngruppe_parser, num_args = 1..=4, required = true, value_name = "KENNGRUPPE",
+            help = "Sets the Kenngruppen for the key from the key sheet, one of which is sent with each message or part. Valid values are groups of 3 letters.")]
+        kenngruppen: Vec<String>,
+
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..), default_value_t = 250,
+            help = "Sets the most letters sent in one part before the message is split into parts (Teile).")]
+        part_length: u32,
+
+        #[command(flatten)]
+        naval: NavalArgs,
+    },
+
+    #[command(about = "Decrypts traffic on stdin. Uses the procedure from 1940, recovering the message key of each part from the Grundstellung and encrypted key in its header, or the Kriegsmarine procedure when given a bigram table.")]
+    Receive {
+        #[command(flatten)]
+        naval: NavalArgs,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct NavalArgs {
+    #[arg(long, required = false, requires = "grundstellung",
+        help = "Loads the bigram table used to disguise naval indicators from a TOML or JSON file, and switches to the Kriegsmarine procedure with messages in groups of 4 letters.")]
+    bigram_table: Option<String>,
+
+    #[arg(long, value_parser = key_parser, required = false, requires = "bigram_table",
+        help = "Sets the Grundstellung from the key sheet, at which the Verfahrenkenngruppe is encrypted to give the message key.")]
+    grundstellung: Option<String>,
+}
+
+fn notation_parser(s: &str) -> Result<String, String> {
+    let notations = ["German", "Bletchley"];
+
+    if notations.contains(&s) {
+        return Ok(s.to_string());
+    }
+
+    Err(format!("Must be one of {:?}", notations))
 }
 
-fn reflector_parser(s: &str) -> Result<String, String> {
-    let reflectors = ["Beta", "Gamma", "A", "B", "C", "ThinB", "ThinC", "ETW"];
+fn greek_wheel_parser(s: &str) -> Result<String, String> {
+    let greek_wheels = ["Beta", "Gamma"];
 
-    if reflectors.contains(&s) {
+    if greek_wheels.contains(&s) {
         return Ok(s.to_string());
     }
 
-    Err(format!("Must be one of {:?}", reflectors))
+    Err(format!("Must be one of {:?}", greek_wheels))
 }
 
-fn rotor_parser(s: &str) -> Result<String, String> {
-    let rotors = ["I", "II", "III", "IV", "V", "VI", "VII", "VIII"];
+fn stepping_parser(s: &str) -> Result<String, String> {
+    let modes = ["DoubleStep", "Odometer"];
 
-    if rotors.contains(&s) {
+    if modes.contains(&s) {
         return Ok(s.to_string());
     }
 
-    Err(format!("Must be one of {:?}", rotors))
+    Err(format!("Must be one of {:?}", modes))
+}
+
+fn input_policy_parser(s: &str) -> Result<String, String> {
+    let policies = ["Error", "Fold", "Strip", "PassThrough"];
+
+    if policies.contains(&s) {
+        return Ok(s.to_string());
+    }
+
+    Err(format!("Must be one of {:?}", policies))
+}
+
+fn convention_parser(s: &str) -> Result<String, String> {
+    let conventions = ["Army", "Navy"];
+
+    if conventions.contains(&s) {
+        return Ok(s.to_string());
+    }
+
+    Err(format!("Must be one of {:?}", conventions))
+}
+
+fn key_sheet_format_parser(s: &str) -> Result<String, String> {
+    let formats = ["Text", "CSV", "JSON"];
+
+    if formats.contains(&s) {
+        return Ok(s.to_string());
+    }
+
+    Err(format!("Must be one of {:?}", formats))
+}
+
+fn time_parser(s: &str) -> Result<String, String> {
+    if s.len() != 4 || !s.chars().all(|c| c.is_ascii_digit()) {
+        return Err("Invalid time. Expected 4 digits such as 1840.".to_string());
+    }
+
+    Ok(s.to_string())
+}
+
+fn kenngruppe_parser(s: &str) -> Result<String, String> {
+    if s.len() != 3 || !s.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err("Invalid Kenngruppe. Expected 3 upper case letters.".to_string());
+    }
+
+    Ok(s.to_string())
 }
 
 fn key_parser(s: &str) -> Result<String, String> {
-    if s.len() > 3 {
-        return Err(format!("Too many key values. 3 keys are required."));
+    if s.len() > 4 {
+        return Err("Too many key values. 3 keys are required, or 4 with a greek wheel.".to_string());
     }
 
     if s.len() < 3 {
-        return Err(format!("Not enough key values. 3 keys are required."));
+        return Err("Not enough key values. 3 keys are required, or 4 with a greek wheel.".to_string());
     }
 
     Ok(s.to_string())
@@ -66,27 +300,154 @@ fn key_parser(s: &str) -> Result<String, String> {
 
 fn plug_parser(s: &str) -> Result<String, String> {
     if s.len() != 2 {
-        return Err(format!("Not a valid plug pair."))
+        return Err("Not a valid plug pair.".to_string())
     }
 
     Ok(s.to_string())
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    
-    println!("{:?}", args);
+// Check a part is either built in or in the catalog, listing all the valid names if not.
+fn check_name(kind: &str, name: &str, built_in: &[&str], catalog: &[&str]) -> Result<()> {
+    if built_in.contains(&name) || catalog.contains(&name) {
+        return Ok(());
+    }
 
-    let reflector = Reflector::get_reflector_type_from_string(&args.reflector)?;
-    let mut rotors: Vec<Rotor> = Vec::new();
+    let names: Vec<&str> = built_in.iter().chain(catalog.iter()).copied().collect();
+    Err(anyhow!("Invalid {} {}. Must be one of {:?}", kind, name, names))
+}
+
+fn list(catalog: &Catalog) {
+    println!("Models: {}", MODELS.iter().chain(catalog.get_model_names().iter()).copied().collect::<Vec<&str>>().join(", "));
+    println!("Reflectors: {}", REFLECTORS.iter().chain(catalog.get_reflector_names().iter()).copied().collect::<Vec<&str>>().join(", "));
+    println!("Entry wheels: {}", ENTRY_WHEELS.iter().chain(catalog.get_entry_wheel_names().iter()).copied().collect::<Vec<&str>>().join(", "));
+    println!("Rotors: {}", ROTORS.iter().chain(catalog.get_rotor_names().iter()).copied().collect::<Vec<&str>>().join(", "));
+
+    for name in catalog.get_model_names() {
+        let model = catalog.get_model(name).unwrap();
+
+        println!();
+        println!("{}", model.name);
+        println!("  Rotors: {}", model.rotors.join(", "));
+        println!("  Reflectors: {}", model.reflectors.join(", "));
+        println!("  Entry wheel: {}", model.entry_wheel.as_deref().unwrap_or("Military"));
+        println!("  Plugboard: {}", if model.plugboard { "Yes" } else { "No" });
+        println!("  Stepping: {}", model.stepping.as_deref().unwrap_or("DoubleStep"));
+    }
+}
+
+// Set up the machine for the day picked from the key sheets.
+fn new_enigma_from_sheets(args: &Args) -> Result<Enigma> {
+    let mut sheets = args.sheet.iter().map(KeySheet::load).collect::<Result<Vec<KeySheet>>>()?;
+
+    if let Some(network) = &args.network {
+        sheets.retain(|s| s.network.as_ref().is_some_and(|n| n.eq_ignore_ascii_case(network)));
+
+        if sheets.is_empty() {
+            return Err(anyhow!("There is no key sheet for the network {}.", network));
+        }
+    }
+
+    let (sheet, day) = match (&args.find_kenngruppe, args.day, &args.date) {
+        (Some(kenngruppe), _, _) => match KeySheet::find_kenngruppe_in(&sheets, kenngruppe) {
+            Some(found) => found,
+            None => return Err(anyhow!("The Kenngruppe {} is not on any of the key sheets.", kenngruppe)),
+        },
+        (None, day, date) => {
+            if sheets.len() > 1 {
+                return Err(anyhow!("More than one key sheet is loaded. Pick the network with --network."));
+            }
+
+            let sheet = &sheets[0];
+
+            let day = match (day, date) {
+                (Some(day), _) => sheet.get_day(day).ok_or_else(|| anyhow!("There is no key for day {} on the key sheet.", day))?,
+                (None, Some(date)) => sheet.get_date(date)?,
+                (None, None) => return Err(anyhow!("Pick the day on the key sheet with --day, --date or --find-kenngruppe.")),
+            };
+
+            (sheet, day)
+        },
+    };
+
+    let settings = day.get_settings(sheet.reflector, args.key.as_deref().unwrap_or("AAA"));
+    println!("Key sheet {} day {}: {}", sheet.network.as_deref().unwrap_or("-"), day.day, settings);
+
+    settings.build()
+}
+
+// Set up the machine from the individual settings, looking up any parts by name in the catalog first.
+fn new_enigma(args: &Args, catalog: &Catalog) -> Result<Enigma> {
+    // Clap makes sure these are present unless listing or given the settings.
+    let reflector_name = args.reflector.as_deref().unwrap();
+    let key = args.key.as_deref().unwrap();
+
+    check_name("reflector", reflector_name, &REFLECTORS, &catalog.get_reflector_names())?;
+
+    for rotor in &args.rotors {
+        check_name("rotor", rotor, &ROTORS, &catalog.get_rotor_names())?;
+    }
+
+    if let Some(model) = &args.model {
+        check_name("model", model, &MODELS, &catalog.get_model_names())?;
+    }
+
+    // A reflector from the catalog takes precedence over a built in one of the same name.
+    let catalog_reflector = if catalog.has_reflector(reflector_name) { Some(catalog.get_reflector(reflector_name)?) } else { None };
+
+    // Only the UKW-D can be rewired.
+    let reflector_wiring = match catalog_reflector {
+        Some(reflector) => Some(reflector),
+        None if Reflector::get_reflector_type_from_string(reflector_name)? == ReflectorType::D => {
+            if args.reflector_plugs.is_empty() {
+                return Err(anyhow!("The reflector D requires its 12 plug connections."));
+            }
+
+            let mut pairs: Vec<[char; 2]> = Vec::new();
+
+            for plug in &args.reflector_plugs {
+                let mut pair = plug.chars();
+                pairs.push([pair.next().unwrap(), pair.next().unwrap()]);
+            }
+
+            let notation = Reflector::get_reflector_notation_from_string(&args.reflector_notation)?;
+            Some(Reflector::with_plugging(&pairs, notation)?)
+        },
+        None => None,
+    };
+
+    if !args.reflector_plugs.is_empty() && reflector_wiring.as_ref().and_then(|r| r.get_reflector_type()) != Some(ReflectorType::D) {
+        return Err(anyhow!("Only the reflector D can be rewired."));
+    }
+
+    // With a greek wheel its ring setting and key come first, ahead of the three rotors.
+    let wheel_count = if args.greek.is_some() { 4 } else { 3 };
+
+    if args.rings.len() != wheel_count {
+        return Err(anyhow!("Expected {} ring settings, got {}.", wheel_count, args.rings.len()));
+    }
+
+    if key.len() != wheel_count {
+        return Err(anyhow!("Expected {} key values, got {}.", wheel_count, key.len()));
+    }
+
+    let offset = wheel_count - 3;
+    let mut rotor_settings: Vec<(&str, char, usize)> = Vec::new();
 
     for i in 0..args.rotors.len() {
+        let position = key.chars().nth(i + offset).unwrap();
+        let ring = args.rings[i + offset] as usize;
+
+        rotor_settings.push((&args.rotors[i], position, ring));
+    }
+
+    let mut rotors: Vec<Rotor> = Vec::new();
 
-        let rotor_type = Rotor::get_rotor_type_from_string(&args.rotors[i])?;
-        let position = args.key.chars().nth(i).unwrap();
-        let ring = args.rings[i] as usize;
+    for (name, position, ring) in &rotor_settings {
+        let rotor = match catalog.has_rotor(name) {
+            true => catalog.get_rotor(name, *position, *ring)?,
+            false => Rotor::new(Rotor::get_rotor_type_from_string(name)?, *position, *ring)?,
+        };
 
-        let rotor = Rotor::new(rotor_type, position, ring)?;
         rotors.push(rotor);
     }
 
@@ -97,12 +458,190 @@ fn main() -> Result<()> {
         plugs.push([pair.next().unwrap(), pair.next().unwrap()]);
     }
 
-    let plugboard = Plugboard::new(&plugs)?;
-    let mut enigma = Enigma::new(reflector, rotors, plugboard);
+    let plugboard = match args.uhr {
+        Some(uhr) => Plugboard::with_uhr(&plugs, uhr as usize)?,
+        None => Plugboard::new(&plugs)?,
+    };
+
+    let greek_wheel = match &args.greek {
+        Some(greek) => {
+            let greek_wheel_type = GreekWheel::get_greek_wheel_type_from_string(greek)?;
+            Some(GreekWheel::new(greek_wheel_type, key.chars().next().unwrap(), args.rings[0] as usize)?)
+        },
+        None => None,
+    };
+
+    let reflector = match reflector_wiring {
+        Some(reflector) => reflector,
+        None => Reflector::with_setting(Reflector::get_reflector_type_from_string(reflector_name)?, args.reflector_key, args.reflector_ring as usize)?,
+    };
+
+    let enigma = match &args.model {
+        Some(model) if catalog.has_model(model) => {
+            if greek_wheel.is_some() {
+                return Err(anyhow!("The {} does not take a greek wheel.", model));
+            }
+
+            catalog.new_enigma(model, reflector, &rotor_settings, plugboard)?
+        },
+        Some(model) => {
+            let model = MachineModel::get_machine_model_from_string(model)?;
+            Enigma::new_with_model(model, reflector, greek_wheel, rotors, plugboard)?
+        },
+        None => match (greek_wheel, reflector.get_reflector_type()) {
+            (Some(greek_wheel), Some(reflector_type)) => Enigma::new_m4(reflector_type, greek_wheel, rotors, plugboard)?,
+            (Some(_), None) => return Err(anyhow!("Invalid reflector {} for the M4. Must be ThinB or ThinC.", reflector_name)),
+            (None, _) => Enigma::new_with_reflector(reflector, rotors, plugboard),
+        },
+    };
+
+    Ok(enigma)
+}
+
+// Seeded random numbers always come out the same, for reproducible key sheets and traffic.
+fn get_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+// Runs a message procedure over everything on stdin, rather than encrypting it a line at a time.
+fn run_command(command: &Command, rng: &mut StdRng, enigma: &mut Enigma, prepare: Option<PlaintextConvention>, prettify: Option<PlaintextConvention>) -> Result<()> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+
+    match command {
+        Command::Send { time, kenngruppen, part_length, naval } => {
+            let kenngruppen: Vec<&str> = kenngruppen.iter().map(|k| k.as_str()).collect();
+
+            let plaintext = match prepare {
+                Some(convention) => convention.prepare(&input),
+                None => input,
+            };
+
+            match (&naval.bigram_table, &naval.grundstellung, time) {
+                (Some(path), Some(grundstellung), _) => {
+                    let procedure = NavalIndicator::new(grundstellung, &kenngruppen, BigramTable::load(path)?)?;
+
+                    println!("{}", procedure.encrypt(rng, enigma, &plaintext)?);
+                },
+                (_, _, Some(time)) => {
+                    let mut procedure = ChosenIndicator::new(&kenngruppen)?;
+                    procedure.set_part_length(*part_length as usize)?;
+
+                    for message in procedure.encrypt(rng, enigma, time, &plaintext)? {
+                        println!("{}\n", message);
+                    }
+                },
+                _ => return Err(anyhow!("A time is needed for the header of the message.")),
+            }
+        },
+        Command::Receive { naval } => {
+            let parts = match (&naval.bigram_table, &naval.grundstellung) {
+                (Some(path), Some(grundstellung)) => {
+                    let procedure = NavalIndicator::new(grundstellung, &[], BigramTable::load(path)?)?;
+                    let received = procedure.decrypt(enigma, &input)?;
+
+                    if received.has_mismatch() {
+                        println!("The indicator repeated at the end of the message does not match the start.");
+                    }
+
+                    vec![received]
+                },
+                _ => ChosenIndicator::new(&[])?.decrypt(enigma, &input)?,
+            };
+
+            for (i, received) in parts.iter().enumerate() {
+                println!("Part {}: message key {}, Kenngruppe {}", i + 1, received.message_key, received.kenngruppe.as_deref().unwrap_or("none"));
+            }
+
+            // The parts are put back together first, as a word or number can be split between them.
+            let text = parts.iter().map(|r| r.text.as_str()).collect::<String>();
+
+            match prettify {
+                Some(convention) => println!("{}", convention.prettify(&text)),
+                None => println!("{}", text),
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let catalog = match &args.catalog {
+        Some(path) => Catalog::load(path)?,
+        None => Catalog::default(),
+    };
+
+    if args.list {
+        list(&catalog);
+        return Ok(());
+    }
+
+    if let Some(format) = &args.key_sheet {
+        let rotors = [RotorType::I, RotorType::II, RotorType::III, RotorType::IV, RotorType::V];
+
+        let sheet = KeySheet::generate(&mut get_rng(args.seed), ReflectorType::B, &rotors, args.days)?;
+
+        match format.as_str() {
+            "CSV" => print!("{}", sheet.to_csv()),
+            "JSON" => println!("{}", sheet.to_json()?),
+            _ => print!("{}", sheet),
+        }
+
+        return Ok(());
+    }
+
+    if let Some(entry_wheel) = &args.entry_wheel {
+        check_name("entry wheel", entry_wheel, &ENTRY_WHEELS, &catalog.get_entry_wheel_names())?;
+    }
+
+    let mut enigma = match &args.settings {
+        Some(settings) => settings.parse::<MachineSettings>()?.build()?,
+        None if !args.sheet.is_empty() => new_enigma_from_sheets(&args)?,
+        None => new_enigma(&args, &catalog)?,
+    };
+
+    if let Some(entry_wheel) = &args.entry_wheel {
+        let entry_wheel = match catalog.has_entry_wheel(entry_wheel) {
+            true => catalog.get_entry_wheel(entry_wheel)?,
+            false => EntryWheel::new(EntryWheel::get_entry_wheel_type_from_string(entry_wheel)?),
+        };
+
+        enigma.set_entry_wheel(entry_wheel);
+    }
+
+    if let Some(stepping) = &args.stepping {
+        enigma.set_stepping_mode(Enigma::get_stepping_mode_from_string(stepping)?);
+    }
+
+    enigma.set_input_policy(Enigma::get_input_policy_from_string(&args.input_policy)?, args.preserve_case);
+
+    let prepare = args.prepare.as_deref().map(PlaintextConvention::get_plaintext_convention_from_string).transpose()?;
+    let prettify = args.prettify.as_deref().map(PlaintextConvention::get_plaintext_convention_from_string).transpose()?;
+
+    let group_format = match args.group_size {
+        Some(group_size) => Some(GroupFormat::new(group_size as usize, args.groups_per_line as usize, args.line_numbers)?),
+        None => None,
+    };
+
+    if let Some(command) = &args.command {
+        return run_command(command, &mut get_rng(args.seed), &mut enigma, prepare, prettify);
+    }
+
+    // Whether the next group of traffic is a Kenngruppe, which is the case at the start and after every header.
+    let mut awaiting_kenngruppe = args.kenngruppe;
 
     let stdin = io::stdin();
     let mut buffer = String::new();
 
+    // The number of letters in each line typed so far, so 'undo' can step the rotors back over the last one.
+    let mut history: Vec<usize> = Vec::new();
+
     loop {
         print!(">");
 
@@ -114,9 +653,56 @@ fn main() -> Result<()> {
             break;
         }
 
-        let output = enigma.encrypt(buffer.trim())?;
-        println!("{}", output);
+        if buffer.trim().eq("undo") {
+            if let Some(count) = history.pop() {
+                enigma.rewind(count)?;
+            }
+
+            println!("{}", enigma.get_keys().iter().collect::<String>());
+
+            buffer.clear();
+            continue;
+        }
+
+        let mut line = match prepare {
+            Some(convention) => convention.prepare(buffer.trim()),
+            None => buffer.trim().to_string(),
+        };
+
+        if args.traffic {
+            let mut message = Message::parse(&line, false)?;
+
+            if args.kenngruppe && (awaiting_kenngruppe || !message.header.is_empty()) {
+                awaiting_kenngruppe = !message.take_kenngruppe();
+            }
+
+            if !message.header.is_empty() {
+                println!("{}", message.header.join(" = "));
+            }
+
+            if let Some(kenngruppe) = &message.kenngruppe {
+                println!("Kenngruppe {}", kenngruppe);
+            }
+
+            line = message.get_text();
+
+            // A line of nothing but header has nothing to decrypt.
+            if line.is_empty() {
+                buffer.clear();
+                continue;
+            }
+        }
+
+        let output = enigma.encrypt(&line)?;
+
+        match (prettify, group_format) {
+            (Some(convention), _) => println!("{}", convention.prettify(&output)),
+            (None, Some(format)) => println!("{}", format.format(&output)),
+            (None, None) => println!("{}", output),
+        }
 
+        // Only letters press a key, whatever happens to the rest of the line.
+        history.push(line.chars().filter(|c| c.is_ascii_alphabetic()).count());
         buffer.clear();
     }
 
@@ -0,0 +1,228 @@
+use crate::rotor::{Rotor, RotorType};
+use crate::reflector::{Reflector, ReflectorType};
+use crate::greek_wheel::GreekWheel;
+use crate::entry_wheel::EntryWheelType;
+use crate::plugboard::Plugboard;
+use crate::error::Error;
+use crate::SteppingMode;
+use anyhow::{Context, Result};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MachineModel {
+    // Wehrmacht and Luftwaffe Enigma I.
+    EnigmaI,
+    // Kriegsmarine three rotor M3.
+    M3,
+    // Kriegsmarine four rotor M4.
+    M4,
+    // Commercial Enigma D.
+    EnigmaD,
+    // Commercial Enigma K.
+    EnigmaK,
+    // Swiss Enigma K.
+    SwissK,
+    // Abwehr Enigma G-312.
+    EnigmaG,
+    // Reichsbahn (Rocket) Enigma.
+    Railway,
+    // Japanese-German Enigma T (Tirpitz).
+    EnigmaT,
+}
+
+impl MachineModel {
+    pub fn get_machine_model_from_string(machine_model: &str) -> Result<MachineModel> {
+        let lower = machine_model.to_ascii_lowercase();
+
+        let m = match lower.as_str() {
+            "enigmai" => MachineModel::EnigmaI,
+            "m3" => MachineModel::M3,
+            "m4" => MachineModel::M4,
+            "enigmad" => MachineModel::EnigmaD,
+            "enigmak" => MachineModel::EnigmaK,
+            "swissk" => MachineModel::SwissK,
+            "enigmag" => MachineModel::EnigmaG,
+            "railway" => MachineModel::Railway,
+            "enigmat" => MachineModel::EnigmaT,
+            _ => return Err(Error::ModelError).with_context(|| { format!("Invalid machine model {}.", machine_model) }),
+        };
+
+        Ok(m)
+    }
+
+    pub fn get_rotor_types(&self) -> &'static [RotorType] {
+        match self {
+            MachineModel::EnigmaI => &[RotorType::I, RotorType::II, RotorType::III, RotorType::IV, RotorType::V],
+            MachineModel::M3 | MachineModel::M4 => &[RotorType::I, RotorType::II, RotorType::III, RotorType::IV, RotorType::V, RotorType::VI, RotorType::VII, RotorType::VIII],
+            MachineModel::EnigmaD | MachineModel::EnigmaK => &[RotorType::ID, RotorType::IID, RotorType::IIID],
+            MachineModel::SwissK => &[RotorType::IK, RotorType::IIK, RotorType::IIIK],
+            MachineModel::EnigmaG => &[RotorType::IG, RotorType::IIG, RotorType::IIIG],
+            MachineModel::Railway => &[RotorType::IR, RotorType::IIR, RotorType::IIIR],
+            MachineModel::EnigmaT => &[RotorType::IT, RotorType::IIT, RotorType::IIIT, RotorType::IVT, RotorType::VT, RotorType::VIT, RotorType::VIIT, RotorType::VIIIT],
+        }
+    }
+
+    pub fn get_reflector_types(&self) -> &'static [ReflectorType] {
+        match self {
+            MachineModel::EnigmaI => &[ReflectorType::A, ReflectorType::B, ReflectorType::C, ReflectorType::D],
+            MachineModel::M3 => &[ReflectorType::B, ReflectorType::C],
+            MachineModel::M4 => &[ReflectorType::ThinB, ReflectorType::ThinC],
+            MachineModel::EnigmaD | MachineModel::EnigmaK | MachineModel::SwissK => &[ReflectorType::Commercial],
+            MachineModel::EnigmaG => &[ReflectorType::G],
+            MachineModel::Railway => &[ReflectorType::Railway],
+            MachineModel::EnigmaT => &[ReflectorType::T],
+        }
+    }
+
+    pub fn get_entry_wheel_type(&self) -> EntryWheelType {
+        match self {
+            MachineModel::EnigmaI | MachineModel::M3 | MachineModel::M4 => EntryWheelType::Military,
+            MachineModel::EnigmaD | MachineModel::EnigmaK | MachineModel::SwissK | MachineModel::EnigmaG | MachineModel::Railway => EntryWheelType::Commercial,
+            MachineModel::EnigmaT => EntryWheelType::Tirpitz,
+        }
+    }
+
+    pub fn get_stepping_mode(&self) -> SteppingMode {
+        match self {
+            // The G is driven by gears, so there is no double step.
+            MachineModel::EnigmaG => SteppingMode::Odometer,
+            _ => SteppingMode::DoubleStep,
+        }
+    }
+
+    pub fn has_plugboard(&self) -> bool {
+        matches!(self, MachineModel::EnigmaI | MachineModel::M3 | MachineModel::M4)
+    }
+
+    pub fn has_greek_wheel(&self) -> bool {
+        *self == MachineModel::M4
+    }
+
+    pub fn has_settable_reflector(&self) -> bool {
+        matches!(self, MachineModel::EnigmaD | MachineModel::EnigmaK | MachineModel::SwissK | MachineModel::EnigmaG | MachineModel::Railway | MachineModel::EnigmaT)
+    }
+
+    // Check that all the components could actually be fitted to this model.
+    pub(crate) fn validate(&self, reflector: &Reflector, greek_wheel: &Option<GreekWheel>, rotors: &[Rotor], plugboard: &Plugboard) -> Result<()> {
+        match reflector.get_reflector_type() {
+            Some(t) if self.get_reflector_types().contains(&t) => (),
+            t => return Err(Error::ReflectorError).with_context(|| { format!("Invalid reflector {:?} for the {:?}. Must be one of {:?}.", t, self, self.get_reflector_types()) }),
+        }
+
+        if reflector.is_set() && !self.has_settable_reflector() {
+            return Err(Error::ReflectorError).with_context(|| { format!("The reflector of the {:?} cannot be set.", self) });
+        }
+
+        if greek_wheel.is_some() != self.has_greek_wheel() {
+            return Err(Error::GreekWheelError).with_context(|| {
+                if self.has_greek_wheel() {
+                    format!("The {:?} requires a greek wheel.", self)
+                }
+                else {
+                    format!("The {:?} does not take a greek wheel.", self)
+                }
+            });
+        }
+
+        if rotors.len() != 3 {
+            return Err(Error::RotorError).with_context(|| { format!("Invalid number of rotors {} for the {:?}. Must be 3.", rotors.len(), self) });
+        }
+
+        for rotor in rotors {
+            match rotor.get_rotor_type() {
+                Some(t) if self.get_rotor_types().contains(&t) => (),
+                t => return Err(Error::RotorError).with_context(|| { format!("Invalid rotor {:?} for the {:?}. Must be one of {:?}.", t, self, self.get_rotor_types()) }),
+            }
+        }
+
+        if !self.has_plugboard() && !plugboard.get_plugs().is_empty() {
+            return Err(Error::PlugboardError).with_context(|| { format!("The {:?} does not have a plugboard.", self) });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_machine_model_from_string() {
+        assert_eq!(MachineModel::EnigmaI, MachineModel::get_machine_model_from_string("EnigmaI").unwrap());
+        assert_eq!(MachineModel::EnigmaI, MachineModel::get_machine_model_from_string("enigmai").unwrap());
+
+        assert_eq!(MachineModel::M3, MachineModel::get_machine_model_from_string("M3").unwrap());
+        assert_eq!(MachineModel::M4, MachineModel::get_machine_model_from_string("m4").unwrap());
+
+        assert_eq!(MachineModel::EnigmaD, MachineModel::get_machine_model_from_string("EnigmaD").unwrap());
+        assert_eq!(MachineModel::EnigmaK, MachineModel::get_machine_model_from_string("enigmak").unwrap());
+        assert_eq!(MachineModel::SwissK, MachineModel::get_machine_model_from_string("SwissK").unwrap());
+        assert_eq!(MachineModel::EnigmaG, MachineModel::get_machine_model_from_string("EnigmaG").unwrap());
+        assert_eq!(MachineModel::Railway, MachineModel::get_machine_model_from_string("Railway").unwrap());
+        assert_eq!(MachineModel::EnigmaT, MachineModel::get_machine_model_from_string("enigmat").unwrap());
+    }
+
+    #[test]
+    fn test_get_machine_model_from_string_invalid() {
+        assert!(MachineModel::get_machine_model_from_string("blah").is_err());
+    }
+
+    #[test]
+    fn test_machine_model_validate() {
+        let rotors = vec![
+            Rotor::new(RotorType::IK, 'A', 1).unwrap(),
+            Rotor::new(RotorType::IIK, 'A', 1).unwrap(),
+            Rotor::new(RotorType::IIIK, 'A', 1).unwrap(),
+        ];
+
+        let reflector = Reflector::with_setting(ReflectorType::Commercial, 'Q', 1).unwrap();
+        let plugboard = Plugboard::new(&[]).unwrap();
+
+        assert!(MachineModel::SwissK.validate(&reflector, &None, &rotors, &plugboard).is_ok());
+
+        // Wrong rotors for the model.
+        assert!(MachineModel::EnigmaD.validate(&reflector, &None, &rotors, &plugboard).is_err());
+
+        // Commercial machines have no plugboard.
+        let plugboard = Plugboard::new(&[['A', 'B']]).unwrap();
+        assert!(MachineModel::SwissK.validate(&reflector, &None, &rotors, &plugboard).is_err());
+    }
+
+    #[test]
+    fn test_machine_model_validate_reflector() {
+        let rotors = vec![
+            Rotor::new(RotorType::I, 'A', 1).unwrap(),
+            Rotor::new(RotorType::II, 'A', 1).unwrap(),
+            Rotor::new(RotorType::III, 'A', 1).unwrap(),
+        ];
+
+        let plugboard = Plugboard::new(&[]).unwrap();
+
+        assert!(MachineModel::EnigmaI.validate(&Reflector::new(ReflectorType::A), &None, &rotors, &plugboard).is_ok());
+        assert!(MachineModel::M3.validate(&Reflector::new(ReflectorType::A), &None, &rotors, &plugboard).is_err());
+
+        // Military reflectors cannot be set.
+        let reflector = Reflector::with_setting(ReflectorType::B, 'C', 1).unwrap();
+        assert!(MachineModel::EnigmaI.validate(&reflector, &None, &rotors, &plugboard).is_err());
+    }
+
+    #[test]
+    fn test_machine_model_validate_greek_wheel() {
+        use crate::greek_wheel::GreekWheelType;
+
+        let rotors = vec![
+            Rotor::new(RotorType::VI, 'A', 1).unwrap(),
+            Rotor::new(RotorType::VII, 'A', 1).unwrap(),
+            Rotor::new(RotorType::VIII, 'A', 1).unwrap(),
+        ];
+
+        let reflector = Reflector::new(ReflectorType::ThinC);
+        let plugboard = Plugboard::new(&[]).unwrap();
+
+        assert!(MachineModel::M4.validate(&reflector, &None, &rotors, &plugboard).is_err());
+
+        let greek_wheel = Some(GreekWheel::new(GreekWheelType::Gamma, 'A', 1).unwrap());
+        assert!(MachineModel::M4.validate(&reflector, &greek_wheel, &rotors, &plugboard).is_ok());
+    }
+}
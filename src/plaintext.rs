@@ -0,0 +1,256 @@
+use crate::error::Error;
+use anyhow::{Context, Result};
+
+// The digits spelt out in German, as the Army sent them. Zwo was used in place of zwei so it could not be misheard as
+// drei, and CH is written as Q like everywhere else.
+const ARMY_DIGITS: [&str; 10] = ["NULL", "EINS", "ZWO", "DREI", "VIER", "FUENF", "SEQS", "SIEBEN", "AQT", "NEUN"];
+
+// The Navy sent digits as the letter above them on the top row of the keyboard, between a pair of Ys.
+const NAVY_DIGITS: [char; 10] = ['P', 'Q', 'W', 'E', 'R', 'T', 'Z', 'U', 'I', 'O'];
+
+// The rules the operators followed to turn a message into letters the machine could send.
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PlaintextConvention {
+    // Wehrmacht and Luftwaffe: X for a full stop, Y for a comma and the digits spelt out.
+    Army,
+    // Kriegsmarine: X for a full stop, ZZ for a comma and the digits on the top row of the keyboard.
+    Navy,
+}
+
+impl PlaintextConvention {
+    pub fn get_plaintext_convention_from_string(convention: &str) -> Result<PlaintextConvention> {
+        let lower = convention.to_ascii_lowercase();
+
+        let c = match lower.as_str() {
+            "army" => PlaintextConvention::Army,
+            "navy" => PlaintextConvention::Navy,
+            _ => return Err(Error::InputError).with_context(|| { format!("Invalid plaintext convention {}.", convention) }),
+        };
+
+        Ok(c)
+    }
+
+    fn get_punctuation(&self, c: char) -> Option<&'static str> {
+        let p = match (self, c) {
+            (_, '.') | (_, '!') => "X",
+            (_, ':') => "XX",
+            (_, '?') => "UD",
+            (_, '(') | (_, ')') => "KK",
+            (PlaintextConvention::Army, ',') | (PlaintextConvention::Army, ';') => "Y",
+            (PlaintextConvention::Army, '-') | (PlaintextConvention::Army, '/') => "YY",
+            (PlaintextConvention::Navy, ',') | (PlaintextConvention::Navy, ';') => "ZZ",
+            _ => return None,
+        };
+
+        Some(p)
+    }
+
+    fn push_number(&self, number: &str, result: &mut String) {
+        match self {
+            PlaintextConvention::Army => {
+                for d in number.chars() {
+                    result.push_str(ARMY_DIGITS[d.to_digit(10).unwrap() as usize]);
+                }
+            },
+            PlaintextConvention::Navy => {
+                result.push('Y');
+
+                for d in number.chars() {
+                    result.push(NAVY_DIGITS[d.to_digit(10).unwrap() as usize]);
+                }
+
+                result.push('Y');
+            },
+        }
+    }
+
+    // Turn ordinary German or English text into upper case letters ready to be encrypted. Umlauts and ß are written out,
+    // CH becomes Q, punctuation and numbers follow the convention and anything else, including spaces, is dropped.
+    pub fn prepare(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut number = String::new();
+        let mut chars = text.chars().flat_map(|c| c.to_uppercase()).peekable();
+
+        while let Some(c) = chars.next() {
+            if c.is_ascii_digit() {
+                number.push(c);
+                continue;
+            }
+
+            if !number.is_empty() {
+                self.push_number(&number, &mut result);
+                number.clear();
+            }
+
+            match c {
+                'A'..='Z' if c == 'C' && chars.peek() == Some(&'H') => {
+                    chars.next();
+                    result.push('Q');
+                },
+                'A'..='Z' => result.push(c),
+                'Ä' => result.push_str("AE"),
+                'Ö' => result.push_str("OE"),
+                'Ü' => result.push_str("UE"),
+                'À' | 'Á' | 'Â' => result.push('A'),
+                'È' | 'É' | 'Ê' | 'Ë' => result.push('E'),
+                'Î' | 'Ï' => result.push('I'),
+                'Ô' => result.push('O'),
+                'Ù' | 'Û' => result.push('U'),
+                'Ç' => result.push('C'),
+                _ => {
+                    if let Some(p) = self.get_punctuation(c) {
+                        result.push_str(p);
+                    }
+                },
+            }
+        }
+
+        if !number.is_empty() {
+            self.push_number(&number, &mut result);
+        }
+
+        result
+    }
+
+    // Make a decrypted message easier to read by putting back the punctuation, numbers and CH. The words still run
+    // together and umlauts are left written out, as there is no telling them apart from ordinary letters.
+    pub fn prettify(&self, text: &str) -> String {
+        let letters: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut result = String::with_capacity(letters.len());
+        let mut open_bracket = true;
+        let mut i = 0;
+
+        while i < letters.len() {
+            if let Some((digits, length)) = self.get_number(&letters[i..]) {
+                result.push_str(&digits);
+                i += length;
+                continue;
+            }
+
+            let rest: String = letters[i..(i + 2).min(letters.len())].iter().collect();
+
+            let (replacement, length) = match (self, rest.as_str()) {
+                (_, "XX") => (":", 2),
+                (_, "KK") => {
+                    open_bracket = !open_bracket;
+                    (if open_bracket { ")" } else { "(" }, 2)
+                },
+                (PlaintextConvention::Army, "YY") => ("-", 2),
+                (PlaintextConvention::Navy, "ZZ") => (", ", 2),
+                (PlaintextConvention::Army, r) if r.starts_with('Y') => (", ", 1),
+                (_, r) if r.starts_with('X') => (". ", 1),
+                (_, r) if r.starts_with('Q') && r != "QU" => ("CH", 1),
+                _ => {
+                    result.push(letters[i]);
+                    i += 1;
+                    continue;
+                },
+            };
+
+            result.push_str(replacement);
+            i += length;
+        }
+
+        result.trim_end().to_string()
+    }
+
+    // Find a number at the start of the letters, returning its digits and how many letters it took up. For the Army
+    // a single spelt out digit is left alone, as it is more likely to be part of a word such as EINSATZ.
+    fn get_number(&self, letters: &[char]) -> Option<(String, usize)> {
+        let mut digits = String::new();
+        let mut length = 0;
+
+        match self {
+            PlaintextConvention::Army => {
+                'digits: loop {
+                    let rest: String = letters[length..].iter().take(6).collect();
+
+                    // Zwei is read as well as zwo, in case the operator didn't keep to the rules.
+                    for (d, word) in ARMY_DIGITS.iter().chain(["ZWEI"].iter()).enumerate() {
+                        if rest.starts_with(word) {
+                            digits.push(std::char::from_digit(if d == 10 { 2 } else { d as u32 }, 10).unwrap());
+                            length += word.len();
+                            continue 'digits;
+                        }
+                    }
+
+                    break;
+                }
+
+                if digits.len() < 2 {
+                    return None;
+                }
+            },
+            PlaintextConvention::Navy => {
+                if letters.first() != Some(&'Y') {
+                    return None;
+                }
+
+                length = 1;
+
+                while let Some(d) = letters.get(length).and_then(|c| NAVY_DIGITS.iter().position(|n| n == c)) {
+                    digits.push(std::char::from_digit(d as u32, 10).unwrap());
+                    length += 1;
+                }
+
+                if digits.is_empty() || letters.get(length) != Some(&'Y') {
+                    return None;
+                }
+
+                length += 1;
+            },
+        }
+
+        Some((digits, length))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prepare_army() {
+        let army = PlaintextConvention::Army;
+
+        assert_eq!("ANGRIFFUMEINSAQTXXDREINULLUHRYKRAEFTEKKSQWAQKKX", army.prepare("Angriff um 18:30 Uhr, Kräfte (schwach)."));
+        assert_eq!("STRASSEXWEITERBEIZWOZWOSIEBENYYNEUN", army.prepare("Straße. Weiter bei 227-9"));
+        assert_eq!("WHEREISTHEFUELUD", army.prepare("Where is the fuel?"));
+    }
+
+    #[test]
+    fn test_prepare_navy() {
+        let navy = PlaintextConvention::Navy;
+
+        assert_eq!("UBOOTYRTYSTANDORTZZQUADRATXXYIPYX", navy.prepare("U-Boot 45 Standort, Quadrat: 80."));
+        assert_eq!("MAQTEYQOREYUEBERFAHRTX", navy.prepare("Machte 1943 Überfahrt!"));
+    }
+
+    #[test]
+    fn test_prettify_army() {
+        let army = PlaintextConvention::Army;
+
+        assert_eq!("ANGRIFFUM18:30UHR, KRAEFTE(SCHWACH).", army.prettify("ANGRI FFUME INSAQ TXXDR EINUL LUHRY KRAEF TEKKS QWAQK KX"));
+
+        // A single digit on its own is more likely part of a word.
+        assert_eq!("EINSATZ. QUELLE", army.prettify("EINSATZXQUELLE"));
+        assert_eq!("227-90", army.prettify("ZWEIZWOSIEBENYYNEUNNULL"));
+    }
+
+    #[test]
+    fn test_prettify_navy() {
+        let navy = PlaintextConvention::Navy;
+
+        assert_eq!("UBOOT45STANDORT, QUADRAT:80.", navy.prettify("UBOOTYRTYSTANDORTZZQUADRATXXYIPYX"));
+        assert_eq!("MACHTE1943UEBERFAHRT.", navy.prettify(&navy.prepare("Machte 1943 Überfahrt!")));
+    }
+
+    #[test]
+    fn test_get_plaintext_convention_from_string() {
+        assert_eq!(PlaintextConvention::Army, PlaintextConvention::get_plaintext_convention_from_string("Army").unwrap());
+        assert_eq!(PlaintextConvention::Navy, PlaintextConvention::get_plaintext_convention_from_string("navy").unwrap());
+
+        assert!(PlaintextConvention::get_plaintext_convention_from_string("blah").is_err());
+    }
+}
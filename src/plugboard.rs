@@ -2,8 +2,24 @@ use crate::utils;
 use crate::error::Error;
 use anyhow::{Context, Result};
 
+// The 40 contact disc of the Enigma Uhr, mapping a contact on the red (a) side to a contact on the white (b) side.
+const UHR_WIRING: [usize; 40] = [6, 31, 4, 29, 18, 39, 16, 25, 30, 23, 28, 1, 38, 11, 36, 37, 26, 27, 24, 21, 14, 3, 12, 17, 2, 7, 0, 33, 10, 35, 8, 5, 22, 19, 20, 13, 34, 15, 32, 9];
+
+// The red plug of pair i is wired to contacts 4i and 4i + 2, the white plug of pair i to contacts 4n + 2 and 4n where n is
+// taken from this table. In setting 00 every red plug is therefore connected to its own white plug.
+const UHR_WHITE_PLUGS: [usize; 10] = [1, 4, 7, 9, 6, 3, 0, 2, 5, 8];
+
+// The number of cables replaced by the Uhr.
+const UHR_PAIRS: usize = 10;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "PlugboardFields"))]
 pub struct Plugboard {
     plugs: Vec<[usize; 2]>,
+    uhr_setting: Option<usize>,
+    // Maps a key to the entry wheel, towards the rotors.
+    wiring: [usize; 26],
+    // Maps the entry wheel to a lamp, coming back from the rotors.
+    wiring_inverse: [usize; 26],
 }
 
 impl Plugboard {
@@ -31,25 +47,116 @@ impl Plugboard {
             plug_positions.push(p);
         }
 
+        let mut wiring = [0; 26];
+
+        for (i, w) in wiring.iter_mut().enumerate() {
+            *w = i;
+        }
+
+        for p in plug_positions.iter() {
+            wiring[p[0]] = p[1];
+            wiring[p[1]] = p[0];
+        }
+
         let plugboard = Plugboard {
             plugs: plug_positions,
+            uhr_setting: None,
+            wiring,
+            wiring_inverse: wiring,
         };
 
         Ok(plugboard)
     }
 
+    // Create a plugboard with the Enigma Uhr attached in place of the 10 cables. The first letter of each pair takes the
+    // red plug and the second letter the white plug. Apart from in a few settings, such as 00, the substitution is not
+    // reciprocal, so the key going in and the lamp coming back out are mapped differently.
+    pub fn with_uhr(plugs: &[[char; 2]], uhr_setting: usize) -> Result<Plugboard> {
+        if plugs.len() != UHR_PAIRS {
+            return Err(Error::PlugboardError).with_context(|| { format!("Invalid number of plugs {} for the Uhr. Must be {}.", plugs.len(), UHR_PAIRS) });
+        }
+
+        if uhr_setting > 39 {
+            return Err(Error::PlugboardError).with_context(|| { format!("Invalid Uhr setting {}. Must be in the range 0 to 39 (inclusive).", uhr_setting) });
+        }
+
+        let mut plugboard = Plugboard::new(plugs)?;
+        plugboard.uhr_setting = Some(uhr_setting);
+
+        let mut uhr_wiring_inverse = [0; 40];
+
+        for (i, w) in UHR_WIRING.iter().enumerate() {
+            uhr_wiring_inverse[*w] = i;
+        }
+
+        for (i, plug) in plugboard.plugs.iter().enumerate() {
+            // Red plug in, through the disc to a white plug.
+            let contact = (UHR_WIRING[(4 * i + uhr_setting) % 40] + 40 - uhr_setting) % 40;
+            let white = UHR_WHITE_PLUGS.iter().position(|n| 4 * n + 2 == contact).unwrap();
+            plugboard.wiring[plug[0]] = plugboard.plugs[white][1];
+
+            // White plug in, back through the disc to a red plug.
+            let contact = (uhr_wiring_inverse[(4 * UHR_WHITE_PLUGS[i] + uhr_setting) % 40] + 40 - uhr_setting) % 40;
+            plugboard.wiring[plug[1]] = plugboard.plugs[(contact - 2) / 4][0];
+        }
+
+        for (i, w) in plugboard.wiring.iter().enumerate() {
+            plugboard.wiring_inverse[*w] = i;
+        }
+
+        Ok(plugboard)
+    }
+
+    pub fn get_uhr_setting(&self) -> Option<usize> {
+        self.uhr_setting
+    }
+
+    pub fn get_plugs(&self) -> Vec<[char; 2]> {
+        self.plugs.iter()
+            .map(|p| [utils::get_char_from_position(p[0]).unwrap(), utils::get_char_from_position(p[1]).unwrap()])
+            .collect()
+    }
+
+    // Scramble a key on its way into the rotors. For a plugboard without the Uhr this is the same in both directions.
     pub fn scramble(&self, input: usize) -> usize {
-        for plug in self.plugs.iter() {
-            if plug[0] == input {
-                return plug[1];
-            }
+        self.scramble_left(input)
+    }
 
-            if plug[1] == input {
-                return plug[0];
-            }
+    // Scramble a key on its way into the rotors.
+    pub fn scramble_left(&self, input: usize) -> usize {
+        self.wiring[input]
+    }
+
+    // Scramble a contact on its way back out to the lamps.
+    pub fn scramble_right(&self, input: usize) -> usize {
+        self.wiring_inverse[input]
+    }
+}
+
+// The fields of a plugboard as they are read back. The plugboard is built again from the plugs and Uhr setting, so
+// they are checked the same way as when it was first set up.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct PlugboardFields {
+    plugs: Vec<[usize; 2]>,
+    uhr_setting: Option<usize>,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<PlugboardFields> for Plugboard {
+    type Error = anyhow::Error;
+
+    fn try_from(fields: PlugboardFields) -> Result<Plugboard> {
+        let mut plugs: Vec<[char; 2]> = Vec::with_capacity(fields.plugs.len());
+
+        for p in &fields.plugs {
+            plugs.push([utils::get_char_from_position(p[0])?, utils::get_char_from_position(p[1])?]);
         }
 
-        input
+        match fields.uhr_setting {
+            Some(uhr_setting) => Plugboard::with_uhr(&plugs, uhr_setting),
+            None => Plugboard::new(&plugs),
+        }
     }
 }
 
@@ -78,6 +185,69 @@ mod test {
         assert_eq!(25, plugboard.scramble(25)); // Z -> Z
     }
 
+    #[test]
+    fn test_plugboard_scramble_reciprocal() {
+        let plugs = vec![['A', 'G'], ['D', 'M'], ['Y', 'S']];
+        let plugboard = Plugboard::new(&plugs).unwrap();
+
+        for i in 0..26 {
+            assert_eq!(plugboard.scramble_left(i), plugboard.scramble_right(i));
+        }
+
+        assert_eq!(None, plugboard.get_uhr_setting());
+    }
+
+    #[test]
+    fn test_plugboard_uhr_setting_zero() {
+        let plugs = vec![['A', 'V'], ['B', 'S'], ['C', 'G'], ['D', 'L'], ['F', 'U'], ['H', 'Z'], ['I', 'N'], ['K', 'M'], ['O', 'W'], ['R', 'X']];
+        let uhr = Plugboard::with_uhr(&plugs, 0).unwrap();
+        let plugboard = Plugboard::new(&plugs).unwrap();
+
+        // Setting 00 is the same as plugging the cables.
+        for i in 0..26 {
+            assert_eq!(plugboard.scramble_left(i), uhr.scramble_left(i));
+            assert_eq!(plugboard.scramble_right(i), uhr.scramble_right(i));
+        }
+
+        assert_eq!(Some(0), uhr.get_uhr_setting());
+    }
+
+    #[test]
+    fn test_plugboard_uhr_scramble() {
+        let plugs = vec![['A', 'V'], ['B', 'S'], ['C', 'G'], ['D', 'L'], ['F', 'U'], ['H', 'Z'], ['I', 'N'], ['K', 'M'], ['O', 'W'], ['R', 'X']];
+        let uhr = Plugboard::with_uhr(&plugs, 1).unwrap();
+
+        assert_eq!(6, uhr.scramble_left(0)); // A -> G
+        assert_eq!(11, uhr.scramble_left(1)); // B -> L
+        assert_eq!(3, uhr.scramble_left(11)); // L -> D
+        assert_eq!(4, uhr.scramble_left(4)); // E -> E (not plugged)
+
+        // Not reciprocal, but the way back undoes the way in.
+        assert_eq!(1, uhr.scramble_right(11)); // L -> B
+        assert_eq!(11, uhr.scramble_right(3)); // D -> L
+
+        for i in 0..26 {
+            assert_eq!(i, uhr.scramble_right(uhr.scramble_left(i)));
+        }
+    }
+
+    #[test]
+    fn test_plugboard_uhr_invalid() {
+        let mut plugs = vec![['A', 'V'], ['B', 'S'], ['C', 'G'], ['D', 'L'], ['F', 'U'], ['H', 'Z'], ['I', 'N'], ['K', 'M'], ['O', 'W'], ['R', 'X']];
+        assert!(Plugboard::with_uhr(&plugs, 40).is_err());
+
+        plugs.pop();
+        assert!(Plugboard::with_uhr(&plugs, 0).is_err());
+    }
+
+    #[test]
+    fn test_plugboard_get_plugs() {
+        let plugs = vec![['A', 'G'], ['D', 'M'], ['Y', 'S']];
+        let plugboard = Plugboard::new(&plugs).unwrap();
+
+        assert_eq!(plugs, plugboard.get_plugs());
+    }
+
     #[test]
     #[should_panic(expected = "Cannot connect plug A to A!")]
     fn test_plugboard_duplicate_plugs() {
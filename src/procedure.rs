@@ -0,0 +1,635 @@
+use crate::Enigma;
+use crate::bigram::BigramTable;
+use crate::traffic::{GroupFormat, Message};
+use crate::error::Error;
+use crate::utils;
+use anyhow::{Context, Result};
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+// A message as it was deciphered, along with the message key it was sent under.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReceivedMessage {
+    pub message_key: String,
+    // The positions in the message key where the two copies sent in the indicator did not agree, which means the
+    // indicator was garbled or the wrong Grundstellung was used. The first copy is taken as the message key.
+    pub mismatches: Vec<usize>,
+    // The Kenngruppe sent with the message, identifying the key it was sent under.
+    pub kenngruppe: Option<String>,
+    pub text: String,
+}
+
+impl ReceivedMessage {
+    pub fn has_mismatch(&self) -> bool {
+        !self.mismatches.is_empty()
+    }
+}
+
+// The message key procedure in use until May 1940. The rotors are set to the Grundstellung from the key sheet and the
+// message key, chosen by the operator, is typed in twice. The six letters that light up are sent ahead of the message,
+// which is encrypted after turning the rotors to the message key.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DoubledIndicator {
+    grundstellung: String,
+}
+
+impl DoubledIndicator {
+    pub fn new(grundstellung: &str) -> Result<DoubledIndicator> {
+        if grundstellung.is_empty() || !grundstellung.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(Error::InputError).with_context(|| { format!("Invalid Grundstellung {}. Expected upper case letters.", grundstellung) });
+        }
+
+        Ok(DoubledIndicator {
+            grundstellung: grundstellung.to_string(),
+        })
+    }
+
+    pub fn get_grundstellung(&self) -> &str {
+        &self.grundstellung
+    }
+
+    // Encrypt a message, returning the indicator followed by the ciphertext. Without a message key one is picked at
+    // random. The machine is left at the end of the message.
+    pub fn encrypt(&self, enigma: &mut Enigma, message_key: Option<&str>, plaintext: &str) -> Result<String> {
+        let message_key = match message_key {
+            Some(key) => key.to_string(),
+            None => utils::get_random_letters(enigma.get_keys().len()),
+        };
+
+        enigma.set_keys(&self.grundstellung).with_context(|| "Unable to set the Grundstellung.")?;
+        let indicator = enigma.encrypt(&message_key.repeat(2)).with_context(|| { format!("Invalid message key {}.", message_key) })?;
+
+        enigma.set_keys(&message_key).with_context(|| "Unable to set the message key.")?;
+        let ciphertext = enigma.encrypt(plaintext)?;
+
+        Ok(indicator + &ciphertext)
+    }
+
+    // Decrypt a transmission that starts with the doubled indicator. Whitespace between groups is ignored.
+    pub fn decrypt(&self, enigma: &mut Enigma, transmission: &str) -> Result<ReceivedMessage> {
+        let letters: String = transmission.chars().filter(|c| !c.is_whitespace()).collect();
+        let length = enigma.get_keys().len();
+
+        if letters.chars().count() < length * 2 {
+            return Err(Error::InputError).with_context(|| { format!("Transmission is too short for a doubled indicator of {} letters.", length * 2) });
+        }
+
+        // An indicator of upper case letters is split where it ends, and so is the message key it decrypts to, whatever
+        // the machine does with other input.
+        if !letters.chars().take(length * 2).all(|c| c.is_ascii_uppercase()) {
+            return Err(Error::InputError).with_context(|| { format!("Indicator is not upper case ASCII: {}", letters.chars().take(length * 2).collect::<String>()) });
+        }
+
+        let (indicator, ciphertext) = letters.split_at(length * 2);
+
+        enigma.set_keys(&self.grundstellung).with_context(|| "Unable to set the Grundstellung.")?;
+        let doubled = enigma.encrypt(indicator).with_context(|| { format!("Invalid indicator {}.", indicator) })?;
+
+        let (message_key, repeat) = doubled.split_at(length);
+        let mismatches = message_key.chars().zip(repeat.chars()).enumerate().filter(|(_, (a, b))| a != b).map(|(i, _)| i).collect();
+
+        enigma.set_keys(message_key)?;
+        let text = enigma.encrypt(ciphertext)?;
+
+        Ok(ReceivedMessage {
+            message_key: message_key.to_string(),
+            mismatches,
+            kenngruppe: None,
+            text,
+        })
+    }
+}
+
+// The longest part sent under one message key, in letters, before a message is split into parts (Teile).
+const PART_LENGTH: usize = 250;
+
+// The message key procedure from May 1940 for the Army and Luftwaffe. The operator picks a Grundstellung of their own
+// and sends it in the clear in the header, followed by the message key encrypted once at that Grundstellung. The
+// message starts with a group of two random letters and one of the Kenngruppen for the day from the key sheet, which
+// tells the receiver which key to use. Long messages are split into parts, each with its own header and keys.
+//
+// The header reads "1840 = 2TLE 1TL = 179 = WXC KCH =", that is the time, the part of how many parts (left out for a
+// message in one part), the letter count including the Kenngruppe group, and the Grundstellung and encrypted key.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChosenIndicator {
+    kenngruppen: Vec<String>,
+    part_length: usize,
+}
+
+impl ChosenIndicator {
+    pub fn new(kenngruppen: &[&str]) -> Result<ChosenIndicator> {
+        if let Some(k) = kenngruppen.iter().find(|k| k.len() != 3 || !k.chars().all(|c| c.is_ascii_uppercase())) {
+            return Err(Error::InputError).with_context(|| { format!("Invalid Kenngruppe {}. Expected 3 upper case letters.", k) });
+        }
+
+        Ok(ChosenIndicator {
+            kenngruppen: kenngruppen.iter().map(|k| k.to_string()).collect(),
+            part_length: PART_LENGTH,
+        })
+    }
+
+    pub fn get_kenngruppen(&self) -> &[String] {
+        &self.kenngruppen
+    }
+
+    pub fn set_part_length(&mut self, part_length: usize) -> Result<()> {
+        if part_length == 0 {
+            return Err(Error::InputError).with_context(|| "Invalid part length 0.");
+        }
+
+        self.part_length = part_length;
+        Ok(())
+    }
+
+    // Encrypt a message for sending at the given time, such as "1840", splitting it into parts as needed. Every part
+    // gets a random Grundstellung, message key and Kenngruppe group, picked with the given random number generator.
+    pub fn encrypt<R: Rng>(&self, rng: &mut R, enigma: &mut Enigma, time: &str, plaintext: &str) -> Result<Vec<Message>> {
+        let letters: Vec<char> = plaintext.chars().filter(|c| !c.is_whitespace()).collect();
+        let parts: Vec<String> = letters.chunks(self.part_length).map(|p| p.iter().collect()).collect();
+        let length = enigma.get_keys().len();
+
+        if parts.is_empty() {
+            return Err(Error::InputError).with_context(|| "No message to encrypt.");
+        }
+
+        parts.iter().enumerate()
+            .map(|(i, part)| {
+                let numbering = match parts.len() {
+                    1 => None,
+                    n => Some((i + 1, n)),
+                };
+
+                let grundstellung = utils::get_random_letters_from(rng, length);
+                let message_key = utils::get_random_letters_from(rng, length);
+
+                self.encrypt_part(rng, enigma, time, numbering, &grundstellung, &message_key, part)
+            })
+            .collect()
+    }
+
+    // Encrypt one part of a message at a chosen Grundstellung and message key. The part is numbered as part of how many,
+    // or None for a message sent in one part.
+    #[allow(clippy::too_many_arguments)]
+    pub fn encrypt_part<R: Rng>(&self, rng: &mut R, enigma: &mut Enigma, time: &str, part: Option<(usize, usize)>, grundstellung: &str, message_key: &str, plaintext: &str) -> Result<Message> {
+        if time.len() != 4 || !time.chars().all(|c| c.is_ascii_digit()) {
+            return Err(Error::InputError).with_context(|| { format!("Invalid time {}. Expected 4 digits such as 1840.", time) });
+        }
+
+        // Which Kenngruppe is used is up to the operator, so it is picked at random along with the two letters before it.
+        let choice = match self.kenngruppen.choose(rng) {
+            Some(k) => k,
+            None => return Err(Error::InputError).with_context(|| "No Kenngruppen to choose from."),
+        };
+
+        let kenngruppe = format!("{}{}", utils::get_random_letters_from(rng, 2), choice);
+
+        enigma.set_keys(grundstellung).with_context(|| "Unable to set the Grundstellung.")?;
+        let encrypted_key = enigma.encrypt(message_key).with_context(|| { format!("Invalid message key {}.", message_key) })?;
+
+        enigma.set_keys(message_key).with_context(|| "Unable to set the message key.")?;
+        let ciphertext = enigma.encrypt(plaintext)?;
+
+        let mut header = vec![time.to_string()];
+
+        if let Some((part, parts)) = part {
+            header.push(format!("{}TLE {}TL", parts, part));
+        }
+
+        header.push((ciphertext.chars().filter(|c| !c.is_whitespace()).count() + kenngruppe.len()).to_string());
+        header.push(format!("{} {}", grundstellung, encrypted_key));
+
+        let format = GroupFormat::army();
+
+        Ok(Message {
+            header,
+            kenngruppe: Some(kenngruppe),
+            groups: format.format(&ciphertext).split_whitespace().map(|g| g.to_string()).collect(),
+            format,
+        })
+    }
+
+    // Decrypt received traffic, which can hold several parts one after the other, returning each part in turn.
+    pub fn decrypt(&self, enigma: &mut Enigma, traffic: &str) -> Result<Vec<ReceivedMessage>> {
+        let messages = Message::parse_all(traffic, true)?;
+
+        if messages.is_empty() {
+            return Err(Error::InputError).with_context(|| "No message to decrypt.");
+        }
+
+        messages.iter().map(|m| self.decrypt_part(enigma, m)).collect()
+    }
+
+    // Decrypt one part, taking the Grundstellung and encrypted key from the last field of its header.
+    pub fn decrypt_part(&self, enigma: &mut Enigma, message: &Message) -> Result<ReceivedMessage> {
+        let length = enigma.get_keys().len();
+        let indicator: Vec<&str> = message.header.last().map(|h| h.split_whitespace().collect()).unwrap_or_default();
+
+        if indicator.len() != 2 || indicator.iter().any(|i| i.len() != length) {
+            return Err(Error::InputError).with_context(|| { format!("Missing indicator in header {:?}. Expected the Grundstellung and encrypted key as the last field.", message.header) });
+        }
+
+        enigma.set_keys(indicator[0]).with_context(|| { format!("Invalid Grundstellung {}.", indicator[0]) })?;
+        let message_key = enigma.encrypt(indicator[1]).with_context(|| { format!("Invalid encrypted key {}.", indicator[1]) })?;
+
+        enigma.set_keys(&message_key)?;
+        let text = enigma.encrypt(&message.get_text())?;
+
+        // Only the last three letters of the group are the Kenngruppe, the first two are padding.
+        let kenngruppe = message.kenngruppe.as_ref().map(|k| k.chars().skip(k.chars().count().saturating_sub(3)).collect());
+
+        Ok(ReceivedMessage {
+            message_key,
+            mismatches: Vec::new(),
+            kenngruppe,
+            text,
+        })
+    }
+}
+
+// The Kriegsmarine message key procedure. Instead of choosing a message key the operator picks a Verfahrenkenngruppe
+// from the Kenngruppenbuch and encrypts it at the Grundstellung from the key sheet, which gives the message key for the
+// three rotors. The greek wheel of an M4 stays where the Grundstellung put it. The Kenngruppe and Verfahrenkenngruppe
+// are written one above the other, offset by a random letter each:
+//
+//     . S S S
+//     V V V .
+//
+// and each of the four columns is swapped for another pair using the bigram table for the day. Reading the rows again
+// gives the two indicator groups, which are sent at the start of the message and repeated at the end. The message
+// itself goes out in groups of four letters.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NavalIndicator {
+    grundstellung: String,
+    kenngruppen: Vec<String>,
+    table: BigramTable,
+}
+
+impl NavalIndicator {
+    pub fn new(grundstellung: &str, kenngruppen: &[&str], table: BigramTable) -> Result<NavalIndicator> {
+        if grundstellung.len() < 3 || !grundstellung.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(Error::InputError).with_context(|| { format!("Invalid Grundstellung {}. Expected upper case letters for at least 3 rotors.", grundstellung) });
+        }
+
+        if let Some(k) = kenngruppen.iter().find(|k| !NavalIndicator::is_trigram(k)) {
+            return Err(Error::InputError).with_context(|| { format!("Invalid Kenngruppe {}. Expected 3 upper case letters.", k) });
+        }
+
+        Ok(NavalIndicator {
+            grundstellung: grundstellung.to_string(),
+            kenngruppen: kenngruppen.iter().map(|k| k.to_string()).collect(),
+            table,
+        })
+    }
+
+    pub fn get_grundstellung(&self) -> &str {
+        &self.grundstellung
+    }
+
+    pub fn get_kenngruppen(&self) -> &[String] {
+        &self.kenngruppen
+    }
+
+    fn is_trigram(trigram: &str) -> bool {
+        trigram.len() == 3 && trigram.chars().all(|c| c.is_ascii_uppercase())
+    }
+
+    // Encrypt a message with one of the Kenngruppen, a random Verfahrenkenngruppe and random padding in the indicator,
+    // picked with the given random number generator.
+    pub fn encrypt<R: Rng>(&self, rng: &mut R, enigma: &mut Enigma, plaintext: &str) -> Result<Message> {
+        let kenngruppe = match self.kenngruppen.choose(rng) {
+            Some(k) => k,
+            None => return Err(Error::InputError).with_context(|| "No Kenngruppen to choose from."),
+        };
+
+        let random = utils::get_random_letters_from(rng, 5);
+
+        self.encrypt_with(enigma, kenngruppe, &random[..3], &random[3..5], plaintext)
+    }
+
+    // Encrypt a message with a chosen Kenngruppe, Verfahrenkenngruppe and the two padding letters for the indicator.
+    pub fn encrypt_with(&self, enigma: &mut Enigma, kenngruppe: &str, verfahrenkenngruppe: &str, padding: &str, plaintext: &str) -> Result<Message> {
+        let indicator = self.encode_indicator(kenngruppe, verfahrenkenngruppe, padding)?;
+        let message_key = self.get_message_key(enigma, verfahrenkenngruppe)?;
+
+        enigma.set_keys(&message_key)?;
+        let ciphertext = enigma.encrypt(plaintext)?;
+
+        let format = GroupFormat::navy();
+
+        let mut groups = indicator.to_vec();
+        groups.extend(format.format(&ciphertext).split_whitespace().map(|g| g.to_string()));
+        groups.extend(indicator);
+
+        Ok(Message {
+            header: Vec::new(),
+            kenngruppe: None,
+            groups,
+            format,
+        })
+    }
+
+    // Turn the Kenngruppe and Verfahrenkenngruppe into the two indicator groups.
+    pub fn encode_indicator(&self, kenngruppe: &str, verfahrenkenngruppe: &str, padding: &str) -> Result<[String; 2]> {
+        if !NavalIndicator::is_trigram(kenngruppe) || !NavalIndicator::is_trigram(verfahrenkenngruppe) {
+            return Err(Error::InputError).with_context(|| { format!("Invalid indicator {} {}. Expected groups of 3 upper case letters.", kenngruppe, verfahrenkenngruppe) });
+        }
+
+        if padding.len() != 2 || !padding.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(Error::InputError).with_context(|| { format!("Invalid padding {}. Expected 2 upper case letters.", padding) });
+        }
+
+        let top: Vec<char> = padding[..1].chars().chain(kenngruppe.chars()).collect();
+        let bottom: Vec<char> = verfahrenkenngruppe.chars().chain(padding[1..].chars()).collect();
+
+        let mut groups = [String::new(), String::new()];
+
+        for (t, b) in top.iter().zip(&bottom) {
+            let pair: Vec<char> = self.table.substitute(&format!("{}{}", t, b))?.chars().collect();
+            groups[0].push(pair[0]);
+            groups[1].push(pair[1]);
+        }
+
+        Ok(groups)
+    }
+
+    // Recover the Kenngruppe and Verfahrenkenngruppe from the two indicator groups.
+    pub fn decode_indicator(&self, groups: &[String]) -> Result<(String, String)> {
+        if groups.len() != 2 || groups.iter().any(|g| g.len() != 4) {
+            return Err(Error::InputError).with_context(|| { format!("Invalid indicator {:?}. Expected 2 groups of 4 letters.", groups) });
+        }
+
+        let mut top = String::new();
+        let mut bottom = String::new();
+
+        for (a, b) in groups[0].chars().zip(groups[1].chars()) {
+            let pair: Vec<char> = self.table.substitute(&format!("{}{}", a, b))?.chars().collect();
+            top.push(pair[0]);
+            bottom.push(pair[1]);
+        }
+
+        Ok((top[1..].to_string(), bottom[..3].to_string()))
+    }
+
+    fn get_message_key(&self, enigma: &mut Enigma, verfahrenkenngruppe: &str) -> Result<String> {
+        enigma.set_keys(&self.grundstellung).with_context(|| "Unable to set the Grundstellung.")?;
+        let rotors = enigma.encrypt(verfahrenkenngruppe).with_context(|| { format!("Invalid Verfahrenkenngruppe {}.", verfahrenkenngruppe) })?;
+
+        Ok(format!("{}{}", &self.grundstellung[..(self.grundstellung.len() - 3)], rotors))
+    }
+
+    // Decrypt a naval message. The first two groups are the indicator and the last two are taken as its repeat, any
+    // letters of the repeat that differ being flagged as mismatches.
+    pub fn decrypt(&self, enigma: &mut Enigma, traffic: &str) -> Result<ReceivedMessage> {
+        let message = Message::parse(traffic, false)?;
+
+        if message.groups.len() < 4 {
+            return Err(Error::InputError).with_context(|| "Message is too short for the indicator groups at the start and the end.");
+        }
+
+        let (indicator, rest) = message.groups.split_at(2);
+        let (body, repeat) = rest.split_at(rest.len() - 2);
+
+        let mismatches = indicator.concat().chars().zip(repeat.concat().chars()).enumerate().filter(|(_, (a, b))| a != b).map(|(i, _)| i).collect();
+
+        let (kenngruppe, verfahrenkenngruppe) = self.decode_indicator(indicator)?;
+        let message_key = self.get_message_key(enigma, &verfahrenkenngruppe)?;
+
+        enigma.set_keys(&message_key)?;
+        let text = enigma.encrypt(&body.concat())?;
+
+        Ok(ReceivedMessage {
+            message_key,
+            mismatches,
+            kenngruppe: Some(kenngruppe),
+            text,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{InputPolicy, MachineSettings};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    // The example from the 1930 operating instructions for the Enigma I.
+    const SETTINGS: &str = "A II I III 24 13 22 AM FI NV PS TU WZ";
+    const PLAINTEXT: &str = "FEINDLIQEINFANTERIEKOLONNEBEOBAQTETXANFANGSUEDAUSGANGBAERWALDEXENDEDREIKMOSTWAERTSNEUSTADT";
+    const TRANSMISSION: &str = "PKPJXI GCDSE AHUGW TQGRK VLFGX UCALX VYMIG MMNMF DXTGN VHVRM MEVOU YFZSL RHDRR XFJWC FHUHM UNZEF RDISI KBGPM YVXUZ";
+
+    #[test]
+    fn test_doubled_indicator_encrypt() {
+        let mut enigma = SETTINGS.parse::<MachineSettings>().unwrap().build().unwrap();
+        let procedure = DoubledIndicator::new("FOL").unwrap();
+
+        let transmission = procedure.encrypt(&mut enigma, Some("ABL"), PLAINTEXT).unwrap();
+        assert_eq!(TRANSMISSION.replace(' ', ""), transmission);
+
+        // A random message key still decrypts.
+        let transmission = procedure.encrypt(&mut enigma, None, PLAINTEXT).unwrap();
+        let received = procedure.decrypt(&mut enigma, &transmission).unwrap();
+
+        assert_eq!(PLAINTEXT, received.text);
+        assert!(!received.has_mismatch());
+    }
+
+    #[test]
+    fn test_doubled_indicator_decrypt() {
+        let mut enigma = SETTINGS.parse::<MachineSettings>().unwrap().build().unwrap();
+        let procedure = DoubledIndicator::new("FOL").unwrap();
+
+        let received = procedure.decrypt(&mut enigma, TRANSMISSION).unwrap();
+
+        assert_eq!("ABL", received.message_key);
+        assert!(!received.has_mismatch());
+        assert_eq!(PLAINTEXT, received.text);
+    }
+
+    #[test]
+    fn test_doubled_indicator_mismatch() {
+        let mut enigma = SETTINGS.parse::<MachineSettings>().unwrap().build().unwrap();
+        let procedure = DoubledIndicator::new("FOL").unwrap();
+
+        // The fifth letter of the indicator garbled in transmission.
+        let received = procedure.decrypt(&mut enigma, &TRANSMISSION.replacen("PKPJXI", "PKPJAI", 1)).unwrap();
+
+        assert_eq!("ABL", received.message_key);
+        assert_eq!(vec![1], received.mismatches);
+        assert_eq!(PLAINTEXT, received.text);
+    }
+
+    #[test]
+    fn test_doubled_indicator_invalid() {
+        let mut enigma = SETTINGS.parse::<MachineSettings>().unwrap().build().unwrap();
+
+        assert!(DoubledIndicator::new("").is_err());
+        assert!(DoubledIndicator::new("fol").is_err());
+
+        assert!(DoubledIndicator::new("FO").unwrap().encrypt(&mut enigma, Some("ABL"), PLAINTEXT).is_err());
+        assert!(DoubledIndicator::new("FOL").unwrap().encrypt(&mut enigma, Some("AB"), PLAINTEXT).is_err());
+        assert!(DoubledIndicator::new("FOL").unwrap().decrypt(&mut enigma, "PKPJX").is_err());
+
+        // Letters outside ASCII in the indicator are rejected rather than split in the middle.
+        assert!(DoubledIndicator::new("FOL").unwrap().decrypt(&mut enigma, "AÄÄÄÄÄÄBC").is_err());
+        assert!(DoubledIndicator::new("FOL").unwrap().decrypt(&mut enigma, "PKPjxcABC").is_err());
+
+        enigma.set_input_policy(InputPolicy::PassThrough, true);
+        assert!(DoubledIndicator::new("FOL").unwrap().decrypt(&mut enigma, "PKPJXÄABC").is_err());
+    }
+
+    // The first part of a message from Operation Barbarossa in 1941.
+    const BARBAROSSA_SETTINGS: &str = "B II IV V 02 21 12 AAA AV BS CG DL FU HZ IN KM OW RX";
+    const BARBAROSSA_PLAINTEXT: &str = "AUFKLXABTEILUNGXVONXKURTINOWAXKURTINOWAXNORDWESTLXSEBEZXSEBEZXUAFFLIEGERSTRASZERIQTUNGXDUBROWKIXDUBROWKIXOPOTSCHKAXOPOTSCHKAXUMXEINSAQTDREINULLXUHRANGETRETENXANGRIFFXINFXRGTX";
+    const BARBAROSSA: &str = "1840 = 2TLE 1TL = 179 = WXC KCH =\n\
+        RFUGZ EDPUD NRGYS ZRCXN UYTPO MRMBO FKTBZ REZKM LXLVE FGUEY\n\
+        SIOZV EQMIK UBPMM YLKLT TDEIS MDICA GYKUA CTCDO MOHWX MUUIA\n\
+        UBSTS LRNBZ SZWNR FXWFY SSXJZ VIJHI DISHP RKLKA YUPAD TXQSP\n\
+        INQMA TLPIF SVKDA SCTAC DPBOP VHJK";
+
+    #[test]
+    fn test_chosen_indicator_decrypt() {
+        let mut enigma = BARBAROSSA_SETTINGS.parse::<MachineSettings>().unwrap().build().unwrap();
+        let procedure = ChosenIndicator::new(&[]).unwrap();
+
+        let received = procedure.decrypt(&mut enigma, BARBAROSSA).unwrap();
+
+        assert_eq!(1, received.len());
+        assert_eq!("BLA", received[0].message_key);
+        assert_eq!(Some("UGZ".to_string()), received[0].kenngruppe);
+        assert_eq!(BARBAROSSA_PLAINTEXT, received[0].text);
+    }
+
+    #[test]
+    fn test_chosen_indicator_encrypt_part() {
+        let mut enigma = BARBAROSSA_SETTINGS.parse::<MachineSettings>().unwrap().build().unwrap();
+        let procedure = ChosenIndicator::new(&["UGZ", "DFK"]).unwrap();
+        let mut rng = StdRng::seed_from_u64(1940);
+
+        let message = procedure.encrypt_part(&mut rng, &mut enigma, "1840", Some((1, 2)), "WXC", "BLA", BARBAROSSA_PLAINTEXT).unwrap();
+        let expected = Message::parse(BARBAROSSA, true).unwrap();
+
+        assert_eq!(expected.header, message.header);
+        assert_eq!(expected.groups, message.groups);
+
+        let kenngruppe = message.kenngruppe.unwrap();
+        assert!(procedure.get_kenngruppen().iter().any(|k| kenngruppe.ends_with(k.as_str())));
+
+        // A message in one part leaves out the part numbers.
+        let message = procedure.encrypt_part(&mut rng, &mut enigma, "1840", None, "WXC", "BLA", "HELLO").unwrap();
+        assert_eq!(vec!["1840", "10", "WXC KCH"], message.header);
+
+        assert!(procedure.encrypt_part(&mut rng, &mut enigma, "18:40", None, "WXC", "BLA", "HELLO").is_err());
+        assert!(procedure.encrypt_part(&mut rng, &mut enigma, "1840", None, "WX", "BLA", "HELLO").is_err());
+        assert!(ChosenIndicator::new(&[]).unwrap().encrypt_part(&mut rng, &mut enigma, "1840", None, "WXC", "BLA", "HELLO").is_err());
+    }
+
+    #[test]
+    fn test_chosen_indicator_parts() {
+        let mut enigma = BARBAROSSA_SETTINGS.parse::<MachineSettings>().unwrap().build().unwrap();
+        let mut procedure = ChosenIndicator::new(&["UGZ", "DFK", "SOT", "PLQ"]).unwrap();
+        procedure.set_part_length(60).unwrap();
+
+        let messages = procedure.encrypt(&mut StdRng::seed_from_u64(1940), &mut enigma, "1840", BARBAROSSA_PLAINTEXT).unwrap();
+
+        // The same seed picks the same keys and Kenngruppen again.
+        assert_eq!(messages, procedure.encrypt(&mut StdRng::seed_from_u64(1940), &mut enigma, "1840", BARBAROSSA_PLAINTEXT).unwrap());
+
+        assert_eq!(3, messages.len());
+        assert_eq!("3TLE 2TL", messages[1].header[1]);
+        assert_eq!("65", messages[1].header[2]);
+        assert_eq!("59", messages[2].header[2]);
+
+        // Sent as traffic and received again.
+        let traffic = messages.iter().map(|m| m.to_string()).collect::<Vec<String>>().join("\n");
+        let received = procedure.decrypt(&mut enigma, &traffic).unwrap();
+
+        assert_eq!(BARBAROSSA_PLAINTEXT, received.iter().map(|r| r.text.as_str()).collect::<String>());
+        assert!(received.iter().all(|r| procedure.get_kenngruppen().contains(r.kenngruppe.as_ref().unwrap())));
+
+        assert!(procedure.set_part_length(0).is_err());
+        assert!(ChosenIndicator::new(&["ABCD"]).is_err());
+        assert!(procedure.decrypt(&mut enigma, "RFUGZ EDPUD NRGYS").is_err());
+    }
+
+    const M4_SETTINGS: &str = "M4 ThinB Beta II IV I 01 01 01 22 AT BL DF GJ HM NW OP QY RZ VX";
+
+    fn get_naval_indicator() -> NavalIndicator {
+        let table = BigramTable::from_toml(&crate::bigram::test::get_table_toml()).unwrap();
+
+        NavalIndicator::new("VJNA", &["SDK", "QWX"], table).unwrap()
+    }
+
+    #[test]
+    fn test_naval_indicator_encode() {
+        let procedure = get_naval_indicator();
+
+        // Columns XQ SW DE KY become XR SX DF KZ with the table that swaps neighbouring bigrams.
+        let indicator = procedure.encode_indicator("SDK", "QWE", "XY").unwrap();
+        assert_eq!(["XSDK".to_string(), "RXFZ".to_string()], indicator);
+
+        assert_eq!(("SDK".to_string(), "QWE".to_string()), procedure.decode_indicator(&indicator).unwrap());
+
+        assert!(procedure.encode_indicator("SD", "QWE", "XY").is_err());
+        assert!(procedure.encode_indicator("SDK", "QWE", "X").is_err());
+        assert!(procedure.decode_indicator(&["XSDK".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_naval_indicator_encrypt_decrypt() {
+        let mut enigma = M4_SETTINGS.parse::<MachineSettings>().unwrap().build().unwrap();
+        let procedure = get_naval_indicator();
+
+        let message = procedure.encrypt_with(&mut enigma, "SDK", "QWE", "XY", "VONVONJLOOKSJHFFTTTEINSEINSDREIZWOYYQNNSNEUNINHALTXX").unwrap();
+        let groups = &message.groups;
+
+        assert_eq!(vec!["XSDK", "RXFZ"], groups[..2].to_vec());
+        assert_eq!(groups[..2], groups[(groups.len() - 2)..]);
+        assert!(groups[2..(groups.len() - 2)].iter().all(|g| g.len() <= 4));
+
+        // The message key is the Verfahrenkenngruppe encrypted at the Grundstellung, with the greek wheel left alone.
+        enigma.set_keys("VJNA").unwrap();
+        let message_key = format!("V{}", enigma.encrypt("QWE").unwrap());
+
+        let received = procedure.decrypt(&mut enigma, &message.to_string()).unwrap();
+
+        assert_eq!(message_key, received.message_key);
+        assert_eq!(Some("SDK".to_string()), received.kenngruppe);
+        assert!(!received.has_mismatch());
+        assert_eq!("VONVONJLOOKSJHFFTTTEINSEINSDREIZWOYYQNNSNEUNINHALTXX", received.text);
+
+        // With a random indicator.
+        let message = procedure.encrypt(&mut StdRng::seed_from_u64(1941), &mut enigma, "HELLOWORLD").unwrap();
+
+        // The same seed picks the same indicator again.
+        assert_eq!(message, procedure.encrypt(&mut StdRng::seed_from_u64(1941), &mut enigma, "HELLOWORLD").unwrap());
+
+        let received = procedure.decrypt(&mut enigma, &message.to_string()).unwrap();
+
+        assert_eq!("HELLOWORLD", received.text);
+        assert!(procedure.get_kenngruppen().contains(received.kenngruppe.as_ref().unwrap()));
+    }
+
+    #[test]
+    fn test_naval_indicator_mismatch() {
+        let mut enigma = M4_SETTINGS.parse::<MachineSettings>().unwrap().build().unwrap();
+        let procedure = get_naval_indicator();
+
+        let message = procedure.encrypt_with(&mut enigma, "SDK", "QWE", "XY", "HELLOWORLD").unwrap();
+        let traffic = message.to_string();
+
+        // The repeat of the indicator garbled at the end.
+        let received = procedure.decrypt(&mut enigma, &(traffic[..(traffic.len() - 1)].to_string() + "A")).unwrap();
+
+        assert_eq!(vec![7], received.mismatches);
+        assert_eq!("HELLOWORLD", received.text);
+
+        assert!(procedure.decrypt(&mut enigma, "XSDK RXFZ ABCD").is_err());
+        assert!(NavalIndicator::new("VJ", &[], procedure.table.clone()).is_err());
+        assert!(NavalIndicator::new("VJNA", &["SD"], procedure.table.clone()).is_err());
+        assert!(NavalIndicator::new("VJNA", &[], procedure.table.clone()).unwrap().encrypt(&mut StdRng::seed_from_u64(1941), &mut enigma, "HELLO").is_err());
+    }
+}
@@ -1,61 +1,272 @@
+#![allow(clippy::needless_return)]
+
+use crate::utils;
 use crate::error::Error;
+use anyhow::{Context, Result};
 
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ReflectorType {
-    Beta,
-    Gamma,
     A,
     B,
     C,
     ThinB,
     ThinC,
-    ETW,
+    // Settable reflector of the commercial Enigma D and K, also used by the Swiss K.
+    Commercial,
+    // Settable reflector of the Abwehr Enigma G-312, which is also moved by the rotors.
+    G,
+    // Settable reflector of the Reichsbahn (Rocket) Enigma.
+    Railway,
+    // Settable reflector of the Enigma T (Tirpitz).
+    T,
+    // Field rewirable reflector (Umkehrwalze D) of the Luftwaffe.
+    D,
 }
 
+// The UKW-D contacts were labelled differently by the Germans and at Bletchley Park.
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReflectorNotation {
+    // Labels as used on the wheel itself, where the fixed pair is J-Y.
+    German,
+    // Labels matching the positions of the other reflectors, where the fixed pair is B-O.
+    Bletchley,
+}
+
+// Maps a German UKW-D label to the Bletchley Park label of the same contact.
+const UKW_D_GERMAN_TO_BLETCHLEY: [usize; 26] = [0, 25, 24, 23, 22, 21, 20, 19, 18, 1, 17, 16, 15, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 14, 2];
+
+// The pair of contacts of the UKW-D that cannot be rewired, in Bletchley Park notation.
+const UKW_D_FIXED_PAIR: [usize; 2] = [1, 14];
+
+// The number of pluggable pairs on the UKW-D, not counting the fixed pair.
+const UKW_D_PAIRS: usize = 12;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "ReflectorFields"))]
 pub struct Reflector {
+    reflector_type: Option<ReflectorType>,
+    position: usize,
+    ring_setting: usize,
+    movable: bool,
     wiring: [usize; 26]
 }
 
 impl Reflector {
     pub fn new(reflector_type: ReflectorType) -> Reflector {
         Reflector {
+            reflector_type: Some(reflector_type),
+            position: 0,
+            ring_setting: 0,
+            movable: reflector_type == ReflectorType::G,
             wiring: Reflector::get_reflector_wiring(reflector_type),
         }
     }
 
-    pub fn get_reflector_type_from_string(reflector_type: &str) -> Result<ReflectorType, Error> {
+    // Create a reflector turned to a set position, for the machines where the reflector can be set like a rotor.
+    pub fn with_setting(reflector_type: ReflectorType, key: char, ring_setting: usize) -> Result<Reflector> {
+        if !(1..=26).contains(&ring_setting) {
+            return Err(Error::ReflectorError).with_context(|| { format!("Invalid ring setting {}. Must be in the range 1 to 26 (inclusive).", ring_setting) });
+        }
+
+        let mut reflector = Reflector::new(reflector_type);
+        reflector.position = utils::get_position_from_char(key)?;
+        reflector.ring_setting = ring_setting - 1;
+
+        Ok(reflector)
+    }
+
+    // Create a UKW-D plugged with the given pairs. The fixed pair is always connected and must not be part of the pairs.
+    pub fn with_plugging(pairs: &[[char; 2]], notation: ReflectorNotation) -> Result<Reflector> {
+        if pairs.len() != UKW_D_PAIRS {
+            return Err(Error::ReflectorError).with_context(|| { format!("Invalid number of UKW-D pairs {}. Must be {}.", pairs.len(), UKW_D_PAIRS) });
+        }
+
+        let mut wiring = [usize::MAX; 26];
+        wiring[UKW_D_FIXED_PAIR[0]] = UKW_D_FIXED_PAIR[1];
+        wiring[UKW_D_FIXED_PAIR[1]] = UKW_D_FIXED_PAIR[0];
+
+        for pair in pairs {
+            let a = Reflector::get_ukw_d_contact(pair[0], notation)?;
+            let b = Reflector::get_ukw_d_contact(pair[1], notation)?;
+
+            if UKW_D_FIXED_PAIR.contains(&a) || UKW_D_FIXED_PAIR.contains(&b) {
+                return Err(Error::ReflectorError).with_context(|| { format!("Invalid UKW-D pair {}{}. The fixed pair cannot be rewired.", pair[0], pair[1]) });
+            }
+
+            if wiring[a] != usize::MAX || wiring[b] != usize::MAX {
+                return Err(Error::ReflectorError).with_context(|| { format!("Invalid UKW-D pair {}{}. Each contact can only be plugged once.", pair[0], pair[1]) });
+            }
+
+            wiring[a] = b;
+            wiring[b] = a;
+        }
+
+        Reflector::validate_wiring(&wiring)?;
+
+        let mut reflector = Reflector::new(ReflectorType::D);
+        reflector.wiring = wiring;
+
+        Ok(reflector)
+    }
+
+    fn get_ukw_d_contact(c: char, notation: ReflectorNotation) -> Result<usize> {
+        let position = utils::get_position_from_char(c)?;
+
+        match notation {
+            ReflectorNotation::German => Ok(UKW_D_GERMAN_TO_BLETCHLEY[position]),
+            ReflectorNotation::Bletchley => Ok(position),
+        }
+    }
+
+    // A reflector must pair every contact with another, so the wiring has to be its own inverse with no fixed points.
+    fn validate_wiring(wiring: &[usize; 26]) -> Result<()> {
+        for (i, w) in wiring.iter().enumerate() {
+            if *w > 25 || wiring[*w] != i {
+                return Err(Error::ReflectorError).with_context(|| { format!("Invalid reflector wiring. Contact {} is not paired.", utils::get_char_from_position(i).unwrap()) });
+            }
+
+            if *w == i {
+                return Err(Error::ReflectorError).with_context(|| { format!("Invalid reflector wiring. Contact {} is wired to itself.", utils::get_char_from_position(i).unwrap()) });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Create a reflector that is not one of the known types from a wiring string such as "YRUHQSLDPXNGOKMIEBFZCWVJAT".
+    pub fn from_wiring_string(wiring: &str) -> Result<Reflector> {
+        let wiring = utils::get_wiring_from_string(wiring)?;
+
+        Reflector::validate_wiring(&wiring)?;
+
+        Ok(Reflector {
+            reflector_type: None,
+            position: 0,
+            ring_setting: 0,
+            movable: false,
+            wiring,
+        })
+    }
+
+    pub fn get_reflector_type(&self) -> Option<ReflectorType> {
+        self.reflector_type
+    }
+
+    pub fn get_key(&self) -> char {
+        utils::get_char_from_position(self.position).unwrap()
+    }
+
+    pub(crate) fn get_position(&self) -> usize {
+        self.position
+    }
+
+    pub(crate) fn set_position(&mut self, position: usize) {
+        self.position = position;
+    }
+
+    pub(crate) fn get_wiring(&self) -> &[usize; 26] {
+        &self.wiring
+    }
+
+    // Return true if the reflector is stepped by the rotor to its right, like a fourth rotor.
+    pub fn is_movable(&self) -> bool {
+        self.movable
+    }
+
+    pub fn step(&mut self) {
+        self.position = (self.position + 1) % 26;
+    }
+
+    // Return true if the reflector has been moved away from its normal position.
+    pub fn is_set(&self) -> bool {
+        self.position != self.ring_setting
+    }
+
+    pub fn get_reflector_type_from_string(reflector_type: &str) -> Result<ReflectorType> {
         let lower = reflector_type.to_ascii_lowercase();
 
         let t = match lower.as_str() {
-            "beta" => ReflectorType::Beta,
-            "gamma" => ReflectorType::Gamma,
             "a" => ReflectorType::A,
             "b" => ReflectorType::B,
             "c" => ReflectorType::C,
             "thinb" => ReflectorType::ThinB,
             "thinc" => ReflectorType::ThinC,
-            "etw" => ReflectorType::ETW,
-            _ => return Err(Error::ReflectorError),
+            "commercial" => ReflectorType::Commercial,
+            "g" => ReflectorType::G,
+            "railway" => ReflectorType::Railway,
+            "t" => ReflectorType::T,
+            "d" => ReflectorType::D,
+            _ => return Err(Error::ReflectorError).with_context(|| { format!("Invalid reflector type {}.", reflector_type) }),
         };
 
         Ok(t)
     }
 
+    pub fn get_reflector_notation_from_string(notation: &str) -> Result<ReflectorNotation> {
+        let lower = notation.to_ascii_lowercase();
+
+        let n = match lower.as_str() {
+            "german" => ReflectorNotation::German,
+            "bletchley" => ReflectorNotation::Bletchley,
+            _ => return Err(Error::ReflectorError).with_context(|| { format!("Invalid reflector notation {}.", notation) }),
+        };
+
+        Ok(n)
+    }
+
     fn get_reflector_wiring(reflector_type: ReflectorType) -> [usize; 26] {
         match reflector_type {
-            ReflectorType::Beta => return [11, 4, 24, 9, 21, 2, 13, 8, 23, 22, 15, 1, 16, 12, 3, 17, 19, 0, 10, 25, 6, 5, 20, 7, 14, 18],
-            ReflectorType::Gamma => return [5, 18, 14, 10, 0, 13, 20, 4, 17, 7, 12, 1, 19, 8, 24, 2, 22, 11, 16, 15, 25, 23, 21, 6, 9, 3],
             ReflectorType::A => return [4, 9, 12, 25, 0, 11, 24, 23, 21, 1, 22, 5, 2, 17, 16, 20, 14, 13, 19, 18, 15, 8, 10, 7, 6, 3],
             ReflectorType::B => return [24, 17, 20, 7, 16, 18, 11, 3, 15, 23, 13, 6, 14, 10, 12, 8, 4, 1, 5, 25, 2, 22, 21, 9, 0, 19],
             ReflectorType::C => return [5, 21, 15, 9, 8, 0, 14, 24, 4, 3, 17, 25, 23, 22, 6, 2, 19, 10, 20, 16, 18, 1, 13, 12, 7, 11],
             ReflectorType::ThinB => return [4, 13, 10, 16, 0, 20, 24, 22, 9, 8, 2, 14, 15, 1, 11, 12, 3, 23, 25, 21, 5, 19, 7, 17, 6, 18],
             ReflectorType::ThinC => return [17, 3, 14, 1, 9, 13, 19, 10, 21, 4, 7, 12, 11, 5, 2, 22, 25, 0, 23, 6, 24, 8, 15, 18, 20, 16],
-            ReflectorType::ETW => return [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25],
-        }
+            ReflectorType::Commercial => return [8, 12, 4, 19, 2, 6, 5, 17, 0, 24, 18, 16, 1, 25, 23, 22, 11, 7, 10, 3, 21, 20, 15, 14, 9, 13],
+            ReflectorType::G => return [17, 20, 11, 16, 12, 25, 9, 18, 24, 6, 14, 2, 4, 19, 10, 22, 3, 0, 7, 13, 1, 23, 15, 21, 8, 5],
+            ReflectorType::Railway => return [16, 24, 7, 14, 6, 13, 4, 2, 21, 15, 20, 25, 19, 5, 3, 9, 0, 23, 22, 12, 10, 8, 18, 17, 1, 11],
+            ReflectorType::T => return [6, 4, 10, 15, 1, 19, 0, 20, 12, 14, 2, 13, 8, 11, 9, 3, 23, 25, 24, 5, 7, 22, 21, 16, 18, 17],
+            // Plugged AC DE FG HI JK LM NP QR ST UV WX YZ (Bletchley) until rewired with Reflector::with_plugging.
+            ReflectorType::D => return [2, 14, 0, 4, 3, 6, 5, 8, 7, 10, 9, 12, 11, 15, 1, 13, 17, 16, 19, 18, 21, 20, 23, 22, 25, 24],
+        };
     }
 
     pub fn scramble(&self, input: usize) -> usize {
-        return self.wiring[input];
+        let offset = (self.position + 26 - self.ring_setting) % 26;
+
+        (self.wiring[(input + offset) % 26] + 26 - offset) % 26
+    }
+}
+
+// The fields of a reflector as they are read back, checked before the reflector is used.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct ReflectorFields {
+    reflector_type: Option<ReflectorType>,
+    position: usize,
+    ring_setting: usize,
+    movable: bool,
+    wiring: [usize; 26],
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<ReflectorFields> for Reflector {
+    type Error = anyhow::Error;
+
+    fn try_from(fields: ReflectorFields) -> Result<Reflector> {
+        if fields.position > 25 || fields.ring_setting > 25 {
+            return Err(Error::ReflectorError).with_context(|| { format!("Invalid reflector position {} and ring setting position {}. Must be in the range 0 to 25 (inclusive).", fields.position, fields.ring_setting) });
+        }
+
+        Reflector::validate_wiring(&fields.wiring)?;
+
+        Ok(Reflector {
+            reflector_type: fields.reflector_type,
+            position: fields.position,
+            ring_setting: fields.ring_setting,
+            movable: fields.movable,
+            wiring: fields.wiring,
+        })
     }
 }
 
@@ -78,13 +289,136 @@ mod test {
     }
 
     #[test]
-    fn test_get_reflector_type_from_string() {
-        assert_eq!(ReflectorType::Beta, Reflector::get_reflector_type_from_string("BETA").unwrap());
-        assert_eq!(ReflectorType::Beta, Reflector::get_reflector_type_from_string("beta").unwrap());
+    fn test_reflector_scramble_with_setting() {
+        let reflector = Reflector::with_setting(ReflectorType::Commercial, 'B', 1).unwrap();
+
+        // Every contact is still paired with another, just shifted by one position.
+        for i in 0..26 {
+            let o = reflector.scramble(i);
+            assert_ne!(i, o);
+            assert_eq!(i, reflector.scramble(o));
+        }
+
+        assert_eq!(11, reflector.scramble(0)); // A (B) -> M (L)
+
+        // Turning the ring back by the same amount as the position cancels out.
+        let reflector = Reflector::with_setting(ReflectorType::Commercial, 'B', 2).unwrap();
+        assert_eq!(8, reflector.scramble(0)); // A -> I
+        assert!(!reflector.is_set());
+    }
+
+    #[test]
+    fn test_reflector_step() {
+        let mut reflector = Reflector::with_setting(ReflectorType::G, 'Z', 1).unwrap();
+        assert!(reflector.is_movable());
+        assert!(!Reflector::new(ReflectorType::Commercial).is_movable());
+
+        reflector.step();
+        assert_eq!('A', reflector.get_key());
+        assert_eq!(17, reflector.scramble(0)); // A -> R
+    }
+
+    #[test]
+    fn test_reflector_with_plugging() {
+        let pairs = [['A', 'F'], ['C', 'V'], ['D', 'S'], ['E', 'L'], ['G', 'I'], ['H', 'Z'], ['K', 'M'], ['N', 'Q'], ['P', 'W'], ['R', 'X'], ['T', 'U'], ['J', 'Y']];
+        let reflector = Reflector::with_plugging(&pairs, ReflectorNotation::Bletchley).unwrap();
+
+        assert_eq!(Some(ReflectorType::D), reflector.get_reflector_type());
+        assert_eq!(5, reflector.scramble(0)); // A -> F
+        assert_eq!(14, reflector.scramble(1)); // B -> O (fixed)
+        assert_eq!(9, reflector.scramble(24)); // Y -> J
 
-        assert_eq!(ReflectorType::Gamma, Reflector::get_reflector_type_from_string("GAMMA").unwrap());
-        assert_eq!(ReflectorType::Gamma, Reflector::get_reflector_type_from_string("gamma").unwrap());
+        for i in 0..26 {
+            let o = reflector.scramble(i);
+            assert_ne!(i, o);
+            assert_eq!(i, reflector.scramble(o));
+        }
+    }
+
+    #[test]
+    fn test_reflector_with_plugging_german() {
+        // The same plugging in both notations.
+        let german = [['A', 'V'], ['Z', 'F'], ['X', 'I'], ['W', 'P'], ['U', 'S'], ['T', 'B'], ['Q', 'O'], ['N', 'L'], ['M', 'E'], ['K', 'D'], ['H', 'G'], ['R', 'C']];
+        let bletchley = [['A', 'F'], ['C', 'V'], ['D', 'S'], ['E', 'L'], ['G', 'I'], ['H', 'Z'], ['K', 'M'], ['N', 'Q'], ['P', 'W'], ['R', 'X'], ['T', 'U'], ['J', 'Y']];
+
+        let german = Reflector::with_plugging(&german, ReflectorNotation::German).unwrap();
+        let bletchley = Reflector::with_plugging(&bletchley, ReflectorNotation::Bletchley).unwrap();
+
+        for i in 0..26 {
+            assert_eq!(bletchley.scramble(i), german.scramble(i));
+        }
+    }
+
+    #[test]
+    fn test_reflector_with_plugging_invalid() {
+        let mut pairs = vec![['A', 'F'], ['C', 'V'], ['D', 'S'], ['E', 'L'], ['G', 'I'], ['H', 'Z'], ['K', 'M'], ['N', 'Q'], ['P', 'W'], ['R', 'X'], ['T', 'U']];
+
+        // Too few pairs.
+        assert!(Reflector::with_plugging(&pairs, ReflectorNotation::Bletchley).is_err());
+
+        // The fixed pair cannot be plugged.
+        pairs.push(['B', 'Y']);
+        assert!(Reflector::with_plugging(&pairs, ReflectorNotation::Bletchley).is_err());
+        assert!(Reflector::with_plugging(&pairs, ReflectorNotation::German).is_err());
+
+        // A contact cannot be plugged twice.
+        pairs[11] = ['A', 'Y'];
+        assert!(Reflector::with_plugging(&pairs, ReflectorNotation::Bletchley).is_err());
+
+        // A contact cannot be wired to itself.
+        pairs[11] = ['J', 'J'];
+        assert!(Reflector::with_plugging(&pairs, ReflectorNotation::Bletchley).is_err());
+
+        pairs[11] = ['J', 'a'];
+        assert!(Reflector::with_plugging(&pairs, ReflectorNotation::Bletchley).is_err());
+    }
 
+    #[test]
+    fn test_reflector_from_wiring_string() {
+        let custom = Reflector::from_wiring_string("YRUHQSLDPXNGOKMIEBFZCWVJAT").unwrap();
+        let reflector = Reflector::new(ReflectorType::B);
+
+        assert_eq!(None, custom.get_reflector_type());
+
+        for i in 0..26 {
+            assert_eq!(reflector.scramble(i), custom.scramble(i));
+        }
+    }
+
+    #[test]
+    fn test_reflector_wiring_railway_and_tirpitz() {
+        // Wiring as published by the Crypto Museum for the Reichsbahn and Enigma T reflectors.
+        for (reflector_type, wiring) in [(ReflectorType::Railway, "QYHOGNECVPUZTFDJAXWMKISRBL"), (ReflectorType::T, "GEKPBTAUMOCNILJDXZYFHWVQSR")] {
+            let wiring: Vec<usize> = wiring.chars().map(|c| utils::get_position_from_char(c).unwrap()).collect();
+            assert_eq!(wiring, Reflector::new(reflector_type).wiring, "{:?}", reflector_type);
+        }
+    }
+
+    #[test]
+    fn test_reflector_from_wiring_string_invalid() {
+        // Not a permutation.
+        assert!(Reflector::from_wiring_string("YRUHQSLDPXNGOKMIEBFZCWVJAY").is_err());
+        // Not an involution, this is rotor I.
+        assert!(Reflector::from_wiring_string("EKMFLGDQVZNTOWYHXUSPAIBRCJ").is_err());
+        // A fixed point, A is wired to itself.
+        assert!(Reflector::from_wiring_string("ARUHQSLDPXNGOKMIEBFZCWVJYT").is_err());
+    }
+
+    #[test]
+    fn test_reflector_wiring_is_involution() {
+        for t in [ReflectorType::A, ReflectorType::B, ReflectorType::C, ReflectorType::ThinB, ReflectorType::ThinC, ReflectorType::Commercial, ReflectorType::G, ReflectorType::Railway, ReflectorType::T, ReflectorType::D] {
+            assert!(Reflector::validate_wiring(&Reflector::get_reflector_wiring(t)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_reflector_invalid_ring_setting() {
+        assert!(Reflector::with_setting(ReflectorType::Commercial, 'A', 0).is_err());
+        assert!(Reflector::with_setting(ReflectorType::Commercial, 'A', 27).is_err());
+    }
+
+    #[test]
+    fn test_get_reflector_type_from_string() {
         assert_eq!(ReflectorType::A, Reflector::get_reflector_type_from_string("A").unwrap());
         assert_eq!(ReflectorType::A, Reflector::get_reflector_type_from_string("a").unwrap());
 
@@ -100,12 +434,38 @@ mod test {
         assert_eq!(ReflectorType::ThinC, Reflector::get_reflector_type_from_string("THINC").unwrap());
         assert_eq!(ReflectorType::ThinC, Reflector::get_reflector_type_from_string("thinc").unwrap());
 
-        assert_eq!(ReflectorType::ETW, Reflector::get_reflector_type_from_string("ETW").unwrap());
-        assert_eq!(ReflectorType::ETW, Reflector::get_reflector_type_from_string("etw").unwrap());
+        assert_eq!(ReflectorType::Commercial, Reflector::get_reflector_type_from_string("COMMERCIAL").unwrap());
+        assert_eq!(ReflectorType::Commercial, Reflector::get_reflector_type_from_string("commercial").unwrap());
+
+        assert_eq!(ReflectorType::G, Reflector::get_reflector_type_from_string("G").unwrap());
+        assert_eq!(ReflectorType::G, Reflector::get_reflector_type_from_string("g").unwrap());
+
+        assert_eq!(ReflectorType::Railway, Reflector::get_reflector_type_from_string("Railway").unwrap());
+        assert_eq!(ReflectorType::Railway, Reflector::get_reflector_type_from_string("railway").unwrap());
+
+        assert_eq!(ReflectorType::T, Reflector::get_reflector_type_from_string("T").unwrap());
+        assert_eq!(ReflectorType::T, Reflector::get_reflector_type_from_string("t").unwrap());
+
+        assert_eq!(ReflectorType::D, Reflector::get_reflector_type_from_string("D").unwrap());
+        assert_eq!(ReflectorType::D, Reflector::get_reflector_type_from_string("d").unwrap());
+    }
+
+    #[test]
+    fn test_get_reflector_notation_from_string() {
+        assert_eq!(ReflectorNotation::German, Reflector::get_reflector_notation_from_string("German").unwrap());
+        assert_eq!(ReflectorNotation::Bletchley, Reflector::get_reflector_notation_from_string("bletchley").unwrap());
+        assert!(Reflector::get_reflector_notation_from_string("blah").is_err());
     }
 
     #[test]
     fn test_get_reflector_type_from_string_invalid() {
         assert!(Reflector::get_reflector_type_from_string("blah").is_err());
+
+        // The greek wheels are rotors of the M4, not reflectors.
+        assert!(Reflector::get_reflector_type_from_string("beta").is_err());
+        assert!(Reflector::get_reflector_type_from_string("gamma").is_err());
+
+        // The entry wheel is not a reflector either.
+        assert!(Reflector::get_reflector_type_from_string("etw").is_err());
     }
 }
\ No newline at end of file
@@ -1,8 +1,11 @@
+#![allow(clippy::needless_return, clippy::upper_case_acronyms, clippy::manual_range_contains, clippy::redundant_field_names, clippy::needless_range_loop)]
+
 use crate::utils;
 use crate::error::{Error};
 use anyhow::{Context, Result};
 
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RotorType {
     I,
     II,
@@ -12,6 +15,31 @@ pub enum RotorType {
     VI,
     VII,
     VIII,
+    // Commercial Enigma D and K.
+    ID,
+    IID,
+    IIID,
+    // Swiss Enigma K.
+    IK,
+    IIK,
+    IIIK,
+    // Abwehr Enigma G-312.
+    IG,
+    IIG,
+    IIIG,
+    // Reichsbahn (Rocket) Enigma.
+    IR,
+    IIR,
+    IIIR,
+    // Japanese-German Enigma T (Tirpitz).
+    IT,
+    IIT,
+    IIIT,
+    IVT,
+    VT,
+    VIT,
+    VIIT,
+    VIIIT,
 }
 
 enum RotorDirection {
@@ -19,7 +47,9 @@ enum RotorDirection {
     RIGHT,
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "RotorFields"))]
 pub struct Rotor {
+    rotor_type: Option<RotorType>,
     position: usize,
     ring_setting: usize,
     turnover: Vec<usize>,
@@ -29,7 +59,64 @@ pub struct Rotor {
 
 impl Rotor {
     pub fn new(rotor_type: RotorType, key: char, ring_setting: usize) -> Result<Rotor> {
-        let wiring = Rotor::get_rotor_wiring(&rotor_type);
+        let turnover = match rotor_type {
+            RotorType::I => vec![17], // R
+            RotorType::II => vec![5], // F
+            RotorType::III => vec![22], // W
+            RotorType::IV => vec![10], // K
+            RotorType::V => vec![0], // A
+            RotorType::VI => vec![0, 13], // A, N
+            RotorType::VII => vec![0, 13], // A, N
+            RotorType::VIII => vec![0, 13], // A, N
+            RotorType::ID => vec![25], // Z
+            RotorType::IID => vec![5], // F
+            RotorType::IIID => vec![14], // O
+            RotorType::IK => vec![25], // Z
+            RotorType::IIK => vec![5], // F
+            RotorType::IIIK => vec![14], // O
+            RotorType::IG => vec![0, 1, 2, 3, 5, 6, 7, 9, 11, 12, 15, 16, 17, 19, 21, 22, 23], // A B C D F G H J L M P Q R T V W X
+            RotorType::IIG => vec![0, 1, 3, 4, 6, 7, 8, 11, 13, 14, 17, 19, 20, 22, 25], // A B D E G H I L N O R T U W Z
+            RotorType::IIIG => vec![1, 5, 6, 8, 11, 13, 14, 18, 21, 23, 24], // B F G I L N O S V X Y
+            RotorType::IR => vec![14], // O
+            RotorType::IIR => vec![5], // F
+            RotorType::IIIR => vec![25], // Z
+            RotorType::IT => vec![0, 5, 11, 17, 23], // A F L R X
+            RotorType::IIT => vec![0, 6, 12, 18, 23], // A G M S X
+            RotorType::IIIT => vec![0, 5, 11, 17, 23], // A F L R X
+            RotorType::IVT => vec![0, 6, 12, 18, 23], // A G M S X
+            RotorType::VT => vec![3, 6, 11, 18, 25], // D G L S Z
+            RotorType::VIT => vec![5, 9, 13, 17, 24], // F J N R Y
+            RotorType::VIIT => vec![3, 6, 11, 18, 25], // D G L S Z
+            RotorType::VIIIT => vec![5, 9, 13, 17, 24], // F J N R Y
+        };
+
+        let mut rotor = Rotor::from_wiring(Rotor::get_rotor_wiring(&rotor_type), turnover, key, ring_setting)?;
+        rotor.rotor_type = Some(rotor_type);
+
+        Ok(rotor)
+    }
+
+    // Create a rotor that is not one of the known types, from a wiring string such as "EKMFLGDQVZNTOWYHXUSPAIBRCJ" and
+    // the letters shown in the window when the rotor turns over the rotor to its left, such as "Q".
+    pub fn from_wiring_string(wiring: &str, notches: &str, key: char, ring_setting: usize) -> Result<Rotor> {
+        let wiring = utils::get_wiring_from_string(wiring)?;
+        let mut turnover: Vec<usize> = Vec::with_capacity(notches.len());
+
+        for c in notches.chars() {
+            // The turnover happens as the rotor steps off the notch letter.
+            let t = (utils::get_position_from_char(c)? + 1) % 26;
+
+            if turnover.contains(&t) {
+                return Err(Error::RotorError).with_context(|| { format!("Invalid notches {}. Letter {} is used more than once.", notches, c) });
+            }
+
+            turnover.push(t);
+        }
+
+        Rotor::from_wiring(wiring, turnover, key, ring_setting)
+    }
+
+    pub(crate) fn from_wiring(wiring: [usize; 26], turnover: Vec<usize>, key: char, ring_setting: usize) -> Result<Rotor> {
         let wiring_inverse = Rotor::get_rotor_wiring_inverse(&wiring);
 
         if ring_setting < 1 || ring_setting > 26 {
@@ -37,18 +124,10 @@ impl Rotor {
         }
 
         let rotor = Rotor {
+            rotor_type: None,
             position: utils::get_position_from_char(key)?,
             ring_setting: ring_setting - 1,
-            turnover: match rotor_type {
-                RotorType::I => vec![17], // R
-                RotorType::II => vec![5], // F
-                RotorType::III => vec![22], // W
-                RotorType::IV => vec![10], // K
-                RotorType::V => vec![0], // A
-                RotorType::VI => vec![0, 13], // A, N
-                RotorType::VII => vec![0, 13], // A, N
-                RotorType::VIII => vec![0, 13] // A, N
-            },
+            turnover,
             wiring: wiring,
             wiring_inverse: wiring_inverse,
         };
@@ -56,10 +135,22 @@ impl Rotor {
         Ok(rotor)
     }
 
+    pub fn get_rotor_type(&self) -> Option<RotorType> {
+        self.rotor_type
+    }
+
     pub fn get_key(&self) -> char {
         utils::get_char_from_position(self.position).unwrap()
     }
 
+    pub(crate) fn get_position(&self) -> usize {
+        self.position
+    }
+
+    pub(crate) fn set_position(&mut self, position: usize) {
+        self.position = position;
+    }
+
     fn get_rotor_wiring(rotor_type: &RotorType) -> [usize; 26] {
         match rotor_type {
             RotorType::I => return [4, 10, 12, 5, 11, 6, 3, 16, 21, 25, 13, 19, 14, 22, 24, 7, 23, 20, 18, 15, 0, 8, 1, 17, 2, 9],
@@ -70,6 +161,26 @@ impl Rotor {
             RotorType::VI => return [9, 15, 6, 21, 14, 20, 12, 5, 24, 16, 1, 4, 13, 7, 25, 17, 3, 10, 0, 18, 23, 11, 8, 2, 19, 22],
             RotorType::VII => return [13, 25, 9, 7, 6, 17, 2, 23, 12, 24, 18, 22, 1, 14, 20, 5, 0, 8, 21, 11, 15, 4, 10, 16, 3, 19],
             RotorType::VIII => return [5, 10, 16, 7, 19, 11, 23, 14, 2, 1, 9, 18, 15, 3, 25, 17, 0, 12, 4, 22, 13, 8, 20, 24, 6, 21],
+            RotorType::ID => return [11, 15, 6, 18, 25, 12, 7, 0, 4, 14, 16, 10, 21, 23, 17, 5, 24, 1, 20, 19, 13, 8, 2, 9, 3, 22],
+            RotorType::IID => return [18, 11, 21, 6, 1, 19, 5, 23, 9, 16, 14, 7, 4, 22, 8, 17, 25, 24, 0, 12, 10, 15, 2, 13, 3, 20],
+            RotorType::IIID => return [2, 9, 6, 3, 15, 18, 7, 10, 19, 20, 17, 0, 22, 25, 23, 5, 12, 24, 13, 16, 14, 1, 21, 11, 8, 4],
+            RotorType::IK => return [15, 4, 25, 20, 14, 7, 23, 18, 2, 21, 5, 12, 19, 1, 6, 11, 17, 8, 13, 16, 9, 22, 0, 24, 3, 10],
+            RotorType::IIK => return [25, 14, 20, 4, 18, 24, 3, 10, 5, 22, 15, 2, 8, 16, 23, 7, 12, 21, 1, 11, 6, 13, 9, 17, 0, 19],
+            RotorType::IIIK => return [4, 7, 17, 21, 23, 6, 0, 14, 1, 16, 20, 18, 8, 12, 25, 5, 11, 24, 13, 22, 10, 19, 15, 3, 9, 2],
+            RotorType::IG => return [3, 12, 19, 22, 18, 8, 11, 17, 20, 24, 16, 13, 10, 5, 4, 9, 2, 0, 25, 1, 15, 6, 23, 14, 7, 21],
+            RotorType::IIG => return [7, 16, 25, 6, 15, 9, 19, 12, 14, 1, 11, 13, 2, 8, 5, 3, 24, 0, 22, 21, 4, 20, 18, 17, 10, 23],
+            RotorType::IIIG => return [20, 16, 13, 19, 11, 18, 25, 5, 12, 17, 4, 7, 3, 15, 23, 10, 8, 1, 21, 24, 6, 9, 2, 22, 14, 0],
+            RotorType::IR => return [9, 6, 3, 16, 14, 23, 20, 18, 2, 0, 12, 8, 5, 17, 21, 19, 15, 13, 4, 22, 10, 1, 11, 25, 24, 7],
+            RotorType::IIR => return [13, 19, 25, 15, 18, 5, 1, 14, 10, 12, 22, 17, 2, 9, 3, 8, 21, 11, 0, 4, 24, 20, 23, 7, 6, 16],
+            RotorType::IIIR => return [9, 21, 8, 20, 1, 7, 19, 2, 3, 24, 0, 10, 4, 16, 25, 15, 14, 18, 6, 23, 13, 17, 12, 22, 5, 11],
+            RotorType::IT => return [10, 15, 19, 24, 20, 4, 11, 14, 2, 21, 6, 17, 5, 16, 3, 0, 13, 9, 12, 1, 18, 22, 7, 25, 23, 8],
+            RotorType::IIT => return [20, 15, 7, 25, 11, 22, 4, 16, 12, 19, 3, 9, 23, 2, 0, 10, 18, 14, 8, 6, 21, 1, 24, 5, 13, 17],
+            RotorType::IIIT => return [16, 20, 3, 11, 24, 17, 5, 4, 10, 14, 13, 21, 25, 0, 23, 22, 7, 12, 6, 15, 9, 1, 18, 8, 2, 19],
+            RotorType::IVT => return [2, 8, 22, 19, 1, 10, 23, 13, 17, 4, 18, 15, 5, 11, 24, 3, 0, 6, 21, 7, 16, 20, 14, 9, 25, 12],
+            RotorType::VT => return [20, 0, 23, 6, 8, 18, 13, 9, 1, 21, 4, 17, 3, 24, 11, 5, 25, 22, 19, 15, 2, 10, 14, 7, 12, 16],
+            RotorType::VIT => return [23, 5, 20, 25, 6, 0, 11, 21, 7, 2, 13, 24, 18, 4, 22, 16, 19, 3, 12, 17, 1, 10, 15, 8, 14, 9],
+            RotorType::VIIT => return [1, 9, 21, 5, 19, 23, 15, 11, 13, 0, 24, 14, 25, 8, 10, 22, 6, 3, 16, 4, 17, 20, 2, 7, 18, 12],
+            RotorType::VIIIT => return [24, 12, 19, 15, 13, 25, 7, 22, 10, 14, 3, 0, 9, 23, 4, 11, 20, 16, 21, 6, 2, 1, 8, 18, 5, 17],
         };
     }
 
@@ -100,6 +211,26 @@ impl Rotor {
             "vi" => RotorType::VI,
             "vii" => RotorType::VII,
             "viii" => RotorType::VIII,
+            "id" => RotorType::ID,
+            "iid" => RotorType::IID,
+            "iiid" => RotorType::IIID,
+            "ik" => RotorType::IK,
+            "iik" => RotorType::IIK,
+            "iiik" => RotorType::IIIK,
+            "ig" => RotorType::IG,
+            "iig" => RotorType::IIG,
+            "iiig" => RotorType::IIIG,
+            "ir" => RotorType::IR,
+            "iir" => RotorType::IIR,
+            "iiir" => RotorType::IIIR,
+            "it" => RotorType::IT,
+            "iit" => RotorType::IIT,
+            "iiit" => RotorType::IIIT,
+            "ivt" => RotorType::IVT,
+            "vt" => RotorType::VT,
+            "vit" => RotorType::VIT,
+            "viit" => RotorType::VIIT,
+            "viiit" => RotorType::VIIIT,
             _ => return Err(Error::RotorError).with_context(|| { format!("Invalid rotor type {}.", rotor_type) }),
         };
 
@@ -121,6 +252,49 @@ impl Rotor {
         false
     }
 
+    // Return true if the rotor is sitting on a notch, i.e. its next step will turn over the rotor to the left.
+    pub fn is_at_notch(&self) -> bool {
+        self.is_notch(self.position)
+    }
+
+    // Return true if a step from the given position turns over the rotor to the left.
+    pub(crate) fn is_notch(&self, position: usize) -> bool {
+        self.turnover.contains(&((position + 1) % 26))
+    }
+
+    // Return true if two neighbouring positions both have notches, so the rotor can turn over on two steps in a row.
+    pub(crate) fn has_adjacent_notches(&self) -> bool {
+        (0..26).any(|p| self.is_notch(p) && self.is_notch(p + 1))
+    }
+
+    // Count how many of the first steps taken from a position leave a position with a notch, or without one.
+    pub(crate) fn count_steps(&self, from: usize, steps: usize, notch: bool) -> usize {
+        let per_turn = (0..26).filter(|p| self.is_notch(*p) == notch).count();
+        let part = (0..steps % 26).filter(|s| self.is_notch(from + s) == notch).count();
+
+        (steps / 26) * per_turn + part
+    }
+
+    // Return which step taken from a position, counting from 0, is the nth to leave a position with a notch, or without
+    // one, counting from 1.
+    pub(crate) fn find_step(&self, from: usize, nth: usize, notch: bool) -> usize {
+        let per_turn = self.count_steps(from, 26, notch);
+        let turns = (nth - 1) / per_turn;
+        let mut left = nth - turns * per_turn;
+
+        for s in 0..26 {
+            if self.is_notch(from + s) == notch {
+                left -= 1;
+
+                if left == 0 {
+                    return turns * 26 + s;
+                }
+            }
+        }
+
+        unreachable!()
+    }
+
     pub fn scramble_left(&self, input: usize) -> usize {
         return self.scramble(input, RotorDirection::LEFT);
     }
@@ -166,7 +340,42 @@ impl Rotor {
     }
 }
 
+// The fields of a rotor as they are read back, which are checked by building the rotor again. The inverse wiring is
+// worked out from the wiring rather than read.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RotorFields {
+    rotor_type: Option<RotorType>,
+    position: usize,
+    ring_setting: usize,
+    turnover: Vec<usize>,
+    wiring: [usize; 26],
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<RotorFields> for Rotor {
+    type Error = anyhow::Error;
+
+    fn try_from(fields: RotorFields) -> Result<Rotor> {
+        utils::check_wiring(&fields.wiring)?;
+
+        if fields.ring_setting > 25 {
+            return Err(Error::RotorError).with_context(|| { format!("Invalid ring setting position {}. Must be in the range 0 to 25 (inclusive).", fields.ring_setting) });
+        }
+
+        if let Some(t) = fields.turnover.iter().find(|t| **t > 25) {
+            return Err(Error::RotorError).with_context(|| { format!("Invalid turnover position {}. Must be in the range 0 to 25 (inclusive).", t) });
+        }
+
+        let mut rotor = Rotor::from_wiring(fields.wiring, fields.turnover, utils::get_char_from_position(fields.position)?, fields.ring_setting + 1)?;
+        rotor.rotor_type = fields.rotor_type;
+
+        Ok(rotor)
+    }
+}
+
 #[cfg(test)]
+#[allow(clippy::useless_vec)]
 mod test {
     use super::*;
 
@@ -210,6 +419,126 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_rotor_step_turnover_commercial() {
+        for rotor_type in [RotorType::ID, RotorType::IK] {
+            let mut rotor = Rotor::new(rotor_type, 'X', 1).unwrap();
+            assert!(!rotor.step()); // Y - No turnover.
+            assert!(rotor.step()); // Z - Turnover.
+        }
+
+        for rotor_type in [RotorType::IID, RotorType::IIK] {
+            let mut rotor = Rotor::new(rotor_type, 'D', 1).unwrap();
+            assert!(!rotor.step()); // E - No turnover.
+            assert!(rotor.step()); // F - Turnover.
+        }
+
+        for rotor_type in [RotorType::IIID, RotorType::IIIK] {
+            let mut rotor = Rotor::new(rotor_type, 'M', 1).unwrap();
+            assert!(!rotor.step()); // N - No turnover.
+            assert!(rotor.step()); // O - Turnover.
+        }
+    }
+
+    #[test]
+    fn test_rotor_step_turnover_multi_notch() {
+        for (rotor_type, notches) in [(RotorType::IG, 17), (RotorType::IIG, 15), (RotorType::IIIG, 11)] {
+            let mut rotor = Rotor::new(rotor_type, 'A', 1).unwrap();
+            let mut turnovers = 0;
+
+            for _ in 0..26 {
+                if rotor.step() {
+                    turnovers += 1;
+                }
+            }
+
+            assert_eq!(notches, turnovers);
+        }
+
+        let mut rotor = Rotor::new(RotorType::IIIG, 'U', 1).unwrap();
+        assert!(rotor.step()); // V - Turnover.
+        assert!(!rotor.step()); // W - No turnover.
+        assert!(rotor.step()); // X - Turnover.
+    }
+
+    #[test]
+    fn test_rotor_from_wiring_string() {
+        let custom = Rotor::from_wiring_string("EKMFLGDQVZNTOWYHXUSPAIBRCJ", "Q", 'P', 3).unwrap();
+        let rotor = Rotor::new(RotorType::I, 'P', 3).unwrap();
+
+        assert_eq!(None, custom.get_rotor_type());
+
+        for i in 0..26 {
+            assert_eq!(rotor.scramble_left(i), custom.scramble_left(i));
+            assert_eq!(rotor.scramble_right(i), custom.scramble_right(i));
+        }
+
+        let mut custom = Rotor::from_wiring_string("EKMFLGDQVZNTOWYHXUSPAIBRCJ", "QB", 'A', 1).unwrap();
+        assert!(!custom.step()); // B - No turnover.
+        assert!(custom.step()); // C - Turnover.
+    }
+
+    #[test]
+    fn test_rotor_wiring_railway_and_tirpitz() {
+        // Wiring and notches as published by the Crypto Museum for the Reichsbahn and Enigma T rotors. No published message
+        // with full settings could be found for either machine, so they are only checked through their wheels, here and
+        // in the reflector tests.
+        let published = [
+            (RotorType::IR, "JGDQOXUSCAMIFRVTPNEWKBLZYH", "N"),
+            (RotorType::IIR, "NTZPSFBOKMWRCJDIVLAEYUXHGQ", "E"),
+            (RotorType::IIIR, "JVIUBHTCDYAKEQZPOSGXNRMWFL", "Y"),
+            (RotorType::IT, "KPTYUELOCVGRFQDANJMBSWHZXI", "WZEKQ"),
+            (RotorType::IIT, "UPHZLWEQMTDJXCAKSOIGVBYFNR", "WZFLR"),
+            (RotorType::IIIT, "QUDLYRFEKONVZAXWHMGPJBSICT", "WZEKQ"),
+            (RotorType::IVT, "CIWTBKXNRESPFLYDAGVHQUOJZM", "WZFLR"),
+            (RotorType::VT, "UAXGISNJBVERDYLFZWTPCKOHMQ", "YCFKR"),
+            (RotorType::VIT, "XFUZGALVHCNYSEWQTDMRBKPIOJ", "XEIMQ"),
+            (RotorType::VIIT, "BJVFTXPLNAYOZIKWGDQERUCHSM", "YCFKR"),
+            (RotorType::VIIIT, "YMTPNZHWKODAJXELUQVGCBISFR", "XEIMQ"),
+        ];
+
+        for (rotor_type, wiring, notches) in published {
+            let rotor = Rotor::new(rotor_type, 'A', 1).unwrap();
+            let wiring: Vec<usize> = wiring.chars().map(|c| utils::get_position_from_char(c).unwrap()).collect();
+
+            // The turnover happens as the rotor steps off the notch letter.
+            let mut turnover: Vec<usize> = notches.chars().map(|c| (utils::get_position_from_char(c).unwrap() + 1) % 26).collect();
+            turnover.sort();
+
+            assert_eq!(wiring, rotor.wiring, "{:?}", rotor_type);
+            assert_eq!(turnover, rotor.turnover, "{:?}", rotor_type);
+        }
+    }
+
+    #[test]
+    fn test_rotor_from_wiring_string_invalid() {
+        // Not a permutation.
+        assert!(Rotor::from_wiring_string("EKMFLGDQVZNTOWYHXUSPAIBRCE", "Q", 'A', 1).is_err());
+        // Duplicate notch.
+        assert!(Rotor::from_wiring_string("EKMFLGDQVZNTOWYHXUSPAIBRCJ", "QQ", 'A', 1).is_err());
+        // Invalid notch.
+        assert!(Rotor::from_wiring_string("EKMFLGDQVZNTOWYHXUSPAIBRCJ", "q", 'A', 1).is_err());
+    }
+
+    #[test]
+    fn test_rotor_get_rotor_type() {
+        assert_eq!(Some(RotorType::IIK), Rotor::new(RotorType::IIK, 'A', 1).unwrap().get_rotor_type());
+    }
+
+    #[test]
+    fn test_rotor_is_at_notch() {
+        assert!(Rotor::new(RotorType::I, 'Q', 1).unwrap().is_at_notch());
+        assert!(!Rotor::new(RotorType::I, 'R', 1).unwrap().is_at_notch());
+        assert!(Rotor::new(RotorType::II, 'E', 1).unwrap().is_at_notch());
+        assert!(Rotor::new(RotorType::V, 'Z', 1).unwrap().is_at_notch());
+
+        for rotor_type in [RotorType::VI, RotorType::VII, RotorType::VIII] {
+            assert!(Rotor::new(rotor_type, 'Z', 1).unwrap().is_at_notch());
+            assert!(Rotor::new(rotor_type, 'M', 1).unwrap().is_at_notch());
+            assert!(!Rotor::new(rotor_type, 'N', 1).unwrap().is_at_notch());
+        }
+    }
+
     #[test]
     fn test_rotor_scramble_left() {
         let rotor = Rotor::new(RotorType::I, 'A', 1).unwrap();
@@ -271,6 +600,25 @@ mod test {
 
         assert_eq!(RotorType::VIII, Rotor::get_rotor_type_from_string("VIII").unwrap());
         assert_eq!(RotorType::VIII, Rotor::get_rotor_type_from_string("viii").unwrap());
+
+        assert_eq!(RotorType::ID, Rotor::get_rotor_type_from_string("ID").unwrap());
+        assert_eq!(RotorType::IID, Rotor::get_rotor_type_from_string("iid").unwrap());
+        assert_eq!(RotorType::IIID, Rotor::get_rotor_type_from_string("IIID").unwrap());
+
+        assert_eq!(RotorType::IK, Rotor::get_rotor_type_from_string("IK").unwrap());
+        assert_eq!(RotorType::IIK, Rotor::get_rotor_type_from_string("iik").unwrap());
+        assert_eq!(RotorType::IIIK, Rotor::get_rotor_type_from_string("IIIK").unwrap());
+
+        assert_eq!(RotorType::IG, Rotor::get_rotor_type_from_string("IG").unwrap());
+        assert_eq!(RotorType::IIG, Rotor::get_rotor_type_from_string("iig").unwrap());
+        assert_eq!(RotorType::IIIG, Rotor::get_rotor_type_from_string("IIIG").unwrap());
+
+        assert_eq!(RotorType::IR, Rotor::get_rotor_type_from_string("IR").unwrap());
+        assert_eq!(RotorType::IIIR, Rotor::get_rotor_type_from_string("iiir").unwrap());
+
+        assert_eq!(RotorType::IT, Rotor::get_rotor_type_from_string("IT").unwrap());
+        assert_eq!(RotorType::IVT, Rotor::get_rotor_type_from_string("ivt").unwrap());
+        assert_eq!(RotorType::VIIIT, Rotor::get_rotor_type_from_string("VIIIT").unwrap());
     }
 
     #[test]
@@ -0,0 +1,470 @@
+use crate::rotor::{Rotor, RotorType};
+use crate::reflector::{Reflector, ReflectorType};
+use crate::greek_wheel::{GreekWheel, GreekWheelType};
+use crate::plugboard::Plugboard;
+use crate::model::MachineModel;
+use crate::error::Error;
+use crate::{Enigma, InputPolicy};
+use crate::utils;
+use anyhow::{Context, Result};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+// Everything an operator would take from a key sheet to set up a machine. The rotor order (Walzenlage) runs from left to
+// right. With a greek wheel its ring setting and key come first in the ring settings (Ringstellung) and the key
+// (Grundstellung), ahead of those for the three rotors.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MachineSettings {
+    pub model: Option<MachineModel>,
+    pub reflector: ReflectorType,
+    pub reflector_key: char,
+    pub reflector_ring: usize,
+    pub greek_wheel: Option<GreekWheelType>,
+    pub rotors: Vec<RotorType>,
+    pub rings: Vec<usize>,
+    pub key: Vec<char>,
+    pub plugs: Vec<[char; 2]>,
+    // How the machine treats text other than upper case letters. This isn't part of the key sheet notation.
+    pub input_policy: InputPolicy,
+    pub preserve_case: bool,
+}
+
+impl MachineSettings {
+    pub fn new(reflector: ReflectorType, rotors: &[RotorType], rings: &[usize], key: &str, plugs: &[[char; 2]]) -> MachineSettings {
+        MachineSettings {
+            model: None,
+            reflector,
+            reflector_key: 'A',
+            reflector_ring: 1,
+            greek_wheel: None,
+            rotors: rotors.to_vec(),
+            rings: rings.to_vec(),
+            key: key.chars().collect(),
+            plugs: plugs.to_vec(),
+            input_policy: InputPolicy::Error,
+            preserve_case: false,
+        }
+    }
+
+    // A reflector that has been turned or had its ring moved is written out as an extra wheel on the left, as it is for
+    // the models where it can be set.
+    fn has_reflector_setting(&self) -> bool {
+        self.reflector_key != 'A' || self.reflector_ring != 1 || self.model.is_some_and(|m| m.has_settable_reflector())
+    }
+
+    // Check all the settings together, reporting every problem found rather than just the first.
+    pub fn validate(&self) -> Result<()> {
+        let problems = self.get_problems();
+
+        if !problems.is_empty() {
+            return Err(Error::SettingsError).with_context(|| { format!("Invalid machine settings: {}.", problems.join("; ")) });
+        }
+
+        Ok(())
+    }
+
+    fn get_problems(&self) -> Vec<String> {
+        let mut problems: Vec<String> = Vec::new();
+        let wheel_count = self.rotors.len() + self.greek_wheel.map_or(0, |_| 1);
+
+        if self.rotors.len() != 3 {
+            problems.push(format!("{} rotors given, must be 3", self.rotors.len()));
+        }
+
+        // Only report a duplicated rotor the first time it comes round again.
+        for (i, rotor) in self.rotors.iter().enumerate() {
+            if self.rotors[..i].iter().filter(|r| *r == rotor).count() == 1 {
+                problems.push(format!("rotor {:?} is used more than once", rotor));
+            }
+        }
+
+        if self.rings.len() != wheel_count {
+            problems.push(format!("{} ring settings given, must be {}", self.rings.len(), wheel_count));
+        }
+
+        for ring in &self.rings {
+            if !(1..=26).contains(ring) {
+                problems.push(format!("ring setting {} must be in the range 1 to 26", ring));
+            }
+        }
+
+        if !(1..=26).contains(&self.reflector_ring) {
+            problems.push(format!("reflector ring setting {} must be in the range 1 to 26", self.reflector_ring));
+        }
+
+        if self.key.len() != wheel_count {
+            problems.push(format!("{} key letters given, must be {}", self.key.len(), wheel_count));
+        }
+
+        for c in self.key.iter().chain(std::iter::once(&self.reflector_key)) {
+            if !c.is_ascii_uppercase() {
+                problems.push(format!("key {} must be a letter from A to Z", c));
+            }
+        }
+
+        if let Err(e) = Plugboard::new(&self.plugs) {
+            problems.push(e.to_string().trim_end_matches('!').to_string());
+        }
+
+        if self.greek_wheel.is_some() && self.reflector != ReflectorType::ThinB && self.reflector != ReflectorType::ThinC {
+            problems.push(format!("reflector {:?} does not fit alongside a greek wheel, must be ThinB or ThinC", self.reflector));
+        }
+
+        if let Some(model) = self.model {
+            if !model.get_reflector_types().contains(&self.reflector) {
+                problems.push(format!("reflector {:?} does not fit the {:?}, must be one of {:?}", self.reflector, model, model.get_reflector_types()));
+            }
+
+            if (self.reflector_key != 'A' || self.reflector_ring != 1) && !model.has_settable_reflector() {
+                problems.push(format!("the reflector of the {:?} cannot be set", model));
+            }
+
+            if self.greek_wheel.is_some() != model.has_greek_wheel() {
+                problems.push(match model.has_greek_wheel() {
+                    true => format!("the {:?} requires a greek wheel", model),
+                    false => format!("the {:?} does not take a greek wheel", model),
+                });
+            }
+
+            for rotor in &self.rotors {
+                if !model.get_rotor_types().contains(rotor) {
+                    problems.push(format!("rotor {:?} does not fit the {:?}, must be one of {:?}", rotor, model, model.get_rotor_types()));
+                }
+            }
+
+            if !model.has_plugboard() && !self.plugs.is_empty() {
+                problems.push(format!("the {:?} does not have a plugboard", model));
+            }
+        }
+
+        problems
+    }
+
+    // Validate the settings and set up a machine from them.
+    pub fn build(&self) -> Result<Enigma> {
+        self.validate()?;
+
+        let offset = self.greek_wheel.map_or(0, |_| 1);
+        let mut rotors: Vec<Rotor> = Vec::with_capacity(self.rotors.len());
+
+        for (i, rotor_type) in self.rotors.iter().enumerate() {
+            rotors.push(Rotor::new(*rotor_type, self.key[i + offset], self.rings[i + offset])?);
+        }
+
+        let greek_wheel = match self.greek_wheel {
+            Some(greek_wheel_type) => Some(GreekWheel::new(greek_wheel_type, self.key[0], self.rings[0])?),
+            None => None,
+        };
+
+        let reflector = Reflector::with_setting(self.reflector, self.reflector_key, self.reflector_ring)?;
+        let plugboard = Plugboard::new(&self.plugs)?;
+
+        let mut enigma = match (self.model, greek_wheel) {
+            (Some(model), greek_wheel) => Enigma::new_with_model(model, reflector, greek_wheel, rotors, plugboard)?,
+            (None, Some(greek_wheel)) => {
+                let mut enigma = Enigma::new_m4(self.reflector, greek_wheel, rotors, plugboard)?;
+                enigma.set_reflector(reflector);
+                enigma
+            },
+            (None, None) => Enigma::new_with_reflector(reflector, rotors, plugboard),
+        };
+
+        enigma.set_input_policy(self.input_policy, self.preserve_case);
+
+        Ok(enigma)
+    }
+}
+
+// Settings are written the way they appear on a key sheet, for example 'B II IV V 02 21 12 BLA AV BS CG DL FU HZ IN KM OW
+// RX'. That is an optional model, the reflector, an optional greek wheel, the rotor order, the ring settings as numbers
+// or single letters, the key as one word and finally the plug pairs. The key can be left out, in which case every wheel
+// starts at A. A settable reflector takes the first ring setting and the first letter of the key.
+impl FromStr for MachineSettings {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<MachineSettings> {
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        let mut i = 0;
+
+        let invalid = |message: String| {
+            Err(Error::SettingsError).with_context(|| { format!("Invalid settings '{}'. {}", s.trim(), message) })
+        };
+
+        // Railway is both a model and a reflector, so only take the first word as the model if a reflector follows it.
+        let mut model = None;
+
+        if tokens.len() > 1 && Reflector::get_reflector_type_from_string(tokens[1]).is_ok() {
+            if let Ok(m) = MachineModel::get_machine_model_from_string(tokens[0]) {
+                model = Some(m);
+                i += 1;
+            }
+        }
+
+        let reflector = match tokens.get(i).map(|t| Reflector::get_reflector_type_from_string(t)) {
+            Some(Ok(reflector)) => reflector,
+            Some(Err(_)) => return invalid(format!("Expected a reflector, got {}.", tokens[i])),
+            None => return invalid(String::from("Expected a reflector.")),
+        };
+
+        i += 1;
+
+        let greek_wheel = match tokens.get(i).map(|t| GreekWheel::get_greek_wheel_type_from_string(t)) {
+            Some(Ok(greek_wheel)) => {
+                i += 1;
+                Some(greek_wheel)
+            },
+            _ => None,
+        };
+
+        // There are never more than three rotors, which stops a ring setting such as 'V' being read as another rotor.
+        let mut rotors: Vec<RotorType> = Vec::new();
+
+        while rotors.len() < 3 {
+            match tokens.get(i).map(|t| Rotor::get_rotor_type_from_string(t)) {
+                Some(Ok(rotor)) => rotors.push(rotor),
+                _ => break,
+            }
+
+            i += 1;
+        }
+
+        let mut rings: Vec<usize> = Vec::new();
+
+        while let Some(token) = tokens.get(i) {
+            if token.len() <= 2 && token.chars().all(|c| c.is_ascii_digit()) {
+                rings.push(token.parse::<usize>()?);
+            }
+            else if token.len() == 1 && token.chars().all(|c| c.is_ascii_alphabetic()) {
+                rings.push(utils::get_position_from_char(token.to_ascii_uppercase().chars().next().unwrap())? + 1);
+            }
+            else {
+                break;
+            }
+
+            i += 1;
+        }
+
+        // Plug pairs are always two letters, so any other word of letters is the key.
+        let key: Option<Vec<char>> = match tokens.get(i) {
+            Some(token) if token.len() != 2 && token.chars().all(|c| c.is_ascii_alphabetic()) => {
+                i += 1;
+                Some(token.to_ascii_uppercase().chars().collect())
+            },
+            _ => None,
+        };
+
+        let mut plugs: Vec<[char; 2]> = Vec::new();
+
+        for token in &tokens[i..] {
+            let pair: Vec<char> = token.to_ascii_uppercase().chars().collect();
+
+            if pair.len() != 2 || !pair.iter().all(|c| c.is_ascii_uppercase()) {
+                return invalid(format!("Expected a plug pair, got {}.", token));
+            }
+
+            plugs.push([pair[0], pair[1]]);
+        }
+
+        let wheel_count = rotors.len() + greek_wheel.map_or(0, |_| 1);
+        let mut key = key.unwrap_or_else(|| vec!['A'; rings.len()]);
+
+        let mut settings = MachineSettings::new(reflector, &rotors, &rings, "", &plugs);
+        settings.model = model;
+        settings.greek_wheel = greek_wheel;
+
+        // One more ring setting than there are wheels means the reflector has been set as well.
+        if rings.len() == wheel_count + 1 {
+            settings.reflector_ring = settings.rings.remove(0);
+
+            if key.len() == wheel_count + 1 {
+                settings.reflector_key = key.remove(0);
+            }
+        }
+
+        settings.key = key;
+
+        Ok(settings)
+    }
+}
+
+impl Display for MachineSettings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut words: Vec<String> = Vec::new();
+
+        if let Some(model) = self.model {
+            words.push(format!("{:?}", model));
+        }
+
+        words.push(format!("{:?}", self.reflector));
+
+        if let Some(greek_wheel) = self.greek_wheel {
+            words.push(format!("{:?}", greek_wheel));
+        }
+
+        for rotor in &self.rotors {
+            words.push(format!("{:?}", rotor));
+        }
+
+        let mut key = String::new();
+
+        if self.has_reflector_setting() {
+            words.push(format!("{:02}", self.reflector_ring));
+            key.push(self.reflector_key);
+        }
+
+        for ring in &self.rings {
+            words.push(format!("{:02}", ring));
+        }
+
+        key.extend(self.key.iter());
+        words.push(key);
+
+        for plug in &self.plugs {
+            words.push(format!("{}{}", plug[0], plug[1]));
+        }
+
+        write!(f, "{}", words.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_machine_settings_build() {
+        let settings = MachineSettings::new(ReflectorType::B, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1], "AAA", &[]);
+        let mut enigma = settings.build().unwrap();
+
+        assert_eq!("BDZGO", enigma.encrypt("AAAAA").unwrap());
+    }
+
+    #[test]
+    fn test_machine_settings_build_input_policy() {
+        let mut settings = MachineSettings::new(ReflectorType::B, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1], "AAA", &[]);
+        settings.input_policy = InputPolicy::Strip;
+        settings.preserve_case = true;
+
+        let mut enigma = settings.build().unwrap();
+
+        assert_eq!("BDzGO", enigma.encrypt("AA a. AA").unwrap());
+    }
+
+    #[test]
+    fn test_machine_settings_build_m4() {
+        let mut settings = MachineSettings::new(ReflectorType::ThinB, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1, 1], "AAAA", &[]);
+        settings.model = Some(MachineModel::M4);
+        settings.greek_wheel = Some(GreekWheelType::Beta);
+
+        // Beta at A with the thin B reflector is the same as the three rotor machine with reflector B.
+        let mut enigma = settings.build().unwrap();
+
+        assert_eq!("BDZGO", enigma.encrypt("AAAAA").unwrap());
+    }
+
+    #[test]
+    fn test_machine_settings_single_error() {
+        let mut settings = MachineSettings::new(ReflectorType::C, &[RotorType::I, RotorType::I, RotorType::ID], &[1, 1], "AAA", &[['A', 'B'], ['B', 'C']]);
+        settings.model = Some(MachineModel::M3);
+
+        let e = settings.build().err().unwrap();
+        let message = format!("{:#}", e);
+
+        assert!(message.contains("rotor I is used more than once"));
+        assert!(message.contains("2 ring settings given, must be 3"));
+        assert!(message.contains("already in use"));
+        assert!(message.contains("rotor ID does not fit the M3"));
+        assert!(!message.contains("reflector"));
+    }
+
+    #[test]
+    fn test_machine_settings_invalid() {
+        // Too few rotors.
+        let settings = MachineSettings::new(ReflectorType::B, &[RotorType::I, RotorType::II], &[1, 1], "AA", &[]);
+        assert!(settings.validate().is_err());
+
+        // Greek wheel with a thick reflector.
+        let mut settings = MachineSettings::new(ReflectorType::B, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1, 1], "AAAA", &[]);
+        settings.greek_wheel = Some(GreekWheelType::Beta);
+        assert!(settings.validate().is_err());
+
+        // Bad key letters.
+        let settings = MachineSettings::new(ReflectorType::B, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1], "a1A", &[]);
+        assert!(settings.validate().is_err());
+
+        // The M3 reflector cannot be set.
+        let mut settings = MachineSettings::new(ReflectorType::B, &[RotorType::I, RotorType::II, RotorType::III], &[1, 1, 1], "AAA", &[]);
+        settings.model = Some(MachineModel::M3);
+        settings.reflector_key = 'C';
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_machine_settings_from_str() {
+        let settings: MachineSettings = "B II IV V 02 21 12 AV BS CG DL FU HZ IN KM OW RX".parse().unwrap();
+
+        assert_eq!(None, settings.model);
+        assert_eq!(ReflectorType::B, settings.reflector);
+        assert_eq!(vec![RotorType::II, RotorType::IV, RotorType::V], settings.rotors);
+        assert_eq!(vec![2, 21, 12], settings.rings);
+        assert_eq!(vec!['A', 'A', 'A'], settings.key);
+        assert_eq!(10, settings.plugs.len());
+        assert_eq!(['A', 'V'], settings.plugs[0]);
+
+        // Ring settings as letters and a key.
+        let letters: MachineSettings = "B II IV V B U L BLA AV BS CG DL FU HZ IN KM OW RX".parse().unwrap();
+
+        assert_eq!(vec![2, 21, 12], letters.rings);
+        assert_eq!(vec!['B', 'L', 'A'], letters.key);
+    }
+
+    #[test]
+    fn test_machine_settings_from_str_model() {
+        let settings: MachineSettings = "M4 ThinB Beta II IV I 01 01 01 22 VJNA AT BL DF GJ HM NW OP QY RZ VX".parse().unwrap();
+
+        assert_eq!(Some(MachineModel::M4), settings.model);
+        assert_eq!(Some(GreekWheelType::Beta), settings.greek_wheel);
+        assert_eq!(vec![1, 1, 1, 22], settings.rings);
+        assert!(settings.validate().is_ok());
+
+        // Railway is also the name of the reflector, which is what it is when nothing else comes before it.
+        let railway: MachineSettings = "Railway IR IIR IIIR 01 01 01 AAA".parse().unwrap();
+
+        assert_eq!(None, railway.model);
+        assert_eq!(ReflectorType::Railway, railway.reflector);
+
+        // The settable reflector comes first.
+        let railway: MachineSettings = "Railway Railway IR IIR IIIR 05 01 01 01 QAAA".parse().unwrap();
+
+        assert_eq!(Some(MachineModel::Railway), railway.model);
+        assert_eq!('Q', railway.reflector_key);
+        assert_eq!(5, railway.reflector_ring);
+        assert_eq!(vec!['A', 'A', 'A'], railway.key);
+    }
+
+    #[test]
+    fn test_machine_settings_from_str_invalid() {
+        assert!("".parse::<MachineSettings>().is_err());
+        assert!("X II IV V 02 21 12".parse::<MachineSettings>().is_err());
+        assert!("B II IV V 02 21 12 AV B".parse::<MachineSettings>().is_err());
+        assert!("B II IV V 02 21 12 A1".parse::<MachineSettings>().is_err());
+    }
+
+    #[test]
+    fn test_machine_settings_display_round_trip() {
+        let notations = [
+            "B II IV V 02 21 12 BLA AV BS CG DL FU HZ IN KM OW RX",
+            "M4 ThinB Beta II IV I 01 01 01 22 VJNA AT BL DF GJ HM NW OP QY RZ VX",
+            "EnigmaG G IG IIG IIIG 03 01 02 01 ZAQB",
+            "M3 C VIII VII VI 26 01 13 ZZZ",
+        ];
+
+        for notation in notations {
+            let settings: MachineSettings = notation.parse().unwrap();
+
+            assert_eq!(notation, settings.to_string());
+            assert_eq!(settings, settings.to_string().parse().unwrap());
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use crate::Enigma;
+use std::io::{self, Read, Write};
+
+// The most the writer encrypts at a time before handing it on.
+const CHUNK_SIZE: usize = 4096;
+
+// Input the machine's input policy rejects is invalid data as far as the reader and writer are concerned.
+fn invalid_data(e: anyhow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+// Encrypts everything read from the inner reader, following the machine's input policy just like Enigma::encrypt. The
+// rotors carry on from one read to the next, so the output is the same however the input happens to be split up.
+pub struct EnigmaReader<R: Read> {
+    enigma: Enigma,
+    inner: R,
+}
+
+impl<R: Read> EnigmaReader<R> {
+    pub fn new(enigma: Enigma, inner: R) -> EnigmaReader<R> {
+        EnigmaReader {
+            enigma,
+            inner,
+        }
+    }
+
+    pub fn get_enigma(&self) -> &Enigma {
+        &self.enigma
+    }
+
+    pub fn into_inner(self) -> (Enigma, R) {
+        (self.enigma, self.inner)
+    }
+}
+
+impl<R: Read> Read for EnigmaReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let count = self.inner.read(buf)?;
+
+            // The machine checks everything first, so the rotors are only moved for input that was actually encrypted.
+            let length = self.enigma.encrypt_in_place(&mut buf[..count]).map_err(invalid_data)?;
+
+            // A read that was stripped down to nothing isn't the end of the input.
+            if length > 0 || count == 0 {
+                return Ok(length);
+            }
+        }
+    }
+}
+
+// Encrypts everything written to it before writing it on to the inner writer, a chunk at a time.
+pub struct EnigmaWriter<W: Write> {
+    enigma: Enigma,
+    inner: W,
+    chunk: [u8; CHUNK_SIZE],
+}
+
+impl<W: Write> EnigmaWriter<W> {
+    pub fn new(enigma: Enigma, inner: W) -> EnigmaWriter<W> {
+        EnigmaWriter {
+            enigma,
+            inner,
+            chunk: [0; CHUNK_SIZE],
+        }
+    }
+
+    pub fn get_enigma(&self) -> &Enigma {
+        &self.enigma
+    }
+
+    pub fn into_inner(self) -> (Enigma, W) {
+        (self.enigma, self.inner)
+    }
+}
+
+impl<W: Write> Write for EnigmaWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = buf.len().min(CHUNK_SIZE);
+        let chunk = &mut self.chunk[..count];
+
+        chunk.copy_from_slice(&buf[..count]);
+        let length = self.enigma.encrypt_in_place(chunk).map_err(invalid_data)?;
+
+        // The rotors have already moved for the whole chunk, so all of it has to be passed on.
+        self.inner.write_all(&chunk[..length])?;
+
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{InputPolicy, MachineSettings};
+
+    const SETTINGS: &str = "B II IV V 02 21 12 BLA AV BS CG DL FU HZ IN KM OW RX";
+
+    // Hands the input out a few bytes at a time, to split it up at awkward places.
+    struct Trickle<'a> {
+        input: &'a [u8],
+        size: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.size = self.size % 7 + 1;
+
+            let count = self.size.min(buf.len()).min(self.input.len());
+            buf[..count].copy_from_slice(&self.input[..count]);
+            self.input = &self.input[count..];
+
+            Ok(count)
+        }
+    }
+
+    fn get_input() -> String {
+        "EDPUD NRGYS ZRCXN UYTPO MRMBO FKTBZ REZKM LXLVE FGUEY SIOZV EQMIK UBPMM YLKLT TDEIS\n".repeat(200)
+    }
+
+    #[test]
+    fn test_enigma_reader() {
+        let settings: MachineSettings = SETTINGS.parse().unwrap();
+        let input = get_input();
+        let expected = settings.build().unwrap().encrypt(&input).unwrap();
+
+        let mut reader = EnigmaReader::new(settings.build().unwrap(), Trickle { input: input.as_bytes(), size: 0 });
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_enigma_writer() {
+        let settings: MachineSettings = SETTINGS.parse().unwrap();
+        let input = get_input();
+        let expected = settings.build().unwrap().encrypt(&input).unwrap();
+
+        let mut writer = EnigmaWriter::new(settings.build().unwrap(), Vec::new());
+
+        for piece in input.as_bytes().chunks(5000).flat_map(|c| c.chunks(13)) {
+            writer.write_all(piece).unwrap();
+        }
+
+        writer.flush().unwrap();
+
+        let (_, output) = writer.into_inner();
+        assert_eq!(expected.as_bytes(), &output[..]);
+
+        // Larger than a chunk in one go.
+        let mut writer = EnigmaWriter::new(settings.build().unwrap(), Vec::new());
+        writer.write_all(input.as_bytes()).unwrap();
+
+        let (_, output) = writer.into_inner();
+        assert_eq!(expected.as_bytes(), &output[..]);
+    }
+
+    #[test]
+    fn test_enigma_stream_invalid() {
+        let settings: MachineSettings = SETTINGS.parse().unwrap();
+
+        let mut reader = EnigmaReader::new(settings.build().unwrap(), "HELLO world".as_bytes());
+        let mut output = String::new();
+
+        assert_eq!(io::ErrorKind::InvalidData, reader.read_to_string(&mut output).unwrap_err().kind());
+        assert_eq!(settings.build().unwrap().snapshot(), reader.get_enigma().snapshot());
+
+        let mut writer = EnigmaWriter::new(settings.build().unwrap(), Vec::new());
+
+        assert_eq!(io::ErrorKind::InvalidData, writer.write(b"HELLO, WORLD").unwrap_err().kind());
+        assert_eq!(settings.build().unwrap().snapshot(), writer.get_enigma().snapshot());
+    }
+
+    #[test]
+    fn test_enigma_stream_input_policy() {
+        let settings: MachineSettings = SETTINGS.parse().unwrap();
+        // Long runs of punctuation mean some reads are stripped down to nothing.
+        let input = "Edpud nrgys, zrcxn... uytpo!!!!!!!!!!!! mrmbo fktbz\n".repeat(200);
+
+        for (policy, preserve_case) in [(InputPolicy::Strip, false), (InputPolicy::Fold, true), (InputPolicy::PassThrough, true)] {
+            let build = || {
+                let mut enigma = settings.build().unwrap();
+                enigma.set_input_policy(policy, preserve_case);
+                enigma
+            };
+
+            let input = match policy {
+                InputPolicy::Fold => input.replace(|c: char| c.is_ascii_punctuation(), ""),
+                _ => input.clone(),
+            };
+
+            let expected = build().encrypt(&input).unwrap();
+
+            let mut reader = EnigmaReader::new(build(), Trickle { input: input.as_bytes(), size: 0 });
+            let mut output = String::new();
+            reader.read_to_string(&mut output).unwrap();
+
+            assert_eq!(expected, output, "{:?}", policy);
+
+            let mut writer = EnigmaWriter::new(build(), Vec::new());
+
+            for piece in input.as_bytes().chunks(13) {
+                writer.write_all(piece).unwrap();
+            }
+
+            let (_, output) = writer.into_inner();
+            assert_eq!(expected.as_bytes(), &output[..], "{:?}", policy);
+        }
+
+        // Fold still rejects anything that isn't a letter or whitespace.
+        let mut enigma = settings.build().unwrap();
+        enigma.set_input_policy(InputPolicy::Fold, false);
+
+        let mut writer = EnigmaWriter::new(enigma, Vec::new());
+
+        assert_eq!(io::ErrorKind::InvalidData, writer.write(b"hello, world").unwrap_err().kind());
+        assert_eq!(settings.build().unwrap().snapshot(), writer.get_enigma().snapshot());
+    }
+}
@@ -0,0 +1,272 @@
+use crate::error::Error;
+use anyhow::{Context, Result};
+use std::fmt::{Display, Formatter};
+
+// How a message is laid out for sending. Enigma traffic went out in groups of letters, five at a time for the Army and
+// Luftwaffe and four for the Navy, with a fixed number of groups to a line.
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GroupFormat {
+    pub group_size: usize,
+    pub groups_per_line: usize,
+    pub line_numbers: bool,
+}
+
+impl Default for GroupFormat {
+    fn default() -> Self {
+        GroupFormat::army()
+    }
+}
+
+impl GroupFormat {
+    pub fn new(group_size: usize, groups_per_line: usize, line_numbers: bool) -> Result<GroupFormat> {
+        if group_size == 0 {
+            return Err(Error::InputError).with_context(|| "Invalid group size 0. Groups need at least one letter.");
+        }
+
+        if groups_per_line == 0 {
+            return Err(Error::InputError).with_context(|| "Invalid groups per line 0. Lines need at least one group.");
+        }
+
+        Ok(GroupFormat {
+            group_size,
+            groups_per_line,
+            line_numbers,
+        })
+    }
+
+    pub fn army() -> GroupFormat {
+        GroupFormat {
+            group_size: 5,
+            groups_per_line: 10,
+            line_numbers: false,
+        }
+    }
+
+    pub fn navy() -> GroupFormat {
+        GroupFormat {
+            group_size: 4,
+            groups_per_line: 10,
+            line_numbers: false,
+        }
+    }
+
+    // Lay the text out in groups, ignoring any whitespace it already has. The last group is left short rather than
+    // padded, as padding would change the message.
+    pub fn format(&self, text: &str) -> String {
+        let letters: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let groups: Vec<String> = letters.chunks(self.group_size.max(1)).map(|g| g.iter().collect()).collect();
+
+        self.format_groups(&groups)
+    }
+
+    // Lay out groups that are already made up, such as those of a message where a short group comes before the indicator
+    // is repeated, a line at a time.
+    pub fn format_groups<S: AsRef<str>>(&self, groups: &[S]) -> String {
+        groups.chunks(self.groups_per_line.max(1))
+            .map(|l| l.iter().map(|g| g.as_ref()).collect::<Vec<&str>>().join(" "))
+            .enumerate()
+            .map(|(i, line)| match self.line_numbers {
+                true => format!("{:03} {}", i + 1, line),
+                false => line,
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+// A message as it was received, split into the header, the Kenngruppe and the groups that are to be decrypted.
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Message {
+    // The fields of the header, such as the time, the letter count and the indicator groups.
+    pub header: Vec<String>,
+    // The group identifying the key, which was sent in the clear ahead of the message.
+    pub kenngruppe: Option<String>,
+    pub groups: Vec<String>,
+    // How the groups are laid out when the message is sent again, as they came in when it was parsed.
+    pub format: GroupFormat,
+}
+
+impl Message {
+    // Parse received traffic. Anything up to the last '=' on a line is header, fields being separated by '=' as in
+    // "1510 = 49 = EHZ TBS =". Line numbers are skipped and everything else has to be groups of letters. When the
+    // message starts with a Kenngruppe, the first group is taken as it rather than as part of the message.
+    pub fn parse(traffic: &str, kenngruppe: bool) -> Result<Message> {
+        let mut message = Message::default();
+        let mut format = GroupFormat { group_size: 0, groups_per_line: 0, line_numbers: false };
+
+        for line in traffic.lines() {
+            let body = match line.rfind('=') {
+                Some(i) => {
+                    message.header.extend(line[..i].split('=').map(|f| f.trim()).filter(|f| !f.is_empty()).map(|f| f.to_string()));
+                    &line[(i + 1)..]
+                },
+                None => line,
+            };
+
+            let mut count = 0;
+
+            for (i, token) in body.split_whitespace().enumerate() {
+                if i == 0 && Message::is_line_number(token) {
+                    format.line_numbers = true;
+                    continue;
+                }
+
+                if !token.chars().all(|c| c.is_ascii_alphabetic()) {
+                    return Err(Error::InputError).with_context(|| { format!("Invalid group {} in line '{}'. Groups must only be letters.", token, line.trim()) });
+                }
+
+                message.groups.push(token.to_ascii_uppercase());
+                format.group_size = format.group_size.max(token.len());
+                count += 1;
+            }
+
+            format.groups_per_line = format.groups_per_line.max(count);
+        }
+
+        // The groups and lines are full but for the last, so the longest give the layout it was sent in.
+        if !message.groups.is_empty() {
+            message.format = format;
+        }
+
+        if kenngruppe {
+            message.take_kenngruppe();
+        }
+
+        Ok(message)
+    }
+
+    // Parse traffic holding several messages, such as the parts of a long message, one after the other. A header after
+    // some groups starts the next message.
+    pub fn parse_all(traffic: &str, kenngruppe: bool) -> Result<Vec<Message>> {
+        let mut messages = Vec::new();
+        let mut lines: Vec<&str> = Vec::new();
+
+        for line in traffic.lines() {
+            let groups = lines.iter().any(|l| l.rsplit('=').next().is_some_and(|b| !b.trim().is_empty()));
+
+            if line.contains('=') && groups {
+                messages.push(Message::parse(&lines.join("\n"), kenngruppe)?);
+                lines.clear();
+            }
+
+            lines.push(line);
+        }
+
+        if lines.iter().any(|l| !l.trim().is_empty()) {
+            messages.push(Message::parse(&lines.join("\n"), kenngruppe)?);
+        }
+
+        Ok(messages)
+    }
+
+    // Numbers such as "001" or "12:" at the start of a line.
+    fn is_line_number(token: &str) -> bool {
+        let digits = token.trim_end_matches([':', '.', ')']);
+
+        !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+    }
+
+    // Take the first group as the Kenngruppe, returning false if there are no groups to take.
+    pub fn take_kenngruppe(&mut self) -> bool {
+        if self.groups.is_empty() {
+            return false;
+        }
+
+        self.kenngruppe = Some(self.groups.remove(0));
+        true
+    }
+
+    // The groups run together, ready to be decrypted.
+    pub fn get_text(&self) -> String {
+        self.groups.concat()
+    }
+}
+
+// Lays the message out as it would be sent, the header on its own line and the groups after it in the message's format.
+impl Display for Message {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if !self.header.is_empty() {
+            writeln!(f, "{} =", self.header.join(" = "))?;
+        }
+
+        let groups: Vec<&String> = self.kenngruppe.iter().chain(self.groups.iter()).collect();
+
+        write!(f, "{}", self.format.format_groups(&groups))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_group_format() {
+        let text = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+        assert_eq!("ABCDE FGHIJ KLMNO PQRST UVWXY Z", GroupFormat::army().format(text));
+        assert_eq!("ABCD EFGH IJKL MNOP QRST UVWX YZ", GroupFormat::navy().format(text));
+
+        let format = GroupFormat::new(5, 2, true).unwrap();
+        assert_eq!("001 ABCDE FGHIJ\n002 KLMNO PQRST\n003 UVWXY Z", format.format(text));
+
+        // Existing spacing is ignored.
+        assert_eq!("ABCDE FGHIJ KLMNO PQRST UVWXY Z", GroupFormat::army().format("ABC DEF GHIJKL\nMNOPQRSTUVWXYZ"));
+        assert_eq!("", GroupFormat::army().format(""));
+
+        assert!(GroupFormat::new(0, 10, false).is_err());
+        assert!(GroupFormat::new(5, 0, false).is_err());
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let traffic = "U6Z DE C 1510 = 49 = EHZ TBS =\n\
+                       001 TVEXS QBLTW LDAHH YEOEF\n\
+                       002 PTWYB LENDP MKOXL DFAMU\n\
+                       003 DWIJD XRJZ";
+
+        let message = Message::parse(traffic, false).unwrap();
+
+        assert_eq!(vec!["U6Z DE C 1510", "49", "EHZ TBS"], message.header);
+        assert_eq!(None, message.kenngruppe);
+        assert_eq!("TVEXSQBLTWLDAHHYEOEFPTWYBLENDPMKOXLDFAMUDWIJDXRJZ", message.get_text());
+
+        let message = Message::parse(traffic, true).unwrap();
+
+        assert_eq!(Some("TVEXS".to_string()), message.kenngruppe);
+        assert_eq!("QBLTWLDAHHYEOEFPTWYBLENDPMKOXLDFAMUDWIJDXRJZ", message.get_text());
+
+        // Formatted output parses back to the same text.
+        let format = GroupFormat::new(4, 3, true).unwrap();
+        assert_eq!("QBLTWLDAHHYEOEFPTWYB", Message::parse(&format.format("QBLTWLDAHHYEOEFPTWYB"), false).unwrap().get_text());
+
+        assert!(Message::parse("1510 = 49 = EHZ TBS =\nTVEXS QB1TW", false).is_err());
+    }
+
+    #[test]
+    fn test_message_display() {
+        let traffic = "U6Z DE C 1510 = 49 = EHZ TBS =\n\
+                       001 TVEXS QBLTW LDAHH YEOEF\n\
+                       002 PTWYB LENDP MKOXL DFAMU\n\
+                       003 DWIJD XRJZ";
+
+        // Sent again as it was received.
+        let mut message = Message::parse(traffic, true).unwrap();
+
+        assert_eq!(GroupFormat::new(5, 4, true).unwrap(), message.format);
+        assert_eq!(traffic.lines().map(|l| l.trim()).collect::<Vec<&str>>().join("\n"), message.to_string());
+
+        // The groups stay as they are, only the lines change.
+        message.format = GroupFormat::new(5, 6, false).unwrap();
+
+        assert_eq!("U6Z DE C 1510 = 49 = EHZ TBS =\n\
+                    TVEXS QBLTW LDAHH YEOEF PTWYB LENDP\n\
+                    MKOXL DFAMU DWIJD XRJZ", message.to_string());
+
+        let message = Message::parse("XSDK RXFZ ABCD EFGH IJ XSDK RXFZ", false).unwrap();
+
+        assert_eq!(GroupFormat::new(4, 7, false).unwrap(), message.format);
+        assert_eq!("001 XSDK RXFZ ABCD\n002 EFGH IJ XSDK\n003 RXFZ", GroupFormat::new(4, 3, true).unwrap().format_groups(&message.groups));
+    }
+}
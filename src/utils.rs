@@ -1,5 +1,6 @@
 use crate::error::{Error};
 use anyhow::{Context, Result};
+use rand::Rng;
 
 pub fn get_position_from_char(a: char) -> Result<usize> {
     if !a.is_ascii_uppercase() {
@@ -21,9 +22,60 @@ pub fn get_char_from_position(position: usize) -> Result<char> {
     }
 }
 
+// Convert a wiring string such as "EKMFLGDQVZNTOWYHXUSPAIBRCJ" into contact positions, checking that every letter is used
+// exactly once so the wiring is a permutation of the alphabet.
+pub fn get_wiring_from_string(wiring: &str) -> Result<[usize; 26]> {
+    if wiring.chars().count() != 26 {
+        return Err(Error::InputError).with_context(|| { format!("Invalid wiring {}. Expected 26 letters, got {}.", wiring, wiring.chars().count()) });
+    }
+
+    let mut positions = [0; 26];
+    let mut used = [false; 26];
+
+    for (i, c) in wiring.chars().enumerate() {
+        let p = get_position_from_char(c)?;
+
+        if used[p] {
+            return Err(Error::InputError).with_context(|| { format!("Invalid wiring {}. Letter {} is used more than once.", wiring, c) });
+        }
+
+        used[p] = true;
+        positions[i] = p;
+    }
+
+    Ok(positions)
+}
+
+// Check that contact positions are a permutation of the alphabet, as the wiring of a wheel read back from a file must be.
+#[cfg(feature = "serde")]
+pub(crate) fn check_wiring(wiring: &[usize; 26]) -> Result<()> {
+    let mut used = [false; 26];
+
+    for p in wiring {
+        if *p > 25 || used[*p] {
+            return Err(Error::InputError).with_context(|| { format!("Invalid wiring {:?}. Every contact must be used exactly once.", wiring) });
+        }
+
+        used[*p] = true;
+    }
+
+    Ok(())
+}
+
+// Letters picked at random, as an operator would for a message key.
+pub fn get_random_letters(count: usize) -> String {
+    get_random_letters_from(&mut rand::thread_rng(), count)
+}
+
+// Letters picked with the given random number generator, so that a seeded generator always gives the same letters.
+pub fn get_random_letters_from<R: Rng>(rng: &mut R, count: usize) -> String {
+    (0..count).map(|_| (b'A' + rng.gen_range(0..26)) as char).collect()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
 
     #[test]
     fn test_get_position_from_char() {
@@ -99,4 +151,35 @@ mod test {
     fn test_get_char_from_position_out_of_range() {
         assert!(get_char_from_position(26).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_get_wiring_from_string() {
+        let wiring = get_wiring_from_string("EKMFLGDQVZNTOWYHXUSPAIBRCJ").unwrap();
+        assert_eq!([4, 10, 12, 5, 11, 6, 3, 16, 21, 25, 13, 19, 14, 22, 24, 7, 23, 20, 18, 15, 0, 8, 1, 17, 2, 9], wiring);
+    }
+
+    #[test]
+    fn test_get_wiring_from_string_invalid() {
+        // Too short.
+        assert!(get_wiring_from_string("EKMFLGDQVZNTOWYHXUSPAIBRC").is_err());
+        // Not a permutation.
+        assert!(get_wiring_from_string("EKMFLGDQVZNTOWYHXUSPAIBRCE").is_err());
+        // Not uppercase.
+        assert!(get_wiring_from_string("ekmflgdqvzntowyhxuspaibrcj").is_err());
+    }
+
+    #[test]
+    fn test_get_random_letters() {
+        let letters = get_random_letters(200);
+
+        assert_eq!(200, letters.len());
+        assert!(letters.chars().all(|c| c.is_ascii_uppercase()));
+        // With 200 letters to pick from, every time getting the same one would be a broken generator.
+        assert!(letters.chars().any(|c| c != letters.chars().next().unwrap()));
+        assert_ne!(get_random_letters(20), get_random_letters(20));
+
+        // The same seed gives the same letters.
+        let first = get_random_letters_from(&mut StdRng::seed_from_u64(1940), 20);
+        assert_eq!(first, get_random_letters_from(&mut StdRng::seed_from_u64(1940), 20));
+    }
+}